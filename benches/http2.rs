@@ -0,0 +1,70 @@
+//! Fetches the metadata of a few nuget.org packages from several threads at
+//! once, over one shared client with and without HTTP/2, and with a new
+//! client per request like before clients were shared. Needs network access:
+//! `cargo bench --bench http2`.
+
+use std::time::{Duration, Instant};
+
+use nuget_dl::NugetClient;
+
+const PACKAGES: &[(&str, &str)] = &[
+    ("Newtonsoft.Json", "13.0.3"),
+    ("Microsoft.Windows.CppWinRT", "2.0.220608.4"),
+    ("WinPixEventRuntime", "1.0.220124001"),
+    ("Microsoft.AI.DirectML", "1.9.0"),
+    ("directxtk12_desktop_2019", "2022.7.30.1"),
+    ("System.Text.Json", "8.0.0"),
+    ("Microsoft.Extensions.Logging", "8.0.0"),
+    ("Serilog", "3.1.1"),
+];
+const THREADS: usize = 8;
+const ROUNDS: usize = 5;
+
+type Fetch<'a> =
+    &'a (dyn Fn(&str, &str) -> Result<(), Box<dyn std::error::Error + Send + Sync>> + Sync);
+
+// Runs `ROUNDS` passes over `PACKAGES` on `THREADS` threads.
+fn run(fetch: Fetch) -> Duration {
+    let start = Instant::now();
+    std::thread::scope(|scope| {
+        for thread in 0..THREADS {
+            scope.spawn(move || {
+                for round in 0..ROUNDS {
+                    let (name, version) = PACKAGES[(thread + round) % PACKAGES.len()];
+                    if let Err(error) = fetch(name, version) {
+                        eprintln!("{name} {version}: {error}");
+                    }
+                }
+            });
+        }
+    });
+    start.elapsed()
+}
+
+fn main() {
+    // Without the metadata cache every request goes over the network
+    let uncached = || NugetClient::new().with_metadata_cache_size(0);
+    let http2 = uncached();
+    let http1 = uncached().with_http2(false);
+    let shared_http2 =
+        |name: &str, version: &str| http2.get_package_metadata(name, version).map(drop);
+    let shared_http1 =
+        |name: &str, version: &str| http1.get_package_metadata(name, version).map(drop);
+    let per_request =
+        |name: &str, version: &str| uncached().get_package_metadata(name, version).map(drop);
+    let requests = THREADS * ROUNDS;
+    // The first passes warm up DNS and the shared clients' connections
+    run(&shared_http2);
+    run(&shared_http1);
+    let timings = [
+        ("shared client, HTTP/2", run(&shared_http2)),
+        ("shared client, HTTP/1.1", run(&shared_http1)),
+        ("client per request", run(&per_request)),
+    ];
+    for (name, elapsed) in timings {
+        println!(
+            "{name}: {requests} requests in {elapsed:?}, {:?} per request",
+            elapsed / requests as u32
+        );
+    }
+}
@@ -0,0 +1,473 @@
+use std::{
+    collections::{HashSet, VecDeque},
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use tokio::io::AsyncWriteExt;
+
+use crate::{
+    cache_path,
+    concurrency::download_rate_limiter,
+    default_temp_file_name, detect_package_format,
+    feed::NUGET_V2_URL,
+    framework::TargetFramework,
+    get_package_file_name, get_package_file_stem,
+    hash::{digest_for, hash_file},
+    long_path,
+    metadata::{dependency_group, parse_odata, PackageMetadata},
+    metadata_cache::LruCache,
+    package_id::check_package_input,
+    remove_stale_temp_files,
+    retry::is_retryable,
+    DownloadProgress, DownloadResult, FileNaming, NugetError, NugetPackageFile, NugetVersion,
+    PackageHash, PackageTimings, RetryPolicy, VersionRange,
+};
+
+// Enough for every package and dependency of a typical config
+const METADATA_CACHE_SIZE: usize = 256;
+
+type ProgressCallback = Box<dyn Fn(&DownloadProgress) + Send + Sync>;
+
+// What config installs run on: the async counterpart of the parts of
+// `NugetClient` an install uses, with its defaults. Only talks to nuget.org's
+// V2 feed, and only verifies packages against SHA512 or SHA256 hashes.
+pub(crate) struct AsyncClient {
+    http: reqwest::Client,
+    feed_url: String,
+    retry_policy: RetryPolicy,
+    deadline: Option<Instant>,
+    progress: Option<ProgressCallback>,
+    metadata_cache: LruCache<PackageMetadata>,
+}
+
+impl AsyncClient {
+    pub fn new() -> Self {
+        Self {
+            http: reqwest::Client::new(),
+            feed_url: NUGET_V2_URL.to_owned(),
+            retry_policy: RetryPolicy::default(),
+            deadline: None,
+            progress: None,
+            metadata_cache: LruCache::new(METADATA_CACHE_SIZE),
+        }
+    }
+
+    /// See `NugetClient::with_deadline`.
+    pub fn with_deadline(mut self, deadline: Instant) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    /// See `NugetClient::with_progress`.
+    pub fn with_progress<F: Fn(&DownloadProgress) + Send + Sync + 'static>(
+        mut self,
+        progress: F,
+    ) -> Self {
+        self.progress = Some(Box::new(progress));
+        self
+    }
+
+    async fn send(
+        &self,
+        method: reqwest::Method,
+        url: &str,
+    ) -> Result<reqwest::Response, Box<dyn std::error::Error + Send + Sync>> {
+        let mut delays = self.retry_policy.delays();
+        loop {
+            let mut request = self.http.request(method.clone(), url);
+            if let Some(remaining) = self.remaining_time()? {
+                request = request.timeout(remaining);
+            }
+            let error = match request.send().await.and_then(|r| r.error_for_status()) {
+                Ok(response) => return Ok(response),
+                Err(error) => error,
+            };
+            self.check_deadline()?;
+            match delays.next() {
+                Some(delay) if is_retryable(&error) => tokio::time::sleep(delay).await,
+                _ => return Err(Box::new(error)),
+            }
+        }
+    }
+
+    async fn get_text(
+        &self,
+        url: &str,
+    ) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+        let response = self.send(reqwest::Method::GET, url).await?;
+        match response.text().await {
+            Ok(text) => Ok(text),
+            Err(error) => {
+                self.check_deadline()?;
+                Err(Box::new(error))
+            }
+        }
+    }
+
+    fn check_deadline(&self) -> Result<(), NugetError> {
+        self.remaining_time().map(|_| ())
+    }
+
+    fn remaining_time(&self) -> Result<Option<Duration>, NugetError> {
+        let Some(deadline) = self.deadline else {
+            return Ok(None);
+        };
+        match deadline.checked_duration_since(Instant::now()) {
+            Some(remaining) if !remaining.is_zero() => Ok(Some(remaining)),
+            _ => Err(NugetError::DeadlineExceeded),
+        }
+    }
+
+    /// See `NugetClient::get_package_metadata`.
+    pub async fn get_package_metadata(
+        &self,
+        package_name: &str,
+        version: &str,
+    ) -> Result<PackageMetadata, Box<dyn std::error::Error + Send + Sync>> {
+        check_package_input(package_name, version)?;
+        if let Some(metadata) = self.metadata_cache.get(package_name, version) {
+            return Ok(metadata);
+        }
+        let url = format!(
+            "{}/Packages(Id='{package_name}',Version='{version}')",
+            self.feed_url
+        );
+        let text = self.get_text(&url).await?;
+        let feed = parse_odata(&text, &[])?;
+        let metadata = PackageMetadata::from_properties(feed.package_entry(package_name, version)?)
+            .ok_or_else(|| format!("No metadata found for {package_name} {version}"))?;
+        self.metadata_cache
+            .insert(package_name, version, metadata.clone());
+        Ok(metadata)
+    }
+
+    /// See `NugetClient::get_package_size`.
+    pub async fn get_package_size(
+        &self,
+        package_name: &str,
+        version: &str,
+    ) -> Result<Option<u64>, Box<dyn std::error::Error + Send + Sync>> {
+        check_package_input(package_name, version)?;
+        let cached = self
+            .metadata_cache
+            .get(package_name, version)
+            .and_then(|metadata| metadata.size);
+        if cached.is_some() {
+            return Ok(cached);
+        }
+        let response = self
+            .send(
+                reqwest::Method::HEAD,
+                &self.package_url(package_name, version),
+            )
+            .await?;
+        Ok(response
+            .headers()
+            .get(reqwest::header::CONTENT_LENGTH)
+            .and_then(|len| len.to_str().ok()?.parse().ok()))
+    }
+
+    fn package_url(&self, package_name: &str, version: &str) -> String {
+        format!("{}/package/{package_name}/{version}", self.feed_url)
+    }
+
+    // The listed versions of the package, following the feed's paging.
+    async fn listed_versions(
+        &self,
+        package_name: &str,
+    ) -> Result<Vec<NugetVersion>, Box<dyn std::error::Error + Send + Sync>> {
+        let mut versions = Vec::new();
+        let mut url = Some(format!(
+            "{}/FindPackagesById()?id='{package_name}'",
+            self.feed_url
+        ));
+        while let Some(next_url) = url {
+            let text = self.get_text(&next_url).await?;
+            let feed = parse_odata(&text, &[])?;
+            for properties in &feed.entries {
+                let Some(metadata) = PackageMetadata::from_properties(properties) else {
+                    continue;
+                };
+                if let (true, Ok(version)) = (metadata.listed, metadata.version.parse()) {
+                    versions.push(version);
+                }
+            }
+            url = feed.next;
+        }
+        Ok(versions)
+    }
+
+    /// See `NugetClient::resolve_version_range`.
+    pub async fn resolve_version_range(
+        &self,
+        package_name: &str,
+        range: &VersionRange,
+    ) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+        let allow_prerelease = range
+            .min()
+            .map(|(min, _)| min.is_prerelease())
+            .unwrap_or(false);
+        let versions = self.listed_versions(package_name).await?;
+        let candidates = versions
+            .into_iter()
+            .filter(|version| allow_prerelease || !version.is_prerelease())
+            .filter(|version| range.satisfies(version));
+        let picked = if range.is_floating() {
+            candidates.max()
+        } else {
+            candidates.min()
+        };
+        let picked = picked.ok_or_else(|| NugetError::NoMatchingVersion {
+            package_name: package_name.to_owned(),
+            requirement: range.to_string(),
+        })?;
+        Ok(picked.to_string())
+    }
+
+    /// The package's transitive dependencies as ids and versions, resolved
+    /// like `NugetClient::resolve_dependencies` does, without the package
+    /// itself.
+    pub async fn resolve_dependencies(
+        &self,
+        package_name: &str,
+        version: &str,
+        target_framework: Option<&str>,
+    ) -> Result<Vec<(String, String)>, Box<dyn std::error::Error + Send + Sync>> {
+        let target_framework = target_framework.map(TargetFramework::parse);
+        let mut resolved = Vec::new();
+        let mut seen = HashSet::from([package_name.to_lowercase()]);
+        let mut queue = VecDeque::from([(package_name.to_owned(), version.to_owned())]);
+        while let Some((id, version)) = queue.pop_front() {
+            let metadata = self.get_package_metadata(&id, &version).await?;
+            let Some(group) = dependency_group(&metadata, target_framework.as_ref()) else {
+                continue;
+            };
+            for dependency in &group.dependencies {
+                if !seen.insert(dependency.id.to_lowercase()) {
+                    continue;
+                }
+                let range: VersionRange = dependency.version_range.parse()?;
+                let version = self.resolve_version_range(&dependency.id, &range).await?;
+                resolved.push((dependency.id.clone(), version.clone()));
+                queue.push_back((dependency.id.clone(), version));
+            }
+        }
+        Ok(resolved)
+    }
+
+    /// Like `NugetClient::download_package_timed` into `download_dir`, or
+    /// `NugetClient::download_package_with_hash` when there's a
+    /// `pinned_hash`. Cached packages are hashed on the runtime's blocking
+    /// thread pool.
+    pub async fn download_package(
+        &self,
+        package_name: &str,
+        version: &str,
+        download_dir: &Path,
+        pinned_hash: Option<&PackageHash>,
+    ) -> Result<DownloadResult, Box<dyn std::error::Error + Send + Sync>> {
+        check_package_input(package_name, version)?;
+        let mut timings = PackageTimings::default();
+        let path = long_path(&cache_path(
+            package_name,
+            version,
+            download_dir,
+            FileNaming::default(),
+        ));
+        let (size, hash) = match pinned_hash {
+            Some(hash) => {
+                let size = self
+                    .metadata_cache
+                    .get(package_name, version)
+                    .and_then(|metadata| metadata.size);
+                (size, hash.clone())
+            }
+            None => {
+                let start = Instant::now();
+                let metadata = self.get_package_metadata(package_name, version).await?;
+                timings.metadata += start.elapsed();
+                let hash = metadata.hash.ok_or_else(|| NugetError::HashUnavailable {
+                    package_name: package_name.to_owned(),
+                    version: version.to_owned(),
+                })?;
+                (metadata.size, hash)
+            }
+        };
+        if digest_for(&hash.algorithm).is_none() {
+            return Err(Box::new(NugetError::UnsupportedHashAlgorithm(
+                hash.algorithm.as_str().to_owned(),
+            )));
+        }
+
+        // Treat failures to read the cached package as a failing match
+        let cached_len = tokio::fs::metadata(&path)
+            .await
+            .ok()
+            .map(|metadata| metadata.len());
+        let matches = match cached_len {
+            Some(len) if size.is_none_or(|size| size == len) => {
+                let start = Instant::now();
+                let matches = file_matches(&path, &hash).await.unwrap_or(false);
+                timings.hashing += start.elapsed();
+                matches
+            }
+            _ => false,
+        };
+        if !matches {
+            // One more try for a download that doesn't match, like the
+            // client's default `with_hash_mismatch_retries`
+            let mut retried = false;
+            loop {
+                match self
+                    .download_to_cache(package_name, version, &path, size, &hash, &mut timings)
+                    .await
+                {
+                    Err(error)
+                        if !retried
+                            && matches!(
+                                error.downcast_ref(),
+                                Some(NugetError::HashMismatch { .. })
+                            ) =>
+                    {
+                        retried = true;
+                        log::warn!("Retrying {package_name} {version}: {error}");
+                    }
+                    result => break result?,
+                }
+            }
+        }
+        let file = tokio::fs::File::open(&path).await?.into_std().await;
+        Ok(DownloadResult {
+            file: NugetPackageFile {
+                stem: get_package_file_stem(package_name, version),
+                file,
+            },
+            downloaded: !matches,
+            path,
+            timings,
+            verified_hash: Some(hash),
+        })
+    }
+
+    // Downloads into a temporary file and moves it into place once it's
+    // verified, so a failed download never leaves a package where a later
+    // run would find it.
+    async fn download_to_cache(
+        &self,
+        package_name: &str,
+        version: &str,
+        path: &Path,
+        size: Option<u64>,
+        hash: &PackageHash,
+        timings: &mut PackageTimings,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let dir = path.parent().unwrap_or(Path::new("")).to_owned();
+        tokio::fs::create_dir_all(&dir).await?;
+        let file_name = get_package_file_name(package_name, version);
+        let temp_path = dir.join(default_temp_file_name(&file_name));
+        tokio::task::spawn_blocking(move || remove_stale_temp_files(&dir, &file_name)).await?;
+        let result = self
+            .download_verified(package_name, version, &temp_path, size, hash, timings)
+            .await;
+        let result = match result {
+            Ok(()) => tokio::fs::rename(&temp_path, path)
+                .await
+                .map_err(Into::into),
+            Err(error) => Err(error),
+        };
+        if result.is_err() {
+            let _ = tokio::fs::remove_file(&temp_path).await;
+        }
+        result
+    }
+
+    async fn download_verified(
+        &self,
+        package_name: &str,
+        version: &str,
+        temp_path: &Path,
+        size: Option<u64>,
+        hash: &PackageHash,
+        timings: &mut PackageTimings,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        self.check_deadline()?;
+        let url = self.package_url(package_name, version);
+        let start = Instant::now();
+        let mut response = self.send(reqwest::Method::GET, &url).await?;
+        timings.request += start.elapsed();
+
+        let start = Instant::now();
+        let total = response.content_length().or(size);
+        let mut file = tokio::fs::File::create(temp_path).await?;
+        let mut digest = digest_for(&hash.algorithm).expect("checked by the caller");
+        // Enough of the start to tell a package from an error page
+        let mut head = Vec::with_capacity(64);
+        let mut downloaded = 0;
+        loop {
+            let chunk = match response.chunk().await {
+                Ok(Some(chunk)) => chunk,
+                Ok(None) => break,
+                Err(error) => {
+                    // The request's timeout covers the body too, report it as the deadline
+                    self.check_deadline()?;
+                    return Err(Box::new(error));
+                }
+            };
+            if let Some(wait) = download_rate_limiter().reserve(chunk.len() as u64) {
+                tokio::time::sleep(wait).await;
+            }
+            file.write_all(&chunk).await?;
+            digest.update(&chunk);
+            let missing = head.capacity() - head.len();
+            head.extend_from_slice(&chunk[..missing.min(chunk.len())]);
+            downloaded += chunk.len() as u64;
+            if let Some(progress) = &self.progress {
+                progress(&DownloadProgress {
+                    package_name,
+                    version,
+                    downloaded,
+                    total,
+                });
+            }
+        }
+        file.flush().await?;
+        timings.download += start.elapsed();
+
+        if let Err(NugetError::InvalidPackage(reason)) = detect_package_format(&head) {
+            return Err(Box::new(NugetError::InvalidPackage(format!(
+                "{package_name} {version}: {reason}"
+            ))));
+        }
+        let actual = base64::encode(digest.finalize());
+        if actual != hash.hash {
+            return Err(Box::new(NugetError::HashMismatch {
+                package_name: package_name.to_owned(),
+                version: version.to_owned(),
+                expected: hash.hash.clone(),
+                actual,
+            }));
+        }
+        Ok(())
+    }
+}
+
+async fn file_matches(
+    path: &Path,
+    hash: &PackageHash,
+) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+    let reference_hash = base64::decode(&hash.hash)?;
+    let path = path.to_owned();
+    let algorithm = hash.algorithm.clone();
+    let file_hash = tokio::task::spawn_blocking(move || hash_file(path, &algorithm)).await??;
+    Ok(file_hash.is_some_and(|file_hash| file_hash == reference_hash))
+}
+
+// Runs an install on a runtime of its own, for the blocking entry points.
+#[cfg(feature = "blocking")]
+pub(crate) fn block_on<F: std::future::Future>(future: F) -> std::io::Result<F::Output> {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?;
+    Ok(runtime.block_on(future))
+}
@@ -0,0 +1,104 @@
+use std::{fs::File, io::Read, path::Path};
+
+use crate::{
+    cache_path, get_package_file_name, hash::hash_file, long_path, manifest::parse_manifest_text,
+    FileNaming, HashAlgorithm, ManifestEntry, ManifestStatus, ManifestVerification, NugetError,
+};
+#[cfg(feature = "blocking")]
+use crate::{hash::hash_reader, install_config, manifest::format_manifest, InstallMode};
+
+// Bundles are zips holding a text manifest and the packages under `packages/`
+const MANIFEST_ENTRY: &str = "manifest.txt";
+const PACKAGES_DIR: &str = "packages";
+
+/// Downloads everything the config needs and writes it into a single zip
+/// along with a manifest of their SHA512 hashes, for moving a cache onto a
+/// machine without network access. See `import_bundle`.
+#[cfg(feature = "blocking")]
+pub fn export_bundle<P: AsRef<Path>, A: AsRef<Path>>(
+    config_path: P,
+    output_archive: A,
+) -> Result<Vec<ManifestEntry>, Box<dyn std::error::Error + Send + Sync>> {
+    use std::io::{Seek, Write};
+
+    let report = install_config(config_path, InstallMode::Strict)?;
+
+    let mut archive = zip::ZipWriter::new(File::create(output_archive)?);
+    // Packages are zips already, compressing them again gains nothing
+    let options =
+        zip::write::FileOptions::default().compression_method(zip::CompressionMethod::Stored);
+    let mut entries = Vec::with_capacity(report.packages.len());
+    for package in report.packages {
+        let mut file = package.outcome?.file.file;
+        let hash = hash_reader(&mut file, &HashAlgorithm::SHA512)?
+            .expect("SHA512 hashes can always be computed");
+        file.rewind()?;
+
+        let file_name = get_package_file_name(&package.name, &package.version);
+        archive.start_file(format!("{PACKAGES_DIR}/{file_name}"), options)?;
+        std::io::copy(&mut file, &mut archive)?;
+        entries.push(ManifestEntry {
+            id: package.name,
+            version: package.version,
+            algorithm: "SHA512".to_owned(),
+            hash: base64::encode(hash),
+        });
+    }
+    archive.start_file(MANIFEST_ENTRY, options)?;
+    archive.write_all(format_manifest(&entries).as_bytes())?;
+    archive.finish()?;
+    Ok(entries)
+}
+
+/// Unpacks a bundle written by `export_bundle` into `dir` and verifies every
+/// package against the bundle's manifest. A package that fails verification
+/// is removed again and reported as `NugetError::HashMismatch`.
+pub fn import_bundle<A: AsRef<Path>, P: AsRef<Path>>(
+    archive: A,
+    dir: P,
+) -> Result<Vec<ManifestVerification>, Box<dyn std::error::Error + Send + Sync>> {
+    let dir = dir.as_ref();
+    let mut archive = zip::ZipArchive::new(File::open(archive)?)?;
+
+    let mut manifest = String::new();
+    archive
+        .by_name(MANIFEST_ENTRY)
+        .map_err(|_| format!("The bundle has no {MANIFEST_ENTRY}"))?
+        .read_to_string(&mut manifest)?;
+    let entries = parse_manifest_text(&manifest, MANIFEST_ENTRY)?;
+
+    std::fs::create_dir_all(dir)?;
+    let mut results = Vec::with_capacity(entries.len());
+    for entry in entries {
+        let file_name = get_package_file_name(&entry.id, &entry.version);
+        let path = long_path(&cache_path(
+            &entry.id,
+            &entry.version,
+            dir,
+            FileNaming::Flat,
+        ));
+        {
+            let mut package = archive
+                .by_name(&format!("{PACKAGES_DIR}/{file_name}"))
+                .map_err(|_| format!("The bundle is missing {file_name}"))?;
+            std::io::copy(&mut package, &mut File::create(&path)?)?;
+        }
+
+        let algorithm = HashAlgorithm::from_string(entry.algorithm.clone());
+        let status = match hash_file(&path, &algorithm)? {
+            Some(actual) if actual == base64::decode(&entry.hash)? => ManifestStatus::Verified,
+            Some(actual) => {
+                let _ = std::fs::remove_file(&path);
+                return Err(Box::new(NugetError::HashMismatch {
+                    package_name: entry.id,
+                    version: entry.version,
+                    expected: entry.hash,
+                    actual: base64::encode(actual),
+                }));
+            }
+            None => ManifestStatus::UnsupportedAlgorithm,
+        };
+        results.push(ManifestVerification { entry, status });
+    }
+    Ok(results)
+}
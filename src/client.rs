@@ -0,0 +1,3388 @@
+use std::{
+    collections::{hash_map::Entry, HashMap, HashSet, VecDeque},
+    fs::File,
+    io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+
+use crate::{
+    concurrency::{
+        acquire_download_permit, download_rate_limiter, max_concurrent_downloads, HostLimiter,
+        HostPermit, RateLimiter,
+    },
+    config::resolve_requirement,
+    default_temp_file_name, detect_package_format,
+    extract::{extract_package_files, has_signature_entry, EntryFilter, ExtractOptions},
+    feed::{self, FeedSource, NUGET_V2_URL},
+    framework::TargetFramework,
+    get_package_file_name, get_package_file_stem,
+    hash::{digest_for, hash_buffer_size, hash_file, hash_reader, DynDigest},
+    long_path,
+    metadata::{dependency_group, parse_nuspec, parse_odata, PackageMetadata, PackageTimestamps},
+    metadata_cache::LruCache,
+    package_id::check_package_input,
+    remove_stale_temp_files,
+    retry::is_retryable,
+    stats::{ClientStats, StatsCounters},
+    v3::{
+        CatalogEntry, CatalogLeaf, RegistrationIndex, RegistrationLeafDocument, RegistrationPage,
+        SearchResponse, SearchResult, ServiceIndex, VulnerabilitySeverity,
+    },
+    version::{NugetVersion, VersionRange},
+    DownloadProgress, DownloadResult, FileNaming, HashAlgorithm, NugetError, NugetPackageFile,
+    PackageFile, PackageHash, PackageId, PackageSource, PackageTimings, RetryPolicy,
+    SourceCredentials,
+};
+
+const NUGET_V3_INDEX_URL: &str = "https://api.nuget.org/v3/index.json";
+// Keeps batched $filter URLs comfortably below common URL length limits
+const METADATA_BATCH_SIZE: usize = 20;
+
+/// Rewrites every URL the client is about to request, e.g. to route requests
+/// through a caching proxy.
+pub trait UrlRewriter: Send + Sync {
+    fn rewrite(&self, url: &str) -> String;
+}
+
+impl<F: Fn(&str) -> String + Send + Sync> UrlRewriter for F {
+    fn rewrite(&self, url: &str) -> String {
+        self(url)
+    }
+}
+
+/// Names the temporary file a package is downloaded into, given the
+/// package's file name, see `NugetClient::with_temp_file_naming`.
+pub trait TempFileNaming: Send + Sync {
+    fn temp_file_name(&self, package_file_name: &str) -> String;
+}
+
+impl<F: Fn(&str) -> String + Send + Sync> TempFileNaming for F {
+    fn temp_file_name(&self, package_file_name: &str) -> String {
+        self(package_file_name)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ResolvedPackage {
+    pub id: String,
+    pub version: String,
+    /// The ids leading from the root package to this one, inclusive.
+    pub path: Vec<String>,
+}
+
+/// One package in the tree `audit_closure` returns.
+#[derive(Debug)]
+pub struct AuditNode {
+    pub id: String,
+    /// `None` when no version of the dependency matches its range.
+    pub version: Option<String>,
+    /// The ids leading from the root package to this one, inclusive.
+    pub path: Vec<String>,
+    pub status: AuditStatus,
+    pub dependencies: Vec<AuditNode>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AuditStatus {
+    /// The package downloaded and matched the feed's hash.
+    Verified,
+    /// The feed doesn't have the package, or no version matching the range.
+    Missing(String),
+    HashMismatch {
+        expected: String,
+        actual: String,
+    },
+    Failed(String),
+}
+
+impl AuditNode {
+    /// Every node in the tree that didn't verify, root first.
+    pub fn problems(&self) -> Vec<&AuditNode> {
+        let mut problems = Vec::new();
+        let mut stack = vec![self];
+        while let Some(node) = stack.pop() {
+            if node.status != AuditStatus::Verified {
+                problems.push(node);
+            }
+            stack.extend(node.dependencies.iter().rev());
+        }
+        problems
+    }
+
+    pub fn is_intact(&self) -> bool {
+        self.problems().is_empty()
+    }
+}
+
+type ProgressCallback = Box<dyn Fn(&DownloadProgress) + Send + Sync>;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum UpdateStatus {
+    /// The cached package already matched the feed.
+    Unchanged,
+    /// A cached copy under another version had the same bytes and was moved
+    /// into place, e.g. `1.0` cached as `1.0.0`.
+    Moved {
+        from_version: String,
+    },
+    Downloaded,
+}
+
+pub struct UpdateResult {
+    pub file: NugetPackageFile,
+    pub version: String,
+    pub status: UpdateStatus,
+}
+
+pub struct LatestDownload {
+    pub package_name: String,
+    /// The resolved version, or `None` if resolution failed.
+    pub version: Option<String>,
+    pub outcome: Result<NugetPackageFile, Box<dyn std::error::Error + Send + Sync>>,
+}
+
+// A version and when it was published, if the feed says.
+pub(crate) type Published<V> = (V, Option<String>);
+
+pub struct NugetClient {
+    http: reqwest::blocking::Client,
+    http_options: HttpOptions,
+    download_source: FeedSource,
+    metadata_source: FeedSource,
+    file_naming: FileNaming,
+    per_feed_cache: bool,
+    retry_policy: RetryPolicy,
+    metadata_retry_policy: Option<RetryPolicy>,
+    download_retry_policy: Option<RetryPolicy>,
+    url_rewriter: Option<Box<dyn UrlRewriter>>,
+    progress: Option<ProgressCallback>,
+    feed_indexes: Mutex<HashMap<String, Arc<ServiceIndex>>>,
+    temp_dir: Option<PathBuf>,
+    temp_file_naming: Option<Box<dyn TempFileNaming>>,
+    prerelease_fallback: bool,
+    download_rate: Option<RateLimiter>,
+    fallback_cache_dir: Option<PathBuf>,
+    deadline: Option<Instant>,
+    hash_sidecars: bool,
+    trust_unchanged_files: bool,
+    header_checksums: bool,
+    durable: bool,
+    require_signature_entry: bool,
+    verify_identity: bool,
+    hash_mismatch_retries: usize,
+    accepted_hash_algorithms: Vec<HashAlgorithm>,
+    hash_cross_check: bool,
+    advisories: bool,
+    fail_on_vulnerability: Option<VulnerabilitySeverity>,
+    stats: Option<StatsCounters>,
+    unsupported_hash_policy: UnsupportedHashPolicy,
+    allow_unverified_on_metadata_failure: bool,
+    odata_aliases: Vec<(String, String)>,
+    host_limiter: Option<HostLimiter>,
+    metadata_cache: LruCache<PackageMetadata>,
+    max_metadata_size: u64,
+    hash_cache: LruCache<PackageHash>,
+}
+
+// Enough for every package and dependency of a typical config
+const METADATA_CACHE_SIZE: usize = 256;
+// Far more than any real metadata response, search pages included
+const MAX_METADATA_SIZE: u64 = 32 * 1024 * 1024;
+
+// Settings baked into the underlying reqwest client, which is rebuilt whenever
+// one of them changes.
+#[derive(Clone, Debug)]
+struct HttpOptions {
+    http2: bool,
+    max_redirects: usize,
+    headers: HeaderMap,
+    timeout: Option<Duration>,
+    user_agent: Option<String>,
+    proxy: Option<reqwest::Proxy>,
+    max_connections_per_host: Option<usize>,
+}
+
+impl Default for HttpOptions {
+    fn default() -> Self {
+        Self {
+            http2: true,
+            max_redirects: 10,
+            headers: HeaderMap::new(),
+            timeout: None,
+            user_agent: None,
+            proxy: None,
+            max_connections_per_host: None,
+        }
+    }
+}
+
+impl HttpOptions {
+    // Header values that look like credentials are marked sensitive so
+    // they're redacted from debug output.
+    fn insert_headers(&mut self, headers: HashMap<String, String>) -> Result<(), NugetError> {
+        for (name, value) in headers {
+            let invalid = || NugetError::InvalidHeader(name.clone());
+            let header_name = HeaderName::from_bytes(name.as_bytes()).map_err(|_| invalid())?;
+            let mut header_value = HeaderValue::from_str(&value).map_err(|_| invalid())?;
+            header_value.set_sensitive(is_sensitive_header(&header_name));
+            self.headers.insert(header_name, header_value);
+        }
+        Ok(())
+    }
+
+    fn build(&self) -> reqwest::blocking::Client {
+        let mut builder =
+            reqwest::blocking::Client::builder().default_headers(self.headers.clone());
+        if let Some(timeout) = self.timeout {
+            builder = builder.timeout(timeout);
+        }
+        if let Some(user_agent) = &self.user_agent {
+            builder = builder.user_agent(user_agent);
+        }
+        if let Some(proxy) = &self.proxy {
+            builder = builder.proxy(proxy.clone());
+        }
+        if let Some(max) = self.max_connections_per_host {
+            // Only bounds idle connections, requests in flight are bounded
+            // by the client's `HostLimiter`
+            builder = builder.pool_max_idle_per_host(max);
+        }
+        builder = if self.http2 {
+            // HTTP/2 is negotiated through ALPN where the feed supports it,
+            // letting parallel downloads share one connection per host.
+            builder.http2_adaptive_window(true)
+        } else {
+            builder.http1_only()
+        };
+        let max_redirects = self.max_redirects;
+        builder = builder.redirect(reqwest::redirect::Policy::custom(move |attempt| {
+            if attempt.previous().contains(attempt.url()) {
+                let url = attempt.url().to_string();
+                attempt.error(NugetError::RedirectLoop { url })
+            } else if attempt.previous().len() > max_redirects {
+                attempt.error(NugetError::TooManyRedirects { max_redirects })
+            } else {
+                attempt.follow()
+            }
+        }));
+        builder.build().expect("Failed to create the HTTP client")
+    }
+}
+
+/// What to do when the feed reports a hash algorithm this crate can't compute.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum UnsupportedHashPolicy {
+    /// Fail with `NugetError::UnsupportedHashAlgorithm`.
+    #[default]
+    Error,
+    /// Accept the package without verifying it.
+    SkipVerification,
+}
+
+impl Default for NugetClient {
+    fn default() -> Self {
+        Self::from_http_options(HttpOptions::default())
+    }
+}
+
+/// Collects `NugetClient` options and checks them together before building
+/// the client, which only creates the underlying HTTP client once. Options
+/// not covered here can still be set on the built client with its `with_`
+/// methods.
+#[derive(Default)]
+pub struct NugetClientBuilder {
+    http_options: HttpOptions,
+    download_source: Option<FeedSource>,
+    metadata_source: Option<FeedSource>,
+    credentials: Option<SourceCredentials>,
+    headers: HashMap<String, String>,
+    proxy: Option<String>,
+    retry_policy: Option<RetryPolicy>,
+    metadata_retry_policy: Option<RetryPolicy>,
+    download_retry_policy: Option<RetryPolicy>,
+}
+
+impl NugetClientBuilder {
+    /// Downloads from and fetches metadata from the same feed, see
+    /// `FeedSource::parse`.
+    pub fn feed_url<S: Into<String>>(self, url: S) -> Self {
+        let source = FeedSource::from(url.into());
+        self.download_source(source.clone()).metadata_source(source)
+    }
+
+    pub fn download_source(mut self, source: FeedSource) -> Self {
+        self.download_source = Some(source);
+        self
+    }
+
+    pub fn metadata_source(mut self, source: FeedSource) -> Self {
+        self.metadata_source = Some(source);
+        self
+    }
+
+    /// Sent as a Basic `Authorization` header with every request.
+    pub fn credentials<U: Into<String>, P: Into<String>>(
+        mut self,
+        username: U,
+        password: P,
+    ) -> Self {
+        self.credentials = Some(SourceCredentials {
+            username: username.into(),
+            password: password.into(),
+        });
+        self
+    }
+
+    pub fn header<N: Into<String>, V: Into<String>>(mut self, name: N, value: V) -> Self {
+        self.headers.insert(name.into(), value.into());
+        self
+    }
+
+    /// Limits how long each request may take, from connecting until the body
+    /// has been read. There's no timeout by default.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.http_options.timeout = Some(timeout);
+        self
+    }
+
+    pub fn user_agent<S: Into<String>>(mut self, user_agent: S) -> Self {
+        self.http_options.user_agent = Some(user_agent.into());
+        self
+    }
+
+    /// Sends every request through the proxy at `url`.
+    pub fn proxy<S: Into<String>>(mut self, url: S) -> Self {
+        self.proxy = Some(url.into());
+        self
+    }
+
+    pub fn http2(mut self, enabled: bool) -> Self {
+        self.http_options.http2 = enabled;
+        self
+    }
+
+    pub fn max_redirects(mut self, max_redirects: usize) -> Self {
+        self.http_options.max_redirects = max_redirects;
+        self
+    }
+
+    pub fn retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = Some(retry_policy);
+        self
+    }
+
+    /// See `NugetClient::with_metadata_retry_policy`.
+    pub fn metadata_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.metadata_retry_policy = Some(retry_policy);
+        self
+    }
+
+    /// See `NugetClient::with_download_retry_policy`.
+    pub fn download_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.download_retry_policy = Some(retry_policy);
+        self
+    }
+
+    /// See `NugetClient::with_max_connections_per_host`.
+    pub fn max_connections_per_host(mut self, max: usize) -> Self {
+        self.http_options.max_connections_per_host = Some(max);
+        self
+    }
+
+    pub fn build(self) -> Result<NugetClient, Box<dyn std::error::Error + Send + Sync>> {
+        let mut http_options = self.http_options;
+        if let Some(proxy) = &self.proxy {
+            let proxy = reqwest::Proxy::all(proxy.as_str()).map_err(|error| {
+                NugetError::InvalidConfig(format!("invalid proxy {proxy}: {error}"))
+            })?;
+            http_options.proxy = Some(proxy);
+        }
+        let mut headers = self.headers;
+        if let Some(credentials) = &self.credentials {
+            let is_custom_feed = |source: &Option<FeedSource>| matches!(source, Some(FeedSource::Url(url) | FeedSource::V3(url)) if url != NUGET_V2_URL);
+            if !is_custom_feed(&self.download_source) && !is_custom_feed(&self.metadata_source) {
+                log::warn!(
+                    "Credentials for {} are set without a custom feed, they'll be sent to nuget.org",
+                    credentials.username
+                );
+            }
+            headers.extend(basic_auth(credentials));
+        }
+        http_options.insert_headers(headers)?;
+
+        let mut client = NugetClient::from_http_options(http_options);
+        if let Some(source) = self.download_source {
+            client.download_source = source;
+        }
+        if let Some(source) = self.metadata_source {
+            client.metadata_source = source;
+        }
+        if let Some(retry_policy) = self.retry_policy {
+            client.retry_policy = retry_policy;
+        }
+        client.metadata_retry_policy = self.metadata_retry_policy;
+        client.download_retry_policy = self.download_retry_policy;
+        Ok(client)
+    }
+}
+
+impl NugetClient {
+    fn from_http_options(http_options: HttpOptions) -> Self {
+        Self {
+            host_limiter: http_options.max_connections_per_host.map(HostLimiter::new),
+            metadata_cache: LruCache::new(METADATA_CACHE_SIZE),
+            max_metadata_size: MAX_METADATA_SIZE,
+            hash_cache: LruCache::new(METADATA_CACHE_SIZE),
+            http: http_options.build(),
+            http_options,
+            download_source: FeedSource::Url(NUGET_V2_URL.to_owned()),
+            metadata_source: FeedSource::Url(NUGET_V2_URL.to_owned()),
+            file_naming: Default::default(),
+            per_feed_cache: false,
+            retry_policy: Default::default(),
+            metadata_retry_policy: None,
+            download_retry_policy: None,
+            url_rewriter: None,
+            progress: None,
+            feed_indexes: Mutex::new(HashMap::new()),
+            temp_dir: None,
+            temp_file_naming: None,
+            prerelease_fallback: false,
+            download_rate: None,
+            fallback_cache_dir: None,
+            deadline: None,
+            hash_sidecars: false,
+            trust_unchanged_files: false,
+            header_checksums: false,
+            durable: false,
+            require_signature_entry: false,
+            verify_identity: false,
+            hash_mismatch_retries: 1,
+            accepted_hash_algorithms: vec![HashAlgorithm::SHA512, HashAlgorithm::SHA256],
+            hash_cross_check: false,
+            advisories: false,
+            fail_on_vulnerability: None,
+            stats: None,
+            unsupported_hash_policy: Default::default(),
+            allow_unverified_on_metadata_failure: false,
+            odata_aliases: Vec::new(),
+        }
+    }
+}
+
+impl NugetClient {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn builder() -> NugetClientBuilder {
+        NugetClientBuilder::default()
+    }
+
+    /// Whether HTTP/2 may be negotiated with the feed (the default). Turn
+    /// this off for feeds or proxies that misbehave on HTTP/2.
+    pub fn with_http2(mut self, enabled: bool) -> Self {
+        self.http_options.http2 = enabled;
+        self.http = self.http_options.build();
+        self
+    }
+
+    /// How many redirects a request may follow before failing. nuget.org
+    /// redirects package downloads to its CDN, so this shouldn't be zero.
+    pub fn with_max_redirects(mut self, max_redirects: usize) -> Self {
+        self.http_options.max_redirects = max_redirects;
+        self.http = self.http_options.build();
+        self
+    }
+
+    /// How many packages' metadata and hashes the client remembers, so flows
+    /// that look a package up more than once only fetch it once. Defaults to
+    /// 256, zero turns the cache off.
+    pub fn with_metadata_cache_size(mut self, entries: usize) -> Self {
+        self.metadata_cache = LruCache::new(entries);
+        self.hash_cache = LruCache::new(entries);
+        self
+    }
+
+    /// The largest metadata response the client reads, anything bigger fails
+    /// with `NugetError::MetadataTooLarge`. Doesn't apply to package
+    /// downloads. Defaults to 32 MiB.
+    pub fn with_max_metadata_size(mut self, bytes: u64) -> Self {
+        self.max_metadata_size = bytes;
+        self
+    }
+
+    /// Caps how many requests this client has in flight to any one host,
+    /// for feeds that rate-limit connections. Unlimited by default. This is
+    /// on top of the process-wide `set_max_concurrent_downloads` limit: a
+    /// download holds one of those permits while it waits for its host, and
+    /// metadata requests only count towards this limit. Redirects, e.g. to a
+    /// CDN, count towards the host that was requested.
+    pub fn with_max_connections_per_host(mut self, max: usize) -> Self {
+        self.http_options.max_connections_per_host = Some(max);
+        self.http = self.http_options.build();
+        self.host_limiter = Some(HostLimiter::new(max));
+        self
+    }
+
+    /// Extra headers sent with every download and metadata request. Header
+    /// values that look like credentials are marked sensitive so they're
+    /// redacted from debug output.
+    pub fn with_headers(
+        mut self,
+        headers: HashMap<String, String>,
+    ) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        self.http_options.insert_headers(headers)?;
+        self.http = self.http_options.build();
+        Ok(self)
+    }
+
+    /// The feed that package bytes are downloaded from, e.g. a mirror or CDN.
+    /// Defaults to nuget.org. The kind of feed is picked from the URL, see
+    /// `FeedSource::parse`.
+    pub fn with_download_url<S: Into<String>>(self, url: S) -> Self {
+        self.with_download_source(FeedSource::from(url.into()))
+    }
+
+    /// The feed that package metadata, including the hash downloads are
+    /// verified against, is fetched from. Defaults to nuget.org. Keeping this
+    /// on the canonical feed while downloading from a mirror means the mirror
+    /// doesn't need to be trusted.
+    pub fn with_metadata_url<S: Into<String>>(self, url: S) -> Self {
+        self.with_metadata_source(FeedSource::from(url.into()))
+    }
+
+    pub fn with_download_source(mut self, source: FeedSource) -> Self {
+        self.download_source = source;
+        self
+    }
+
+    pub fn with_metadata_source(mut self, source: FeedSource) -> Self {
+        self.metadata_source = source;
+        self
+    }
+
+    /// Downloads packages and reads their metadata from a local directory,
+    /// e.g. a vendored package folder for offline builds.
+    pub fn with_local_feed<P: Into<PathBuf>>(self, dir: P) -> Self {
+        let source = FeedSource::LocalDir(dir.into());
+        self.with_metadata_source(source.clone())
+            .with_download_source(source)
+    }
+
+    /// Downloads from and fetches metadata from a source read from a
+    /// `NuGet.Config`, sending its credentials with every request.
+    pub fn with_package_source(
+        self,
+        source: &PackageSource,
+    ) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let is_remote = source.url.starts_with("http://") || source.url.starts_with("https://");
+        if !is_remote {
+            return Ok(self.with_local_feed(source.url.as_str()));
+        }
+        let feed_source = match source.protocol_version {
+            2 => FeedSource::Url(source.url.trim_end_matches('/').to_owned()),
+            3 => FeedSource::V3(source.url.clone()),
+            protocol_version => {
+                return Err(Box::new(NugetError::InvalidConfig(format!(
+                    "{}: unsupported protocol version {protocol_version}",
+                    source.name
+                ))))
+            }
+        };
+        let mut client = self
+            .with_download_source(feed_source.clone())
+            .with_metadata_source(feed_source);
+        if let Some(credentials) = &source.credentials {
+            client = client.with_headers(basic_auth(credentials))?;
+        }
+        Ok(client)
+    }
+
+    pub fn with_file_naming(mut self, file_naming: FileNaming) -> Self {
+        self.file_naming = file_naming;
+        self
+    }
+
+    pub fn file_naming(&self) -> FileNaming {
+        self.file_naming
+    }
+
+    /// Whether packages are cached in a subdirectory per download feed, e.g.
+    /// `www.nuget.org-1a2b3c4d`, so packages with the same id and version from
+    /// different feeds don't overwrite each other. The name is the feed's host
+    /// and a hash of its full URL or path. Off by default.
+    pub fn with_per_feed_cache(mut self, per_feed_cache: bool) -> Self {
+        self.per_feed_cache = per_feed_cache;
+        self
+    }
+
+    /// Where `download_package` caches the package inside `download_dir`,
+    /// following the configured `FileNaming` and `with_per_feed_cache`.
+    /// Nothing is checked on disk.
+    pub fn cache_path<P: AsRef<Path>>(
+        &self,
+        package_name: &str,
+        version: &str,
+        download_dir: P,
+    ) -> PathBuf {
+        self.feed_cache_dir(download_dir.as_ref())
+            .join(self.file_naming.package_path(package_name, version))
+    }
+
+    // The directory inside `download_dir` this client's packages go in.
+    fn feed_cache_dir(&self, download_dir: &Path) -> PathBuf {
+        if !self.per_feed_cache {
+            return download_dir.to_owned();
+        }
+        let (location, host) = match &self.download_source {
+            FeedSource::Url(url) | FeedSource::V3(url) => {
+                let host = reqwest::Url::parse(url)
+                    .ok()
+                    .and_then(|url| url.host_str().map(str::to_owned));
+                (url.clone(), host.unwrap_or_else(|| "feed".to_owned()))
+            }
+            FeedSource::LocalDir(dir) => (dir.display().to_string(), "local".to_owned()),
+        };
+        let digest = hash_reader(&mut location.as_bytes(), &HashAlgorithm::SHA256)
+            .ok()
+            .flatten()
+            .unwrap_or_default();
+        let tag: String = digest[..4.min(digest.len())]
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect();
+        download_dir.join(format!("{host}-{tag}"))
+    }
+
+    /// The retry policy for every request that doesn't have its own, see
+    /// `with_metadata_retry_policy` and `with_download_retry_policy`.
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    pub fn retry_policy(&self) -> &RetryPolicy {
+        &self.retry_policy
+    }
+
+    /// Retries metadata requests, like hash lookups and version listings,
+    /// with this policy instead of the one set by `with_retry_policy`. Feeds
+    /// often answer those with a 503 under load, and they're cheap to retry.
+    pub fn with_metadata_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.metadata_retry_policy = Some(retry_policy);
+        self
+    }
+
+    pub fn metadata_retry_policy(&self) -> &RetryPolicy {
+        self.metadata_retry_policy
+            .as_ref()
+            .unwrap_or(&self.retry_policy)
+    }
+
+    /// Retries package downloads with this policy instead of the one set by
+    /// `with_retry_policy`. A retry starts the download over, which is
+    /// expensive for large packages.
+    pub fn with_download_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.download_retry_policy = Some(retry_policy);
+        self
+    }
+
+    pub fn download_retry_policy(&self) -> &RetryPolicy {
+        self.download_retry_policy
+            .as_ref()
+            .unwrap_or(&self.retry_policy)
+    }
+
+    /// Where in-progress downloads are written before being moved into the
+    /// download directory. Defaults to the download directory itself. The
+    /// temp directory must be on the same filesystem as the download
+    /// directory, otherwise the final rename fails.
+    pub fn with_temp_dir<P: Into<PathBuf>>(mut self, temp_dir: P) -> Self {
+        self.temp_dir = Some(temp_dir.into());
+        self
+    }
+
+    /// Throttles package downloads to `bytes_per_second`, shared by every
+    /// download through this client, including the concurrent ones of a
+    /// batch or config install. A process-wide cap can be set on top with
+    /// `set_max_download_rate`. Unlimited by default. Local feeds aren't
+    /// throttled.
+    pub fn with_download_rate_limit(mut self, bytes_per_second: u64) -> Self {
+        self.download_rate = Some(RateLimiter::new(bytes_per_second));
+        self
+    }
+
+    /// Whether resolving the latest stable version of a package that has
+    /// only ever shipped prereleases picks the newest prerelease instead of
+    /// failing with `NugetError::NoMatchingVersion`. A warning is logged when
+    /// it does. Packages with any listed stable version are unaffected. Off
+    /// by default. Applies everywhere the latest version is resolved, see
+    /// `resolve_latest_version`.
+    pub fn with_prerelease_fallback(mut self, prerelease_fallback: bool) -> Self {
+        self.prerelease_fallback = prerelease_fallback;
+        self
+    }
+
+    /// Overrides how temporary download files are named. By default a
+    /// package downloads into `{id}.{version}.nupkg.download-{pid}` next to
+    /// where it goes, or in `with_temp_dir`. The process id keeps concurrent
+    /// processes from writing into each other's files. A download that
+    /// breaks off is resumed within the same process and file, other
+    /// processes never pick up a prior partial file. Default-named files left
+    /// behind by other processes are removed once they've gone unmodified for
+    /// an hour, the next time the same package downloads. Files named by
+    /// `naming` aren't cleaned up, and it must pick names that don't collide
+    /// between processes sharing the directory.
+    pub fn with_temp_file_naming<N: TempFileNaming + 'static>(mut self, naming: N) -> Self {
+        self.temp_file_naming = Some(Box::new(naming));
+        self
+    }
+
+    /// Where packages are downloaded when the download directory isn't
+    /// writable, e.g. a shared cache mounted read-only. Packages already in
+    /// the download directory are still used. Without a fallback, downloading
+    /// into an unwritable directory fails with `NugetError::CacheNotWritable`.
+    pub fn with_fallback_cache_dir<P: Into<PathBuf>>(mut self, dir: P) -> Self {
+        self.fallback_cache_dir = Some(dir.into());
+        self
+    }
+
+    /// A point in time after which every request fails with
+    /// `NugetError::DeadlineExceeded`, including ones already in flight, so
+    /// a feed that stops responding can't hang a whole install. Interrupted
+    /// downloads don't leave partial files behind.
+    pub fn with_deadline(mut self, deadline: Instant) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    /// Whether every download gets a `.sha512` file next to it holding the
+    /// package's base64 SHA512, like NuGet's global packages folder. Cached
+    /// packages with a sidecar are verified against it without contacting the
+    /// feed, a package that doesn't match is checked against the feed as
+    /// usual. Off by default.
+    pub fn with_hash_sidecars(mut self, hash_sidecars: bool) -> Self {
+        self.hash_sidecars = hash_sidecars;
+        self
+    }
+
+    /// Whether a cached package that was verified before is trusted without
+    /// hashing it again, as long as its size and modification time haven't
+    /// changed. They're recorded in a `.verified` file next to the package,
+    /// along with the hash it matched, the first time it's fully hashed. This
+    /// trades some integrity for speed with very large packages: a file whose
+    /// bytes change without its size or modification time changing, e.g.
+    /// through disk corruption or tampering that restores the time, goes
+    /// unnoticed. Off by default, every check hashes the whole file.
+    pub fn with_trust_unchanged_files(mut self, trust: bool) -> Self {
+        self.trust_unchanged_files = trust;
+        self
+    }
+
+    /// Whether packages that aren't cached are verified against a checksum
+    /// the package response carries, saving the metadata request. The
+    /// recognized headers are `x-ms-meta-sha512` and `x-ms-meta-sha256`, the
+    /// blob metadata Azure storage serves, holding base64 or hex. `Content-MD5`
+    /// isn't used, MD5 isn't a supported algorithm. Without a usable header the
+    /// feed's hash is fetched after the download. Not used when failing on
+    /// vulnerabilities, which needs the metadata anyway, or when packages are
+    /// downloaded from somewhere other than the metadata feed, which would
+    /// vouch for its own bytes. Off by default.
+    pub fn with_header_checksums(mut self, header_checksums: bool) -> Self {
+        self.header_checksums = header_checksums;
+        self
+    }
+
+    /// Whether downloads are synced to disk before they're moved into place,
+    /// so a cache snapshotted right after a build never holds a package that
+    /// only existed in the OS page cache. Off by default since it's slower.
+    pub fn with_durable_writes(mut self, durable: bool) -> Self {
+        self.durable = durable;
+        self
+    }
+
+    /// Rejects packages without a `.signature.p7s` entry with
+    /// `NugetError::UnsignedPackage`, whether freshly downloaded or already
+    /// cached. This is a presence check only: the signature isn't parsed or
+    /// validated against a certificate chain, so any file with that name
+    /// passes. Verify signatures with `nuget verify` where that matters.
+    pub fn with_require_signature_entry(mut self, require_signature_entry: bool) -> Self {
+        self.require_signature_entry = require_signature_entry;
+        self
+    }
+
+    /// Checks that the id and version in the package's `.nuspec` are the
+    /// ones requested, failing with `NugetError::IdentityMismatch` otherwise.
+    /// Unlike the hash check this doesn't trust the feed, so it catches a
+    /// feed serving the wrong package under a URL. Off by default.
+    pub fn with_verify_identity(mut self, verify_identity: bool) -> Self {
+        self.verify_identity = verify_identity;
+        self
+    }
+
+    /// Starts counting downloaded bytes, cache hits and misses and requests,
+    /// see `stats`. Counting is off by default.
+    pub fn with_stats(mut self) -> Self {
+        self.stats = Some(StatsCounters::default());
+        self
+    }
+
+    /// The traffic counted since `with_stats`, or `None` if it wasn't called.
+    pub fn stats(&self) -> Option<ClientStats> {
+        self.stats.as_ref().map(StatsCounters::snapshot)
+    }
+
+    /// How many times a download that doesn't match the feed's hash is
+    /// retried before failing with `NugetError::HashMismatch`. Defaults to one
+    /// retry, which rules out a corrupted transfer, a persistent mismatch
+    /// points to a tampered or corrupted package.
+    pub fn with_hash_mismatch_retries(mut self, retries: usize) -> Self {
+        self.hash_mismatch_retries = retries;
+        self
+    }
+
+    /// The hash algorithms packages may be verified with, most preferred
+    /// first. Defaults to SHA512, then SHA256.
+    ///
+    /// V2 feeds report one hash per package, which is used if its algorithm
+    /// is accepted. Otherwise local feeds hash the package themselves with
+    /// the first accepted algorithm, and V3 feeds and nuget.org's V2 feed
+    /// offer the SHA512 in their flat container's `.sha512` file, if SHA512
+    /// is accepted. Other feeds have no other hash to offer, so the
+    /// unsupported hash policy decides whether to fail or skip verification.
+    pub fn with_accepted_hash_algorithms(mut self, algorithms: Vec<HashAlgorithm>) -> Self {
+        self.accepted_hash_algorithms = algorithms;
+        self
+    }
+
+    /// Compares the SHA512 hash the feed's metadata reports with the
+    /// `.nupkg.sha512` file next to the package in a V3 flat container,
+    /// failing with `NugetError::InconsistentHash` if they disagree. Only V3
+    /// feeds and nuget.org have a flat container to compare with, and the
+    /// check is skipped when the file can't be fetched.
+    pub fn with_hash_cross_check(mut self) -> Self {
+        self.hash_cross_check = true;
+        self
+    }
+
+    /// Looks up deprecations and known vulnerabilities in nuget.org's v3
+    /// registration data whenever package metadata is fetched from a remote
+    /// feed. They're logged as warnings and returned in the metadata.
+    pub fn with_advisories(mut self) -> Self {
+        self.advisories = true;
+        self
+    }
+
+    /// Fails downloads of packages with a known vulnerability of at least
+    /// `severity` with `NugetError::VulnerablePackage`. Implies
+    /// `with_advisories`.
+    pub fn with_fail_on_vulnerability(mut self, severity: VulnerabilitySeverity) -> Self {
+        self.advisories = true;
+        self.fail_on_vulnerability = Some(severity);
+        self
+    }
+
+    pub fn with_unsupported_hash_policy(mut self, policy: UnsupportedHashPolicy) -> Self {
+        self.unsupported_hash_policy = policy;
+        self
+    }
+
+    /// Whether packages are downloaded without verification, with a warning,
+    /// when their metadata or hash can't be fetched, e.g. during an outage of
+    /// the feed's metadata endpoint. Cached packages are then used as they
+    /// are. Off by default, so integrity is never lost silently.
+    pub fn with_allow_unverified_on_metadata_failure(mut self, allow: bool) -> Self {
+        self.allow_unverified_on_metadata_failure = allow;
+        self
+    }
+
+    /// Reads the V2 feed's `alias` property as `property`, e.g.
+    /// `("Sha512", "PackageHash")`, for feeds that name properties
+    /// differently from nuget.org. Names are matched ignoring case and
+    /// namespace prefixes. Common synonyms like `Hash` are already known.
+    pub fn with_odata_property_alias<A: Into<String>, P: Into<String>>(
+        mut self,
+        alias: A,
+        property: P,
+    ) -> Self {
+        self.odata_aliases.push((alias.into(), property.into()));
+        self
+    }
+
+    /// Calls `progress` as package bodies are received, from whichever
+    /// thread is downloading. Packages found in the cache aren't reported.
+    pub fn with_progress<F: Fn(&DownloadProgress) + Send + Sync + 'static>(
+        mut self,
+        progress: F,
+    ) -> Self {
+        self.progress = Some(Box::new(progress));
+        self
+    }
+
+    pub fn with_url_rewriter<R: UrlRewriter + 'static>(mut self, url_rewriter: R) -> Self {
+        self.url_rewriter = Some(Box::new(url_rewriter));
+        self
+    }
+
+    // The response holds on to its host permit until it's read.
+    fn get(&self, url: &str) -> Result<FeedResponse<'_>, Box<dyn std::error::Error + Send + Sync>> {
+        self.send(
+            reqwest::Method::GET,
+            url,
+            self.metadata_retry_policy(),
+            None,
+        )
+    }
+
+    // Requests package bytes from `range_start` on, retried with the
+    // download policy.
+    fn get_package(
+        &self,
+        url: &str,
+        range_start: Option<u64>,
+    ) -> Result<FeedResponse<'_>, Box<dyn std::error::Error + Send + Sync>> {
+        self.send(
+            reqwest::Method::GET,
+            url,
+            self.download_retry_policy(),
+            range_start,
+        )
+    }
+
+    fn send(
+        &self,
+        method: reqwest::Method,
+        url: &str,
+        retry_policy: &RetryPolicy,
+        range_start: Option<u64>,
+    ) -> Result<FeedResponse<'_>, Box<dyn std::error::Error + Send + Sync>> {
+        let rewritten;
+        let url = match &self.url_rewriter {
+            Some(url_rewriter) => {
+                rewritten = url_rewriter.rewrite(url);
+                rewritten.as_str()
+            }
+            None => url,
+        };
+        let permit = self.host_limiter.as_ref().map(|host_limiter| {
+            let host = reqwest::Url::parse(url)
+                .ok()
+                .and_then(|url| url.host_str().map(str::to_owned))
+                .unwrap_or_default();
+            host_limiter.acquire(&host)
+        });
+        let mut delays = retry_policy.delays();
+        loop {
+            if let Some(stats) = &self.stats {
+                stats.add_request();
+            }
+            let mut request = self.http.request(method.clone(), url);
+            if let Some(range_start) = range_start {
+                request = request.header(reqwest::header::RANGE, format!("bytes={range_start}-"));
+            }
+            if let Some(remaining) = self.remaining_time()? {
+                request = request.timeout(remaining);
+            }
+            let error = match request.send().and_then(|r| r.error_for_status()) {
+                Ok(response) => {
+                    // Surface where redirects ended up, restricted networks
+                    // may need to allowlist the CDN host
+                    if response.url().as_str() != url {
+                        log::debug!("{url} redirected to {}", response.url());
+                    }
+                    return Ok(FeedResponse {
+                        response,
+                        max_size: self.max_metadata_size,
+                        _permit: permit,
+                    });
+                }
+                Err(error) => error,
+            };
+            self.check_deadline()?;
+            match delays.next() {
+                Some(delay) if is_retryable(&error) => std::thread::sleep(delay),
+                _ => return Err(Box::new(error)),
+            }
+        }
+    }
+
+    fn check_deadline(&self) -> Result<(), NugetError> {
+        self.remaining_time().map(|_| ())
+    }
+
+    // How long until the deadline, if there is one.
+    fn remaining_time(&self) -> Result<Option<Duration>, NugetError> {
+        let Some(deadline) = self.deadline else {
+            return Ok(None);
+        };
+        match deadline.checked_duration_since(Instant::now()) {
+            Some(remaining) if !remaining.is_zero() => Ok(Some(remaining)),
+            _ => Err(NugetError::DeadlineExceeded),
+        }
+    }
+
+    // Streams the package bytes into the writer, returning how many bytes were written.
+    fn download_package_stream(
+        &self,
+        package_name: &str,
+        version: &str,
+        writer: &mut dyn Write,
+        timings: &mut PackageTimings,
+    ) -> Result<u64, Box<dyn std::error::Error + Send + Sync>> {
+        self.download_package_stream_with_checksum(package_name, version, writer, timings, None)
+    }
+
+    /// The package's size in bytes, for progress totals: from metadata the
+    /// client already fetched, or else the `Content-Length` of a HEAD request
+    /// to the download URL. `None` when the feed doesn't say.
+    pub fn get_package_size(
+        &self,
+        package_name: &str,
+        version: &str,
+    ) -> Result<Option<u64>, Box<dyn std::error::Error + Send + Sync>> {
+        check_package_input(package_name, version)?;
+        let cached = self
+            .metadata_cache
+            .get(package_name, version)
+            .and_then(|metadata| metadata.size);
+        if cached.is_some() {
+            return Ok(cached);
+        }
+        let url = match &self.download_source {
+            FeedSource::LocalDir(dir) => {
+                let file = feed::open_package(dir, package_name, version)?;
+                return Ok(Some(file.metadata()?.len()));
+            }
+            _ => self.package_url(package_name, version)?,
+        };
+        let response = self.send(
+            reqwest::Method::HEAD,
+            &url,
+            self.metadata_retry_policy(),
+            None,
+        )?;
+        // HEAD responses have no body, so the header has to be read directly
+        Ok(response
+            .headers()
+            .get(reqwest::header::CONTENT_LENGTH)
+            .and_then(|len| len.to_str().ok()?.parse().ok()))
+    }
+
+    // Where the download source serves the package. Not for local feeds.
+    fn package_url(
+        &self,
+        package_name: &str,
+        version: &str,
+    ) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+        match &self.download_source {
+            FeedSource::Url(base_url) => Ok(format!("{base_url}/package/{package_name}/{version}")),
+            FeedSource::V3(index_url) => {
+                let base_url = self.v3_resource(index_url, "PackageBaseAddress")?;
+                Ok(flat_container_url(&base_url, package_name, version))
+            }
+            FeedSource::LocalDir(dir) => {
+                Err(format!("{} is a local feed, it has no URLs", dir.display()).into())
+            }
+        }
+    }
+
+    // `response_hash` is set to the checksum in the response headers, if
+    // there is one, before any of the body is written.
+    fn download_package_stream_with_checksum(
+        &self,
+        package_name: &str,
+        version: &str,
+        writer: &mut dyn Write,
+        timings: &mut PackageTimings,
+        response_hash: Option<&mut Option<PackageHash>>,
+    ) -> Result<u64, Box<dyn std::error::Error + Send + Sync>> {
+        check_package_input(package_name, version)?;
+        self.check_deadline()?;
+        let url = match &self.download_source {
+            FeedSource::Url(_) | FeedSource::V3(_) => self.package_url(package_name, version)?,
+            FeedSource::LocalDir(dir) => {
+                let start = Instant::now();
+                let mut file = feed::open_package(dir, package_name, version)?;
+                let total = file.metadata().ok().map(|metadata| metadata.len());
+                let mut writer = self.progress_writer(package_name, version, total, writer);
+                let len = std::io::copy(&mut file, &mut writer)?;
+                self.record_bytes_downloaded(len);
+                timings.download += start.elapsed();
+                return Ok(len);
+            }
+        };
+        let _permit = acquire_download_permit();
+        let start = Instant::now();
+        let mut response = self.get_package(&url, None)?;
+        timings.request += start.elapsed();
+        if let Some(response_hash) = response_hash {
+            *response_hash = header_checksum(response.headers());
+        }
+        let start = Instant::now();
+        let total = response.content_length().or_else(|| {
+            self.metadata_cache
+                .get(package_name, version)
+                .and_then(|metadata| metadata.size)
+        });
+        let mut writer = ThrottledWriter {
+            writer,
+            limiters: [self.download_rate.as_ref(), Some(download_rate_limiter())],
+        };
+        let mut writer = self.progress_writer(package_name, version, total, &mut writer);
+        // The bytes go to the writer exactly as received. Never unpack or
+        // rewrite the package on the way, the feed's hash is over these bytes
+        // and re-zipping the same entries rarely gives the same ones back.
+        let resumable = response
+            .headers()
+            .get(reqwest::header::ACCEPT_RANGES)
+            .is_some_and(|value| value.as_bytes().eq_ignore_ascii_case(b"bytes"));
+        let mut delays = self.download_retry_policy().delays();
+        let len = loop {
+            let error = match response.copy_to(&mut writer) {
+                Ok(_) => break writer.downloaded,
+                Err(error) => error,
+            };
+            // The request's timeout covers the body too, report it as the deadline
+            self.check_deadline()?;
+            // Ask for the rest of a body that broke off. Everything received
+            // so far went through the writer, so a hash computed by it covers
+            // those bytes and the rest alike.
+            match delays.next() {
+                Some(delay) if resumable && writer.downloaded > 0 => {
+                    log::debug!(
+                        "Resuming {url} at byte {} after: {error}",
+                        writer.downloaded
+                    );
+                    // Give the host's connection permit back before asking
+                    // again, a host allowed one connection would wait forever
+                    drop(response);
+                    std::thread::sleep(delay);
+                    response = self.get_package(&url, Some(writer.downloaded))?;
+                    if !response.continues_at(writer.downloaded) {
+                        return Err(format!("{url} can't be resumed: {error}").into());
+                    }
+                }
+                _ => return Err(Box::new(error)),
+            }
+        };
+        self.record_bytes_downloaded(len);
+        timings.download += start.elapsed();
+        Ok(len)
+    }
+
+    pub fn download_package_bytes(
+        &self,
+        package_name: &str,
+        version: &str,
+    ) -> Result<Vec<u8>, Box<dyn std::error::Error + Send + Sync>> {
+        check_package_input(package_name, version)?;
+        let mut bytes = Vec::new();
+        self.download_package_stream(
+            package_name,
+            version,
+            &mut bytes,
+            &mut PackageTimings::default(),
+        )?;
+        Ok(bytes)
+    }
+
+    pub fn download_package_to_writer<W: Write>(
+        &self,
+        package_name: &str,
+        version: &str,
+        writer: &mut W,
+    ) -> Result<u64, Box<dyn std::error::Error + Send + Sync>> {
+        self.download_package_stream(
+            package_name,
+            version,
+            writer,
+            &mut PackageTimings::default(),
+        )
+    }
+
+    /// Like `download_package_to_writer`, but hashes the bytes as they are
+    /// streamed and compares them against the feed's hash once the download
+    /// completes. The writer will already have received the bytes when a
+    /// mismatch is reported.
+    pub fn download_package_to_writer_verified<W: Write>(
+        &self,
+        package_name: &str,
+        version: &str,
+        writer: &mut W,
+    ) -> Result<u64, Box<dyn std::error::Error + Send + Sync>> {
+        let (len, _) = self.download_package_stream_verified(package_name, version, writer)?;
+        Ok(len)
+    }
+
+    /// Downloads the package into memory and verifies it against the feed's
+    /// hash, returning the bytes along with the hash they matched.
+    pub fn download_package_bytes_verified(
+        &self,
+        package_name: &str,
+        version: &str,
+    ) -> Result<(Vec<u8>, PackageHash), Box<dyn std::error::Error + Send + Sync>> {
+        self.retry_on_hash_mismatch(package_name, version, || {
+            let mut bytes = Vec::new();
+            let (_, hash) =
+                self.download_package_stream_verified(package_name, version, &mut bytes)?;
+            Ok((bytes, hash))
+        })
+    }
+
+    fn download_package_stream_verified(
+        &self,
+        package_name: &str,
+        version: &str,
+        writer: &mut dyn Write,
+    ) -> Result<(u64, PackageHash), Box<dyn std::error::Error + Send + Sync>> {
+        let hash = self.get_package_hash(package_name, version)?;
+        let expected = self.verification_hash(package_name, version, &hash)?;
+        let mut hashing_writer =
+            HashingWriter::new(writer, expected.as_ref().map(|hash| &hash.algorithm));
+        let len = self.download_package_stream(
+            package_name,
+            version,
+            &mut hashing_writer,
+            &mut PackageTimings::default(),
+        )?;
+        if let (Some(expected), Some(actual)) = (&expected, hashing_writer.finalize()) {
+            self.check_hash(package_name, version, expected, &actual)?;
+        }
+        Ok((len, expected.unwrap_or(hash)))
+    }
+
+    // Fails with `HashMismatch` unless `actual` is the expected hash.
+    fn check_hash(
+        &self,
+        package_name: &str,
+        version: &str,
+        expected: &PackageHash,
+        actual: &[u8],
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let actual = base64::encode(actual);
+        if actual != expected.hash {
+            return Err(Box::new(NugetError::HashMismatch {
+                package_name: package_name.to_owned(),
+                version: version.to_owned(),
+                expected: expected.hash.clone(),
+                actual,
+            }));
+        }
+        Ok(())
+    }
+
+    // Runs the download again when it fails with `HashMismatch`, up to the
+    // configured number of times.
+    fn retry_on_hash_mismatch<T>(
+        &self,
+        package_name: &str,
+        version: &str,
+        mut download: impl FnMut() -> Result<T, Box<dyn std::error::Error + Send + Sync>>,
+    ) -> Result<T, Box<dyn std::error::Error + Send + Sync>> {
+        let mut retries = 0;
+        loop {
+            match download() {
+                Err(error)
+                    if retries < self.hash_mismatch_retries
+                        && matches!(
+                            error.downcast_ref(),
+                            Some(NugetError::HashMismatch { .. })
+                        ) =>
+                {
+                    retries += 1;
+                    log::warn!("Retrying {package_name} {version}: {error}");
+                }
+                result => return result,
+            }
+        }
+    }
+
+    pub fn download_package_overwrite<P: AsRef<Path>>(
+        &self,
+        package_name: &str,
+        version: &str,
+        download_dir: P,
+    ) -> Result<NugetPackageFile, Box<dyn std::error::Error + Send + Sync>> {
+        self.download_package_overwrite_timed(
+            package_name,
+            version,
+            download_dir.as_ref(),
+            None,
+            false,
+            &mut PackageTimings::default(),
+        )
+        .map(|(file, _)| file)
+    }
+
+    // With `hash_from_response`, the package is verified against the
+    // checksum in the response headers, or failing that the feed's hash,
+    // instead of `expected_hash`. Returns the hash the package matched, if
+    // it was checked.
+    fn download_package_overwrite_timed(
+        &self,
+        package_name: &str,
+        version: &str,
+        download_dir: &Path,
+        expected_hash: Option<&PackageHash>,
+        hash_from_response: bool,
+        timings: &mut PackageTimings,
+    ) -> Result<(NugetPackageFile, Option<PackageHash>), Box<dyn std::error::Error + Send + Sync>>
+    {
+        let download_dir = self.writable_cache_dir(download_dir)?;
+        let path = long_path(&self.cache_path(package_name, version, download_dir));
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        // Download into a temporary file and move it into place once it's
+        // complete, so an interrupted download never leaves a truncated package
+        // where a later run would find it.
+        let temp_path = {
+            let temp_dir = match &self.temp_dir {
+                Some(temp_dir) => {
+                    std::fs::create_dir_all(temp_dir)?;
+                    long_path(temp_dir)
+                }
+                None => path.parent().unwrap_or(Path::new("")).to_owned(),
+            };
+            let file_name = get_package_file_name(package_name, version);
+            let temp_file_name = match &self.temp_file_naming {
+                Some(naming) => naming.temp_file_name(&file_name),
+                None => {
+                    remove_stale_temp_files(&temp_dir, &file_name);
+                    default_temp_file_name(&file_name)
+                }
+            };
+            temp_dir.join(temp_file_name)
+        };
+        let result = File::options()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&temp_path)
+            .map_err(Into::into)
+            .and_then(|mut temp_file| {
+                // Most feeds use SHA512, so that's hashed while the algorithm
+                // the response uses isn't known yet
+                let streamed_algorithm = match hash_from_response {
+                    true => Some(&HashAlgorithm::SHA512),
+                    false => expected_hash.map(|hash| &hash.algorithm),
+                };
+                let mut hashing_writer = HashingWriter::new(&mut temp_file, streamed_algorithm);
+                let mut response_hash = None;
+                self.download_package_stream_with_checksum(
+                    package_name,
+                    version,
+                    &mut hashing_writer,
+                    timings,
+                    hash_from_response.then_some(&mut response_hash),
+                )?;
+                let mut actual = hashing_writer.finalize();
+                check_package_format(package_name, version, &temp_file)?;
+                let expected_hash = match (hash_from_response, response_hash) {
+                    (false, _) => expected_hash.cloned(),
+                    (true, Some(hash)) => self.verification_hash(package_name, version, &hash)?,
+                    (true, None) => self.expected_package(package_name, version, timings)?.hash,
+                };
+                if let Some(expected_hash) = &expected_hash {
+                    if expected_hash.algorithm != HashAlgorithm::SHA512 && hash_from_response {
+                        actual = hash_file(&temp_path, &expected_hash.algorithm)?;
+                    }
+                }
+                if let (Some(expected_hash), Some(actual)) = (&expected_hash, &actual) {
+                    self.check_hash(package_name, version, expected_hash, actual)?;
+                }
+                // Checked before the rename so unsigned packages never enter the cache
+                self.check_signature(package_name, version, &temp_file)?;
+                self.check_identity(package_name, version, &temp_path)?;
+                if self.durable {
+                    temp_file.flush()?;
+                    temp_file.sync_all()?;
+                }
+                Ok((expected_hash, actual))
+            })
+            .and_then(|verified| {
+                std::fs::rename(&temp_path, &path)?;
+                Ok(verified)
+            });
+        let (expected_hash, actual) = match result {
+            Ok(verified) => verified,
+            Err(error) => {
+                let _ = std::fs::remove_file(&temp_path);
+                return Err(error);
+            }
+        };
+        // `actual` is only set when the download was checked against the hash
+        let verified_hash = expected_hash.filter(|_| actual.is_some());
+        if self.hash_sidecars {
+            update_sidecar(&path, verified_hash.as_ref())?;
+        }
+        if self.durable {
+            sync_dir(path.parent().unwrap_or(Path::new("")))?;
+        }
+
+        let file = File::open(&path)?;
+        let file = NugetPackageFile {
+            stem: get_package_file_stem(package_name, version),
+            file,
+        };
+        Ok((file, verified_hash))
+    }
+
+    // The directory downloads into `download_dir` should go to: the directory
+    // itself if it's writable, otherwise the fallback cache dir.
+    fn writable_cache_dir<'a>(
+        &'a self,
+        download_dir: &'a Path,
+    ) -> Result<&'a Path, Box<dyn std::error::Error + Send + Sync>> {
+        if is_writable(download_dir)? {
+            return Ok(download_dir);
+        }
+        match &self.fallback_cache_dir {
+            Some(fallback) => {
+                log::warn!(
+                    "{} is not writable, downloading into {}",
+                    download_dir.display(),
+                    fallback.display()
+                );
+                Ok(fallback)
+            }
+            None => Err(Box::new(NugetError::CacheNotWritable {
+                path: download_dir.to_owned(),
+            })),
+        }
+    }
+
+    pub fn download_package_id<P: AsRef<Path>>(
+        &self,
+        package_id: &PackageId,
+        download_dir: P,
+    ) -> Result<NugetPackageFile, Box<dyn std::error::Error + Send + Sync>> {
+        let version = package_id.version.to_string();
+        self.download_package(&package_id.name, &version, download_dir)
+    }
+
+    /// Like `download_package_id`, but the file keeps the package's id, path
+    /// and hash with it.
+    pub fn download_package_file<P: AsRef<Path>>(
+        &self,
+        package_id: &PackageId,
+        download_dir: P,
+    ) -> Result<PackageFile, Box<dyn std::error::Error + Send + Sync>> {
+        let version = package_id.version.to_string();
+        let DownloadResult {
+            file,
+            path,
+            verified_hash,
+            ..
+        } = self.download_package_timed(&package_id.name, &version, download_dir)?;
+        // The package was usually just verified against its SHA512
+        let hash = match verified_hash {
+            Some(hash) if hash.algorithm == HashAlgorithm::SHA512 => hash,
+            _ => {
+                let hash = hash_file(long_path(&path), &HashAlgorithm::SHA512)?
+                    .expect("SHA512 can always be computed");
+                PackageHash {
+                    hash: base64::encode(hash),
+                    algorithm: HashAlgorithm::SHA512,
+                }
+            }
+        };
+        Ok(PackageFile {
+            id: package_id.clone(),
+            path,
+            hash,
+            file: file.file,
+        })
+    }
+
+    /// Like `download_package_file`, for a version requirement rather than an
+    /// exact version: an exact version, a floating version like `1.2.*` or
+    /// `>=1.2.0` (the highest match), a range like `[1.0,2.0)` (the lowest
+    /// match, like NuGet) or `latest` (the newest stable version). The
+    /// returned file's id has the version it resolved to.
+    pub fn download_matching_package<P: AsRef<Path>>(
+        &self,
+        package_name: &str,
+        requirement: &str,
+        download_dir: P,
+    ) -> Result<PackageFile, Box<dyn std::error::Error + Send + Sync>> {
+        check_package_input(package_name, requirement)?;
+        let requirement = requirement.trim();
+        let version = if requirement.eq_ignore_ascii_case("latest") {
+            self.resolve_latest_version(package_name, false)?
+        } else if requirement.starts_with(['[', '(']) {
+            self.resolve_version_range(package_name, &requirement.parse()?)?
+        } else {
+            resolve_requirement(self, package_name, requirement)?
+        };
+        let package_id = PackageId::new(package_name, &version)?;
+        self.download_package_file(&package_id, download_dir)
+    }
+
+    pub fn download_package<P: AsRef<Path>>(
+        &self,
+        package_name: &str,
+        version: &str,
+        download_dir: P,
+    ) -> Result<NugetPackageFile, Box<dyn std::error::Error + Send + Sync>> {
+        let result = self.download_package_timed(package_name, version, download_dir)?;
+        Ok(result.file)
+    }
+
+    /// Like `download_package`, but also reports how long each phase took,
+    /// to find out whether the network or hashing is the bottleneck.
+    pub fn download_package_timed<P: AsRef<Path>>(
+        &self,
+        package_name: &str,
+        version: &str,
+        download_dir: P,
+    ) -> Result<DownloadResult, Box<dyn std::error::Error + Send + Sync>> {
+        self.download_package_expecting(package_name, version, download_dir.as_ref(), None)
+    }
+
+    /// Like `download_package_timed`, but verifies the cached or downloaded
+    /// package against `hash` instead of the hash the feed reports, e.g. one
+    /// pinned in a config. The feed's metadata isn't used at all.
+    pub fn download_package_with_hash<P: AsRef<Path>>(
+        &self,
+        package_name: &str,
+        version: &str,
+        download_dir: P,
+        hash: &PackageHash,
+    ) -> Result<DownloadResult, Box<dyn std::error::Error + Send + Sync>> {
+        if digest_for(&hash.algorithm).is_none() {
+            return Err(Box::new(NugetError::UnsupportedHashAlgorithm(
+                hash.algorithm.as_str().to_owned(),
+            )));
+        }
+        self.download_package_expecting(package_name, version, download_dir.as_ref(), Some(hash))
+    }
+
+    /// Verifies the package cached in `cache_dir` and copies it to `dest`, or
+    /// into `dest` when it's a directory, returning where it was copied to.
+    /// Nothing is downloaded: a package that isn't cached or doesn't match is
+    /// an error. A cached package with a matching sidecar (see
+    /// `with_hash_sidecars`) is verified without contacting the feed.
+    pub fn copy_cached_package<P: AsRef<Path>, D: AsRef<Path>>(
+        &self,
+        package_name: &str,
+        version: &str,
+        cache_dir: P,
+        dest: D,
+    ) -> Result<PathBuf, Box<dyn std::error::Error + Send + Sync>> {
+        check_package_input(package_name, version)?;
+        let path = long_path(&self.cache_path(package_name, version, cache_dir.as_ref()));
+        if !path.exists() {
+            return Err(format!(
+                "{package_name} {version} is not cached at {}",
+                path.display()
+            )
+            .into());
+        }
+
+        let sidecar_matches = match read_sidecar(&path).filter(|_| self.hash_sidecars) {
+            Some(hash) => self.file_matches_hash(&path, &hash)?,
+            None => false,
+        };
+        let mut timings = PackageTimings::default();
+        let expected = match sidecar_matches {
+            true => None,
+            false => Some(self.expected_package(package_name, version, &mut timings)?),
+        };
+        if let Some(expected) = expected.filter(|expected| {
+            !self
+                .file_matches(expected, &path, &mut timings)
+                .unwrap_or(false)
+        }) {
+            let (expected, actual) = match &expected.hash {
+                Some(hash) => {
+                    let actual = hash_file(&path, &hash.algorithm)?.unwrap_or_default();
+                    (hash.hash.clone(), base64::encode(actual))
+                }
+                None => (
+                    format!("{} bytes", expected.size.unwrap_or_default()),
+                    format!("{} bytes", std::fs::metadata(&path)?.len()),
+                ),
+            };
+            return Err(Box::new(NugetError::HashMismatch {
+                package_name: package_name.to_owned(),
+                version: version.to_owned(),
+                expected,
+                actual,
+            }));
+        }
+
+        let dest = dest.as_ref();
+        let dest = if dest.is_dir() {
+            dest.join(path.file_name().unwrap_or_default())
+        } else {
+            dest.to_owned()
+        };
+        // Copied next to the destination and moved into place, so a failed
+        // copy doesn't leave a truncated package behind
+        let mut temp_path = dest.as_os_str().to_owned();
+        temp_path.push(format!(".copy-{}", std::process::id()));
+        let temp_path = PathBuf::from(temp_path);
+        let result = (|| -> std::io::Result<()> {
+            let mut reader = BufReader::with_capacity(hash_buffer_size(), File::open(&path)?);
+            let mut writer =
+                BufWriter::with_capacity(hash_buffer_size(), File::create(&temp_path)?);
+            std::io::copy(&mut reader, &mut writer)?;
+            writer.flush()?;
+            std::fs::rename(&temp_path, &dest)
+        })();
+        if let Err(error) = result {
+            let _ = std::fs::remove_file(&temp_path);
+            return Err(Box::new(error));
+        }
+        Ok(dest)
+    }
+
+    /// Downloads and verifies the package, then extracts the entries the
+    /// filter matches into `dest_dir`, returning the extracted paths. Unless
+    /// `keep_package` is set, the package is downloaded next to `dest_dir`
+    /// (or into the temp dir) and deleted once it's extracted. Kept packages
+    /// are cached in `dest_dir` like `download_package` would.
+    pub fn download_and_extract<P: AsRef<Path>, F: EntryFilter>(
+        &self,
+        package_name: &str,
+        version: &str,
+        dest_dir: P,
+        filter: F,
+        keep_package: bool,
+    ) -> Result<Vec<PathBuf>, Box<dyn std::error::Error + Send + Sync>> {
+        let dest_dir = dest_dir.as_ref();
+        let options = ExtractOptions::default();
+        if keep_package {
+            let file = self.download_package(package_name, version, dest_dir)?;
+            return extract_package_files(&file, dest_dir, &options, filter);
+        }
+
+        check_package_input(package_name, version)?;
+        let mut timings = PackageTimings::default();
+        let expected = self.expected_package(package_name, version, &mut timings)?;
+        let staging_dir = {
+            let parent = self.temp_dir.as_deref().unwrap_or(dest_dir);
+            let stem = get_package_file_stem(package_name, version);
+            parent.join(format!(".{stem}.extract-{}", std::process::id()))
+        };
+        let result = self
+            .retry_on_hash_mismatch(package_name, version, || {
+                self.download_package_overwrite_timed(
+                    package_name,
+                    version,
+                    &staging_dir,
+                    expected.hash.as_ref(),
+                    false,
+                    &mut timings,
+                )
+            })
+            .and_then(|(file, _)| extract_package_files(&file, dest_dir, &options, filter));
+        let _ = std::fs::remove_dir_all(&staging_dir);
+        result
+    }
+
+    fn download_package_expecting(
+        &self,
+        package_name: &str,
+        version: &str,
+        download_dir: &Path,
+        pinned_hash: Option<&PackageHash>,
+    ) -> Result<DownloadResult, Box<dyn std::error::Error + Send + Sync>> {
+        check_package_input(package_name, version)?;
+        let mut timings = PackageTimings::default();
+
+        // Get the download file path
+        let mut path = long_path(&self.cache_path(package_name, version, download_dir));
+        log::debug!("Package path: {}", path.display());
+
+        // A sidecar lets the cached copy be verified without the feed. A size
+        // this client already looked up still rules out most stale copies
+        // without hashing them.
+        let cached_size = self
+            .metadata_cache
+            .get(package_name, version)
+            .and_then(|metadata| metadata.size);
+        let sidecar = match pinned_hash {
+            None if self.hash_sidecars && path.exists() => read_sidecar(&path),
+            _ => None,
+        };
+        let sidecar_matches = sidecar.as_ref().is_some_and(|hash| {
+            let expected = ExpectedPackage {
+                size: cached_size,
+                hash: Some(hash.clone()),
+            };
+            self.file_matches(&expected, &path, &mut timings)
+                .unwrap_or(false)
+        });
+        // A package that isn't cached anywhere can be checked against the
+        // download's own checksum instead of the feed's, as long as the
+        // download comes from the feed
+        let hash_from_response = pinned_hash.is_none()
+            && sidecar.is_none()
+            && self.header_checksums
+            && self.fail_on_vulnerability.is_none()
+            && self.download_source == self.metadata_source
+            && !path.exists()
+            && self.writable_cache_dir(download_dir)? == download_dir;
+        let expected = match (pinned_hash, sidecar) {
+            _ if hash_from_response => ExpectedPackage {
+                size: None,
+                hash: None,
+            },
+            (Some(hash), _) => ExpectedPackage {
+                size: cached_size,
+                hash: Some(hash.clone()),
+            },
+            (None, Some(hash)) if sidecar_matches => ExpectedPackage {
+                size: cached_size,
+                hash: Some(hash),
+            },
+            _ => self.expected_package(package_name, version, &mut timings)?,
+        };
+
+        // First check if the file is already there. Treat failures to read it
+        // as a failing match.
+        let mut matches = sidecar_matches
+            || path.exists()
+                && self
+                    .file_matches(&expected, &path, &mut timings)
+                    .unwrap_or(false);
+        let mut download_dir = download_dir;
+        if !matches {
+            // A read-only cache may be missing the package, the fallback
+            // may have it from an earlier run
+            let writable_dir = self.writable_cache_dir(download_dir)?;
+            if writable_dir != download_dir {
+                download_dir = writable_dir;
+                path = long_path(&self.cache_path(package_name, version, download_dir));
+                matches = path.exists()
+                    && self
+                        .file_matches(&expected, &path, &mut timings)
+                        .unwrap_or(false);
+            }
+        }
+
+        let (file, verified_hash) = if !matches {
+            self.retry_on_hash_mismatch(package_name, version, || {
+                self.download_package_overwrite_timed(
+                    package_name,
+                    version,
+                    download_dir,
+                    expected.hash.as_ref(),
+                    hash_from_response,
+                    &mut timings,
+                )
+            })?
+        } else {
+            let stem = get_package_file_stem(package_name, version);
+            let file = File::open(&path)?;
+            // The package may have been cached before signatures were required
+            self.check_signature(package_name, version, &file)?;
+            self.check_identity(package_name, version, &path)?;
+            // or before sidecars were written, or its sidecar is stale. The
+            // cache may be read-only, so that's not an error.
+            if self.hash_sidecars && !sidecar_matches {
+                if let Err(error) = update_sidecar(&path, expected.hash.as_ref()) {
+                    log::debug!("Not updating the sidecar for {}: {error}", path.display());
+                }
+            }
+            // A cached package only matches a hash it was checked against
+            (NugetPackageFile { stem, file }, expected.hash)
+        };
+        if let Some(stats) = &self.stats {
+            stats.add_cache_lookup(matches);
+        }
+        Ok(DownloadResult {
+            file,
+            downloaded: !matches,
+            path,
+            timings,
+            verified_hash,
+        })
+    }
+
+    /// Fetches the metadata feed's v3 service index, nuget.org's for its V2
+    /// feed. The index is fetched once and reused for the lifetime of the
+    /// client. Other V2 feeds and local feeds have no service index.
+    pub fn get_service_index(
+        &self,
+    ) -> Result<Arc<ServiceIndex>, Box<dyn std::error::Error + Send + Sync>> {
+        let index_url = self
+            .v3_index()
+            .ok_or("The metadata feed has no V3 service index")?;
+        self.feed_index(index_url)
+    }
+
+    pub fn search_packages(
+        &self,
+        query: &str,
+        take: usize,
+        include_prerelease: bool,
+    ) -> Result<Vec<SearchResult>, Box<dyn std::error::Error + Send + Sync>> {
+        let service_index = self.get_service_index()?;
+        let search_service = service_index
+            .resource("SearchQueryService")
+            .ok_or("The feed does not provide a SearchQueryService")?;
+        let mut url = reqwest::Url::parse(&search_service.id)?;
+        url.query_pairs_mut()
+            .append_pair("q", query)
+            .append_pair("take", &take.to_string())
+            .append_pair("prerelease", &include_prerelease.to_string())
+            .append_pair("semVerLevel", "2.0.0");
+        let response: SearchResponse = self.get(url.as_str())?.json()?;
+        Ok(response.data)
+    }
+
+    pub fn get_package_metadata(
+        &self,
+        package_name: &str,
+        version: &str,
+    ) -> Result<PackageMetadata, Box<dyn std::error::Error + Send + Sync>> {
+        check_package_input(package_name, version)?;
+        if let Some(metadata) = self.metadata_cache.get(package_name, version) {
+            return Ok(metadata);
+        }
+        let metadata = self.fetch_package_metadata(package_name, version)?;
+        self.metadata_cache
+            .insert(package_name, version, metadata.clone());
+        Ok(metadata)
+    }
+
+    /// When the version was published and last updated, as the feed formats
+    /// them, e.g. `2022-07-30T10:00:00.123Z`. Each is `None` when
+    /// the feed leaves it out or sends it empty. nuget.org reports unlisted
+    /// versions as published in 1900. Local feeds report neither.
+    pub fn get_package_timestamps(
+        &self,
+        package_name: &str,
+        version: &str,
+    ) -> Result<PackageTimestamps, Box<dyn std::error::Error + Send + Sync>> {
+        let metadata = self.get_package_metadata(package_name, version)?;
+        Ok(PackageTimestamps {
+            published: metadata.published,
+            last_updated: metadata.last_updated,
+        })
+    }
+
+    /// The metadata response for the package as the feed sent it, for fields
+    /// `PackageMetadata` doesn't have: the OData XML entry for V2 feeds, the
+    /// catalog entry JSON for V3 feeds and the `.nuspec` for local feeds.
+    /// Limited to `with_max_metadata_size`. Not cached.
+    pub fn get_raw_metadata(
+        &self,
+        package_name: &str,
+        version: &str,
+    ) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+        check_package_input(package_name, version)?;
+        match &self.metadata_source {
+            FeedSource::Url(base_url) => {
+                let url = format!("{base_url}/Packages(Id='{package_name}',Version='{version}')");
+                self.get(&url)?.text()
+            }
+            FeedSource::V3(index_url) => self.get_catalog_leaf(index_url, package_name, version),
+            FeedSource::LocalDir(dir) => feed::package_nuspec(dir, package_name, version),
+        }
+    }
+
+    fn fetch_package_metadata(
+        &self,
+        package_name: &str,
+        version: &str,
+    ) -> Result<PackageMetadata, Box<dyn std::error::Error + Send + Sync>> {
+        match &self.metadata_source {
+            FeedSource::Url(_) => {}
+            FeedSource::V3(index_url) => {
+                let metadata = self.get_v3_package_metadata(index_url, package_name, version)?;
+                return Ok(metadata);
+            }
+            FeedSource::LocalDir(dir) => return feed::package_metadata(dir, package_name, version),
+        };
+        let text = self.get_raw_metadata(package_name, version)?;
+        let feed = parse_odata(&text, &self.odata_aliases)?;
+        let mut metadata =
+            PackageMetadata::from_properties(feed.package_entry(package_name, version)?)
+                .ok_or_else(|| format!("No metadata found for {package_name} {version}"))?;
+        self.add_advisories(&mut metadata)?;
+        Ok(metadata)
+    }
+
+    fn add_advisories(
+        &self,
+        metadata: &mut PackageMetadata,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        if !self.advisories {
+            return Ok(());
+        }
+        let Some(entry) = self.get_catalog_entry(&metadata.id, &metadata.version)? else {
+            return Ok(());
+        };
+        metadata.deprecation = entry.deprecation;
+        metadata.vulnerabilities = entry.vulnerabilities;
+        warn_advisories(metadata);
+        Ok(())
+    }
+
+    // The version's entry in nuget.org's registration index.
+    fn get_catalog_entry(
+        &self,
+        package_name: &str,
+        version: &str,
+    ) -> Result<Option<CatalogEntry>, Box<dyn std::error::Error + Send + Sync>> {
+        let registrations = self.v3_resource(NUGET_V3_INDEX_URL, "RegistrationsBaseUrl")?;
+        let parsed_version = version.parse::<NugetVersion>().ok();
+        let same_version =
+            |candidate: &str| match (&parsed_version, candidate.parse::<NugetVersion>()) {
+                (Some(version), Ok(candidate)) => *version == candidate,
+                _ => candidate.eq_ignore_ascii_case(version),
+            };
+        Ok(self
+            .registration_entries(&registrations, package_name, parsed_version.as_ref())?
+            .into_iter()
+            .find(|entry| same_version(&entry.version)))
+    }
+
+    // The package's entries in a registration index, fetching the pages the
+    // index doesn't inline. Only pages that could hold `version` are
+    // fetched when there is one.
+    fn registration_entries(
+        &self,
+        registrations_url: &str,
+        package_name: &str,
+        version: Option<&NugetVersion>,
+    ) -> Result<Vec<CatalogEntry>, Box<dyn std::error::Error + Send + Sync>> {
+        let url = format!(
+            "{}/{}/index.json",
+            registrations_url.trim_end_matches('/'),
+            package_name.to_lowercase()
+        );
+        let index: RegistrationIndex = self.get(&url)?.json()?;
+        let mut entries = Vec::new();
+        for page in index.items {
+            let in_page = match (
+                version,
+                page.lower.parse::<NugetVersion>(),
+                page.upper.parse::<NugetVersion>(),
+            ) {
+                (Some(version), Ok(lower), Ok(upper)) => lower <= *version && *version <= upper,
+                _ => true,
+            };
+            if !in_page {
+                continue;
+            }
+            let items = match page.items {
+                Some(items) => items,
+                None => self
+                    .get(&page.id)?
+                    .json::<RegistrationPage>()?
+                    .items
+                    .unwrap_or_default(),
+            };
+            entries.extend(items.into_iter().map(|leaf| leaf.catalog_entry));
+        }
+        Ok(entries)
+    }
+
+    // A V3 feed's service index. Indexes are fetched once per client.
+    fn feed_index(
+        &self,
+        index_url: &str,
+    ) -> Result<Arc<ServiceIndex>, Box<dyn std::error::Error + Send + Sync>> {
+        let mut indexes = self.feed_indexes.lock().unwrap();
+        let index = match indexes.entry(index_url.to_owned()) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                let text = self.get(index_url)?.text()?;
+                entry.insert(Arc::new(ServiceIndex::from_json(&text)?))
+            }
+        };
+        Ok(index.clone())
+    }
+
+    // The URL of a resource in a V3 feed's service index.
+    fn v3_resource(
+        &self,
+        index_url: &str,
+        resource_type: &str,
+    ) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+        let index = self.feed_index(index_url)?;
+        let resource = index
+            .resource(resource_type)
+            .ok_or_else(|| format!("{index_url} does not provide a {resource_type}"))?;
+        Ok(resource.id.trim_end_matches('/').to_owned())
+    }
+
+    fn registration_leaf_url(
+        &self,
+        index_url: &str,
+        package_name: &str,
+        version: &str,
+    ) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+        let registrations = self.v3_resource(index_url, "RegistrationsBaseUrl")?;
+        Ok(format!(
+            "{registrations}/{}/{}.json",
+            package_name.to_lowercase(),
+            version.to_lowercase()
+        ))
+    }
+
+    fn get_catalog_leaf(
+        &self,
+        index_url: &str,
+        package_name: &str,
+        version: &str,
+    ) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+        let url = self.registration_leaf_url(index_url, package_name, version)?;
+        let leaf: RegistrationLeafDocument = self.get(&url)?.json()?;
+        self.get(&leaf.catalog_entry)?.text()
+    }
+
+    fn get_v3_package_metadata(
+        &self,
+        index_url: &str,
+        package_name: &str,
+        version: &str,
+    ) -> Result<PackageMetadata, Box<dyn std::error::Error + Send + Sync>> {
+        let text = self.get_catalog_leaf(index_url, package_name, version)?;
+        let leaf: CatalogLeaf = serde_json::from_str(&text)?;
+        let metadata = PackageMetadata::from(leaf);
+        warn_advisories(&metadata);
+        Ok(metadata)
+    }
+
+    fn check_vulnerabilities(
+        &self,
+        metadata: &PackageMetadata,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let Some(threshold) = self.fail_on_vulnerability else {
+            return Ok(());
+        };
+        match metadata
+            .vulnerabilities
+            .iter()
+            .filter(|vulnerability| vulnerability.severity >= threshold)
+            .max_by_key(|vulnerability| vulnerability.severity)
+        {
+            Some(vulnerability) => Err(Box::new(NugetError::VulnerablePackage {
+                package_name: metadata.id.clone(),
+                version: metadata.version.clone(),
+                severity: vulnerability.severity,
+                advisory_url: vulnerability.advisory_url.clone(),
+            })),
+            None => Ok(()),
+        }
+    }
+
+    /// Fetches metadata for several packages, batching them into `$filter`
+    /// queries where the feed supports it. Anything a batch doesn't return
+    /// is fetched individually.
+    pub fn get_packages_metadata(
+        &self,
+        packages: &[(&str, &str)],
+    ) -> Result<HashMap<(String, String), PackageMetadata>, Box<dyn std::error::Error + Send + Sync>>
+    {
+        let mut results = HashMap::new();
+        // Only V2 feeds can batch
+        let base_url = match &self.metadata_source {
+            FeedSource::Url(url) => Some(url.as_str()),
+            FeedSource::V3(_) | FeedSource::LocalDir(_) => None,
+        };
+        for batch in packages.chunks(METADATA_BATCH_SIZE) {
+            let Some(base_url) = base_url else {
+                break;
+            };
+            // Batching is best effort, feeds without $filter support fall through
+            if let Ok(entries) = self.query_packages_metadata(base_url, batch) {
+                for &(package_name, version) in batch {
+                    let found = entries.iter().find(|properties| {
+                        let property = |key: &str| properties.get(key).map(String::as_str);
+                        property("Id").is_some_and(|id| id.eq_ignore_ascii_case(package_name))
+                            && (property("Version") == Some(version)
+                                || property("NormalizedVersion") == Some(version))
+                    });
+                    if let Some(mut metadata) = found.and_then(PackageMetadata::from_properties) {
+                        self.add_advisories(&mut metadata)?;
+                        self.metadata_cache
+                            .insert(package_name, version, metadata.clone());
+                        results.insert((package_name.to_owned(), version.to_owned()), metadata);
+                    }
+                }
+            }
+        }
+        for &(package_name, version) in packages {
+            let key = (package_name.to_owned(), version.to_owned());
+            if let Entry::Vacant(entry) = results.entry(key) {
+                entry.insert(self.get_package_metadata(package_name, version)?);
+            }
+        }
+        Ok(results)
+    }
+
+    fn query_packages_metadata(
+        &self,
+        base_url: &str,
+        packages: &[(&str, &str)],
+    ) -> Result<Vec<HashMap<String, String>>, Box<dyn std::error::Error + Send + Sync>> {
+        let escape = |value: &str| value.replace('\'', "''");
+        let filter = packages
+            .iter()
+            .map(|(package_name, version)| {
+                format!(
+                    "(Id eq '{}' and Version eq '{}')",
+                    escape(package_name),
+                    escape(version)
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(" or ");
+        let mut url = Some(format!(
+            "{base_url}/Packages()?$filter={}",
+            filter.replace(' ', "%20")
+        ));
+        let mut entries = Vec::new();
+        while let Some(next_url) = url {
+            let text = self.get(&next_url)?.text()?;
+            let feed = parse_odata(&text, &self.odata_aliases)?;
+            entries.extend(feed.entries);
+            url = feed.next;
+        }
+        Ok(entries)
+    }
+
+    /// Lists every version of a package known to the feed, optionally
+    /// including unlisted versions.
+    pub fn list_package_versions(
+        &self,
+        package_name: &str,
+        include_unlisted: bool,
+    ) -> Result<Vec<String>, Box<dyn std::error::Error + Send + Sync>> {
+        let versions = self.list_published_versions(package_name, include_unlisted)?;
+        Ok(versions.into_iter().map(|(version, _)| version).collect())
+    }
+
+    // Like `list_package_versions`, with each version's publish date.
+    fn list_published_versions(
+        &self,
+        package_name: &str,
+        include_unlisted: bool,
+    ) -> Result<Vec<Published<String>>, Box<dyn std::error::Error + Send + Sync>> {
+        let base_url = match &self.metadata_source {
+            FeedSource::Url(url) => url,
+            FeedSource::V3(index_url) => {
+                let registrations = self.v3_resource(index_url, "RegistrationsBaseUrl")?;
+                let entries = self.registration_entries(&registrations, package_name, None)?;
+                return Ok(entries
+                    .into_iter()
+                    .filter(|entry| include_unlisted || entry.listed)
+                    .map(|entry| (entry.version, entry.published))
+                    .collect());
+            }
+            // Every package in a local feed counts as listed
+            FeedSource::LocalDir(dir) => {
+                let versions = feed::list_versions(dir, package_name)?;
+                return Ok(versions
+                    .into_iter()
+                    .map(|version| (version, None))
+                    .collect());
+            }
+        };
+        let mut versions = Vec::new();
+        let mut url = Some(format!("{base_url}/FindPackagesById()?id='{package_name}'"));
+        while let Some(next_url) = url {
+            let text = self.get(&next_url)?.text()?;
+            let feed = parse_odata(&text, &self.odata_aliases)?;
+            for properties in &feed.entries {
+                if let Some(metadata) = PackageMetadata::from_properties(properties) {
+                    if include_unlisted || metadata.listed {
+                        versions.push((metadata.version, metadata.published));
+                    }
+                }
+            }
+            url = feed.next;
+        }
+        Ok(versions)
+    }
+
+    /// The listed versions satisfying `range` with their publish dates.
+    /// Prereleases are only included when the lower bound of the range is a
+    /// prerelease.
+    pub(crate) fn matching_versions(
+        &self,
+        package_name: &str,
+        range: &VersionRange,
+    ) -> Result<Vec<Published<NugetVersion>>, Box<dyn std::error::Error + Send + Sync>> {
+        let allow_prerelease = range
+            .min()
+            .map(|(min, _)| min.is_prerelease())
+            .unwrap_or(false);
+        let versions = self.list_published_versions(package_name, false)?;
+        Ok(versions
+            .into_iter()
+            .filter_map(|(version, published)| {
+                Some((version.parse::<NugetVersion>().ok()?, published))
+            })
+            .filter(|(version, _)| allow_prerelease || !version.is_prerelease())
+            .filter(|(version, _)| range.satisfies(version))
+            .collect())
+    }
+
+    /// Whether the feed has this version of the package, listed or not. Only
+    /// the package's versions are fetched.
+    pub fn package_exists(
+        &self,
+        package_name: &str,
+        version: &str,
+    ) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+        let wanted = version.parse::<NugetVersion>().ok();
+        let versions = self.list_package_versions(package_name, true)?;
+        Ok(versions.iter().any(|candidate| match &wanted {
+            Some(wanted) => candidate.parse::<NugetVersion>().ok().as_ref() == Some(wanted),
+            None => candidate.eq_ignore_ascii_case(version),
+        }))
+    }
+
+    /// The lowest listed version satisfying `range`, which is how NuGet picks
+    /// dependency versions, or the highest for floating ranges like `1.2.*`.
+    /// Prereleases are only considered when the lower bound of the range is a
+    /// prerelease.
+    pub fn resolve_version_range(
+        &self,
+        package_name: &str,
+        range: &VersionRange,
+    ) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+        let versions = self.matching_versions(package_name, range)?;
+        let candidates = versions.into_iter().map(|(version, _)| version);
+        let picked = if range.is_floating() {
+            candidates.max()
+        } else {
+            candidates.min()
+        };
+        let picked = picked.ok_or_else(|| NugetError::NoMatchingVersion {
+            package_name: package_name.to_owned(),
+            requirement: range.to_string(),
+        })?;
+        Ok(picked.to_string())
+    }
+
+    /// The highest listed version that's at least `min`. Prereleases are only
+    /// considered when `min` is a prerelease.
+    pub fn resolve_min_version(
+        &self,
+        package_name: &str,
+        min: &str,
+    ) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+        let range: VersionRange = format!(">={min}").parse()?;
+        self.resolve_version_range(package_name, &range)
+    }
+
+    /// Resolves the package and its transitive dependencies, using the
+    /// dependency group nearest to `target_framework`. Without a target
+    /// framework only framework agnostic dependencies are followed. When
+    /// several packages depend on the same id, the one nearest to the root
+    /// wins. The root package is the first entry of the result.
+    pub fn resolve_dependencies(
+        &self,
+        package_name: &str,
+        version: &str,
+        target_framework: Option<&str>,
+    ) -> Result<Vec<ResolvedPackage>, Box<dyn std::error::Error + Send + Sync>> {
+        let target_framework = target_framework.map(TargetFramework::parse);
+        let mut resolved = vec![ResolvedPackage {
+            id: package_name.to_owned(),
+            version: version.to_owned(),
+            path: vec![package_name.to_owned()],
+        }];
+        let mut seen = HashSet::from([package_name.to_lowercase()]);
+        let mut queue = VecDeque::from([0]);
+        while let Some(index) = queue.pop_front() {
+            let package = resolved[index].clone();
+            let metadata = self.get_package_metadata(&package.id, &package.version)?;
+            let Some(group) = dependency_group(&metadata, target_framework.as_ref()) else {
+                continue;
+            };
+            for dependency in &group.dependencies {
+                if !seen.insert(dependency.id.to_lowercase()) {
+                    continue;
+                }
+                let range: VersionRange = dependency.version_range.parse()?;
+                let version = self.resolve_version_range(&dependency.id, &range)?;
+                let mut path = package.path.clone();
+                path.push(dependency.id.clone());
+                resolved.push(ResolvedPackage {
+                    id: dependency.id.clone(),
+                    version,
+                    path,
+                });
+                queue.push_back(resolved.len() - 1);
+            }
+        }
+        Ok(resolved)
+    }
+
+    /// Resolves the package's dependencies like `resolve_dependencies` and
+    /// downloads them into `download_dir`, but not the package itself, e.g.
+    /// when it's built locally. Only the root's metadata is needed from the
+    /// feed. The results are in resolution order.
+    pub fn download_dependencies<P: AsRef<Path>>(
+        &self,
+        package_name: &str,
+        version: &str,
+        target_framework: Option<&str>,
+        download_dir: P,
+    ) -> Result<Vec<DownloadResult>, Box<dyn std::error::Error + Send + Sync>> {
+        let download_dir = download_dir.as_ref();
+        self.resolve_dependencies(package_name, version, target_framework)?
+            .into_iter()
+            .skip(1)
+            .map(|package| self.download_package_timed(&package.id, &package.version, download_dir))
+            .collect()
+    }
+
+    /// Resolves the package's dependencies like `resolve_dependencies`, then
+    /// downloads every package in the closure and verifies it against the
+    /// feed's hash. Nothing is cached. Problems are reported on their node in
+    /// the tree rather than failing the audit, packages that can't be found
+    /// have no dependencies in it.
+    pub fn audit_closure(
+        &self,
+        package_name: &str,
+        version: &str,
+        target_framework: Option<&str>,
+    ) -> AuditNode {
+        let target_framework = target_framework.map(TargetFramework::parse);
+        // The nodes in the order they're found, with their parent's index
+        let mut nodes = vec![(
+            AuditNode {
+                id: package_name.to_owned(),
+                version: Some(version.to_owned()),
+                path: vec![package_name.to_owned()],
+                status: AuditStatus::Verified,
+                dependencies: Vec::new(),
+            },
+            None,
+        )];
+        let mut seen = HashSet::from([package_name.to_lowercase()]);
+        let mut queue = VecDeque::from([0]);
+        while let Some(index) = queue.pop_front() {
+            let (id, path) = (nodes[index].0.id.clone(), nodes[index].0.path.clone());
+            let Some(version) = nodes[index].0.version.clone() else {
+                continue;
+            };
+            let metadata = match self.get_package_metadata(&id, &version) {
+                Ok(metadata) => metadata,
+                Err(error) => {
+                    nodes[index].0.status = audit_failure(error);
+                    continue;
+                }
+            };
+            if let Err(error) =
+                self.download_package_to_writer_verified(&id, &version, &mut std::io::sink())
+            {
+                nodes[index].0.status = audit_failure(error);
+            }
+
+            let Some(group) = dependency_group(&metadata, target_framework.as_ref()) else {
+                continue;
+            };
+            for dependency in &group.dependencies {
+                if !seen.insert(dependency.id.to_lowercase()) {
+                    continue;
+                }
+                let resolved = dependency
+                    .version_range
+                    .parse::<VersionRange>()
+                    .map_err(Into::into)
+                    .and_then(|range| self.resolve_version_range(&dependency.id, &range));
+                let (version, status) = match resolved {
+                    Ok(version) => (Some(version), AuditStatus::Verified),
+                    Err(error) => (None, audit_failure(error)),
+                };
+                let mut path = path.clone();
+                path.push(dependency.id.clone());
+                let node = AuditNode {
+                    id: dependency.id.clone(),
+                    version,
+                    path,
+                    status,
+                    dependencies: Vec::new(),
+                };
+                nodes.push((node, Some(index)));
+                queue.push_back(nodes.len() - 1);
+            }
+        }
+
+        // Children are always found after their parent, so attach them last first
+        let mut taken: Vec<Option<AuditNode>> = Vec::with_capacity(nodes.len());
+        let mut parents = Vec::with_capacity(nodes.len());
+        for (node, parent) in nodes {
+            taken.push(Some(node));
+            parents.push(parent);
+        }
+        for index in (1..taken.len()).rev() {
+            let node = taken[index].take().expect("each node is attached once");
+            if let Some(parent) = parents[index].and_then(|parent| taken[parent].as_mut()) {
+                parent.dependencies.insert(0, node);
+            }
+        }
+        taken[0].take().expect("the root is attached last")
+    }
+
+    /// The newest listed version of a package. Unlisted versions are never
+    /// considered. Without `include_prerelease`, a package with no stable
+    /// versions fails, unless `with_prerelease_fallback` is set.
+    pub fn resolve_latest_version(
+        &self,
+        package_name: &str,
+        include_prerelease: bool,
+    ) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+        let versions: Vec<NugetVersion> = self
+            .list_package_versions(package_name, false)?
+            .iter()
+            .filter_map(|version| version.parse::<NugetVersion>().ok())
+            .collect();
+        let stable = versions
+            .iter()
+            .filter(|version| include_prerelease || !version.is_prerelease())
+            .max();
+        let latest = match stable {
+            None if self.prerelease_fallback => {
+                let prerelease = versions.iter().max();
+                if let Some(prerelease) = prerelease {
+                    log::warn!(
+                        "{package_name} has no stable versions, using the prerelease {prerelease}"
+                    );
+                }
+                prerelease
+            }
+            stable => stable,
+        };
+        let latest = latest.ok_or_else(|| NugetError::NoMatchingVersion {
+            package_name: package_name.to_owned(),
+            requirement: if include_prerelease {
+                "latest".to_owned()
+            } else {
+                "latest stable".to_owned()
+            },
+        })?;
+        Ok(latest.to_string())
+    }
+
+    /// Resolves and downloads the newest version of each package in parallel,
+    /// on up to `max_concurrent_downloads` threads. Results are returned in
+    /// the same order as `package_names`.
+    pub fn download_latest_batch<P: AsRef<Path>>(
+        &self,
+        package_names: &[&str],
+        download_dir: P,
+        include_prerelease: bool,
+    ) -> Vec<LatestDownload> {
+        let download_dir = download_dir.as_ref();
+        let download_latest = |package_name: &str| {
+            let version = match self.resolve_latest_version(package_name, include_prerelease) {
+                Ok(version) => version,
+                Err(error) => {
+                    return LatestDownload {
+                        package_name: package_name.to_owned(),
+                        version: None,
+                        outcome: Err(error),
+                    }
+                }
+            };
+            let outcome = self.download_package(package_name, &version, download_dir);
+            LatestDownload {
+                package_name: package_name.to_owned(),
+                version: Some(version),
+                outcome,
+            }
+        };
+        let next = AtomicUsize::new(0);
+        let threads = max_concurrent_downloads().clamp(1, package_names.len().max(1));
+        let mut downloads: Vec<_> = std::thread::scope(|scope| {
+            let handles: Vec<_> = (0..threads)
+                .map(|_| {
+                    scope.spawn(|| {
+                        let mut downloads = Vec::new();
+                        loop {
+                            let index = next.fetch_add(1, Ordering::Relaxed);
+                            let Some(package_name) = package_names.get(index) else {
+                                break;
+                            };
+                            downloads.push((index, download_latest(package_name)));
+                        }
+                        downloads
+                    })
+                })
+                .collect();
+            handles
+                .into_iter()
+                .flat_map(|handle| handle.join().expect("download thread panicked"))
+                .collect()
+        });
+        downloads.sort_by_key(|(index, _)| *index);
+        downloads
+            .into_iter()
+            .map(|(_, download)| download)
+            .collect()
+    }
+
+    // Picks the hash to verify the package against, see
+    // `with_accepted_hash_algorithms`. `None` when the unsupported hash policy
+    // says to skip verification.
+    fn verification_hash(
+        &self,
+        package_name: &str,
+        version: &str,
+        reported: &PackageHash,
+    ) -> Result<Option<PackageHash>, Box<dyn std::error::Error + Send + Sync>> {
+        let accepted = |algorithm: &HashAlgorithm| {
+            digest_for(algorithm).is_some() && self.accepted_hash_algorithms.contains(algorithm)
+        };
+        if accepted(&reported.algorithm) {
+            self.cross_check_hash(package_name, version, reported)?;
+            return Ok(Some(reported.clone()));
+        }
+        if let FeedSource::LocalDir(dir) = &self.metadata_source {
+            for algorithm in self.accepted_hash_algorithms.iter().filter(|a| accepted(a)) {
+                if let Some(hash) = feed::package_hash(dir, package_name, version, algorithm)? {
+                    return Ok(Some(hash));
+                }
+            }
+        }
+        let flat_container = self.v3_index().filter(|_| accepted(&HashAlgorithm::SHA512));
+        if let Some(index_url) = flat_container {
+            match self.flat_container_sha512(index_url, package_name, version) {
+                Ok((hash, _)) => {
+                    return Ok(Some(PackageHash {
+                        hash,
+                        algorithm: HashAlgorithm::SHA512,
+                    }))
+                }
+                Err(error) => {
+                    log::debug!("No flat container hash for {package_name} {version}: {error}")
+                }
+            }
+        }
+        self.unsupported_hash(reported.algorithm.as_str())?;
+        Ok(None)
+    }
+
+    fn cross_check_hash(
+        &self,
+        package_name: &str,
+        version: &str,
+        reported: &PackageHash,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        if !self.hash_cross_check || reported.algorithm != HashAlgorithm::SHA512 {
+            return Ok(());
+        }
+        let (metadata_url, index_url) = match &self.metadata_source {
+            FeedSource::V3(index_url) => (
+                self.registration_leaf_url(index_url, package_name, version)?,
+                index_url.as_str(),
+            ),
+            FeedSource::Url(url) if url == NUGET_V2_URL => (
+                format!("{url}/Packages(Id='{package_name}',Version='{version}')"),
+                NUGET_V3_INDEX_URL,
+            ),
+            _ => {
+                log::debug!("No flat container to cross-check {package_name} {version} with");
+                return Ok(());
+            }
+        };
+        let (sidecar_hash, sidecar_url) =
+            match self.flat_container_sha512(index_url, package_name, version) {
+                Ok(sidecar) => sidecar,
+                Err(error) => {
+                    log::debug!(
+                        "Skipping the hash cross-check for {package_name} {version}: {error}"
+                    );
+                    return Ok(());
+                }
+            };
+        if sidecar_hash != reported.hash {
+            return Err(Box::new(NugetError::InconsistentHash {
+                package_name: package_name.to_owned(),
+                version: version.to_owned(),
+                endpoints: vec![
+                    (metadata_url, reported.hash.clone()),
+                    (sidecar_url, sidecar_hash),
+                ],
+            }));
+        }
+        Ok(())
+    }
+
+    // The V3 service index for the metadata source's packages. nuget.org's V2
+    // feed serves the same packages as V3.
+    fn v3_index(&self) -> Option<&str> {
+        match &self.metadata_source {
+            FeedSource::V3(index_url) => Some(index_url),
+            FeedSource::Url(url) if url == NUGET_V2_URL => Some(NUGET_V3_INDEX_URL),
+            _ => None,
+        }
+    }
+
+    // The base64 SHA512 in the package's flat container `.sha512` file, with
+    // the file's URL.
+    fn flat_container_sha512(
+        &self,
+        index_url: &str,
+        package_name: &str,
+        version: &str,
+    ) -> Result<(String, String), Box<dyn std::error::Error + Send + Sync>> {
+        let base_url = self.v3_resource(index_url, "PackageBaseAddress")?;
+        let url = format!(
+            "{}.sha512",
+            flat_container_url(&base_url, package_name, version)
+        );
+        let hash = self.get(&url)?.text()?.trim().to_owned();
+        Ok((hash, url))
+    }
+
+    // Decides what to do with a hash we don't know how to compute: fails
+    // unless the unsupported hash policy says to skip verification.
+    fn unsupported_hash(
+        &self,
+        algorithm: &str,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        match self.unsupported_hash_policy {
+            UnsupportedHashPolicy::Error => Err(Box::new(NugetError::UnsupportedHashAlgorithm(
+                algorithm.to_owned(),
+            ))),
+            UnsupportedHashPolicy::SkipVerification => Ok(()),
+        }
+    }
+
+    fn progress_writer<'a>(
+        &'a self,
+        package_name: &'a str,
+        version: &'a str,
+        total: Option<u64>,
+        writer: &'a mut dyn Write,
+    ) -> ProgressWriter<'a> {
+        ProgressWriter {
+            writer,
+            progress: self.progress.as_deref(),
+            package_name,
+            version,
+            downloaded: 0,
+            total,
+        }
+    }
+
+    fn record_bytes_downloaded(&self, bytes: u64) {
+        if let Some(stats) = &self.stats {
+            stats.add_bytes_downloaded(bytes);
+        }
+    }
+
+    fn check_signature(
+        &self,
+        package_name: &str,
+        version: &str,
+        file: &File,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        if self.require_signature_entry && !has_signature_entry(file)? {
+            return Err(Box::new(NugetError::UnsignedPackage {
+                package_name: package_name.to_owned(),
+                version: version.to_owned(),
+            }));
+        }
+        Ok(())
+    }
+
+    fn check_identity(
+        &self,
+        package_name: &str,
+        version: &str,
+        path: &Path,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        if !self.verify_identity {
+            return Ok(());
+        }
+        let nuspec = parse_nuspec(&feed::read_nuspec(path)?)?;
+        // Ids are case-insensitive and versions compare the way NuGet does,
+        // so `1.0` matches `1.0.0`
+        let same_version = match (
+            nuspec.version.parse::<NugetVersion>(),
+            version.parse::<NugetVersion>(),
+        ) {
+            (Ok(actual), Ok(requested)) => actual == requested,
+            _ => nuspec.version.eq_ignore_ascii_case(version),
+        };
+        if !nuspec.id.eq_ignore_ascii_case(package_name) || !same_version {
+            return Err(Box::new(NugetError::IdentityMismatch {
+                package_name: package_name.to_owned(),
+                version: version.to_owned(),
+                actual_id: nuspec.id,
+                actual_version: nuspec.version,
+            }));
+        }
+        Ok(())
+    }
+
+    /// Brings the cache up to date with the package, without writing anything
+    /// when the bytes the feed serves are already cached. The cached copies
+    /// of other versions are checked too, so a different version string
+    /// resolving to the same bytes only moves the file.
+    pub fn update_package<P: AsRef<Path>>(
+        &self,
+        package_name: &str,
+        version: &str,
+        download_dir: P,
+    ) -> Result<UpdateResult, Box<dyn std::error::Error + Send + Sync>> {
+        let download_dir = download_dir.as_ref();
+        let path = long_path(&self.cache_path(package_name, version, download_dir));
+        let open = |status| -> Result<UpdateResult, Box<dyn std::error::Error + Send + Sync>> {
+            Ok(UpdateResult {
+                file: NugetPackageFile {
+                    stem: get_package_file_stem(package_name, version),
+                    file: File::open(&path)?,
+                },
+                version: version.to_owned(),
+                status,
+            })
+        };
+
+        let metadata = self.get_package_metadata(package_name, version)?;
+        self.check_vulnerabilities(&metadata)?;
+        let reported = match metadata.hash {
+            Some(hash) => hash,
+            None => self.get_package_hash(package_name, version)?,
+        };
+        // Always download when the policy skips verification, there's no way
+        // to tell whether the cached bytes are the same
+        let hash = self.verification_hash(package_name, version, &reported)?;
+        if let Some(hash) = &hash {
+            let expected = base64::decode(&hash.hash)?;
+            let matches = |candidate: &Path| -> std::io::Result<bool> {
+                Ok(hash_file(candidate, &hash.algorithm)?.as_ref() == Some(&expected))
+            };
+            if path.exists() && matches(&path)? {
+                return open(UpdateStatus::Unchanged);
+            }
+            let feed_dir = self.feed_cache_dir(download_dir);
+            for cached_version in feed::list_versions(&feed_dir, package_name)? {
+                let candidate =
+                    long_path(&self.cache_path(package_name, &cached_version, download_dir));
+                if cached_version != version && candidate.is_file() && matches(&candidate)? {
+                    if let Some(parent) = path.parent() {
+                        std::fs::create_dir_all(parent)?;
+                    }
+                    std::fs::rename(&candidate, &path)?;
+                    return open(UpdateStatus::Moved {
+                        from_version: cached_version,
+                    });
+                }
+            }
+        }
+
+        let (file, _) = self.retry_on_hash_mismatch(package_name, version, || {
+            self.download_package_overwrite_timed(
+                package_name,
+                version,
+                download_dir,
+                hash.as_ref(),
+                false,
+                &mut PackageTimings::default(),
+            )
+        })?;
+        Ok(UpdateResult {
+            file,
+            version: version.to_owned(),
+            status: UpdateStatus::Downloaded,
+        })
+    }
+
+    /// `update_package` for the newest listed version of the package.
+    pub fn update_latest_package<P: AsRef<Path>>(
+        &self,
+        package_name: &str,
+        download_dir: P,
+        include_prerelease: bool,
+    ) -> Result<UpdateResult, Box<dyn std::error::Error + Send + Sync>> {
+        let version = self.resolve_latest_version(package_name, include_prerelease)?;
+        self.update_package(package_name, &version, download_dir)
+    }
+
+    pub(crate) fn package_matches_hash<P: AsRef<Path>>(
+        &self,
+        package_name: &str,
+        version: &str,
+        package_file: P,
+    ) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+        self.package_matches_hash_timed(
+            package_name,
+            version,
+            package_file.as_ref(),
+            &mut PackageTimings::default(),
+        )
+    }
+
+    /// Whether the file matches `hash`, without contacting the feed.
+    pub(crate) fn file_matches_hash(
+        &self,
+        package_file: &Path,
+        hash: &PackageHash,
+    ) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+        let expected = ExpectedPackage {
+            size: None,
+            hash: Some(hash.clone()),
+        };
+        self.file_matches(&expected, package_file, &mut PackageTimings::default())
+    }
+
+    fn package_matches_hash_timed(
+        &self,
+        package_name: &str,
+        version: &str,
+        package_file: &Path,
+        timings: &mut PackageTimings,
+    ) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+        let expected = self.expected_package(package_name, version, timings)?;
+        self.file_matches(&expected, package_file, timings)
+    }
+
+    fn expected_package(
+        &self,
+        package_name: &str,
+        version: &str,
+        timings: &mut PackageTimings,
+    ) -> Result<ExpectedPackage, Box<dyn std::error::Error + Send + Sync>> {
+        let start = Instant::now();
+        let unverified = |error: Box<dyn std::error::Error + Send + Sync>| {
+            let deadline = matches!(error.downcast_ref(), Some(NugetError::DeadlineExceeded));
+            if !self.allow_unverified_on_metadata_failure || deadline {
+                return Err(error);
+            }
+            log::warn!(
+                "Not verifying {package_name} {version}, its metadata is unavailable: {error}"
+            );
+            Ok(ExpectedPackage {
+                size: None,
+                hash: None,
+            })
+        };
+        let metadata = match self.get_package_metadata(package_name, version) {
+            Ok(metadata) => metadata,
+            Err(error) => return unverified(error),
+        };
+        self.check_vulnerabilities(&metadata)?;
+        // Get the hash from the feed
+        let hash = match metadata.hash {
+            Some(hash) => hash,
+            None => match self.get_package_hash(package_name, version) {
+                Ok(hash) => hash,
+                Err(error) => return unverified(error),
+            },
+        };
+        let hash = self.verification_hash(package_name, version, &hash)?;
+        timings.metadata += start.elapsed();
+
+        Ok(ExpectedPackage {
+            size: metadata.size,
+            hash,
+        })
+    }
+
+    fn file_matches(
+        &self,
+        expected: &ExpectedPackage,
+        package_file: &Path,
+        timings: &mut PackageTimings,
+    ) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+        // A file of the wrong size can't match, so skip reading and hashing it.
+        // This is as early as a mismatch can be caught: a cryptographic hash
+        // says nothing until the last byte is read, so there's no prefix of
+        // it to compare partway through the file.
+        if let Some(size) = expected.size {
+            if std::fs::metadata(package_file)?.len() != size {
+                return Ok(false);
+            }
+        }
+        let hash = match &expected.hash {
+            Some(hash) => hash,
+            None => return Ok(true),
+        };
+        if self.trust_unchanged_files && read_stamp(package_file).as_ref() == Some(hash) {
+            return Ok(true);
+        }
+        let reference_hash = base64::decode(&hash.hash)?;
+
+        // Hash the existing file
+        let start = Instant::now();
+        let file_hash = hash_file(package_file, &hash.algorithm)?;
+        timings.hashing += start.elapsed();
+        let matches = file_hash.is_some_and(|file_hash| file_hash == reference_hash);
+        if matches && self.trust_unchanged_files {
+            if let Err(error) = write_stamp(package_file, hash) {
+                log::debug!(
+                    "Not recording {} as verified: {error}",
+                    package_file.display()
+                );
+            }
+        }
+        Ok(matches)
+    }
+
+    pub fn get_package_hash(
+        &self,
+        package_name: &str,
+        version: &str,
+    ) -> Result<PackageHash, Box<dyn std::error::Error + Send + Sync>> {
+        check_package_input(package_name, version)?;
+        let cached = self.hash_cache.get(package_name, version).or_else(|| {
+            self.metadata_cache
+                .get(package_name, version)
+                .and_then(|metadata| metadata.hash)
+        });
+        if let Some(hash) = cached {
+            return Ok(hash);
+        }
+        let hash = self.fetch_package_hash(package_name, version)?;
+        self.hash_cache.insert(package_name, version, hash.clone());
+        Ok(hash)
+    }
+
+    fn fetch_package_hash(
+        &self,
+        package_name: &str,
+        version: &str,
+    ) -> Result<PackageHash, Box<dyn std::error::Error + Send + Sync>> {
+        let base_url = match &self.metadata_source {
+            FeedSource::Url(url) => url,
+            FeedSource::V3(index_url) => {
+                let metadata = self.get_v3_package_metadata(index_url, package_name, version)?;
+                return Ok(metadata.hash.ok_or_else(|| NugetError::HashUnavailable {
+                    package_name: package_name.to_owned(),
+                    version: version.to_owned(),
+                })?);
+            }
+            FeedSource::LocalDir(dir) => {
+                let metadata = feed::package_metadata(dir, package_name, version)?;
+                return Ok(metadata.hash.expect("SHA512 hashes can always be computed"));
+            }
+        };
+        let url = format!("{base_url}/Packages(Id='{package_name}',Version='{version}')");
+        log::debug!("Fetching package hash from {url}");
+        let text = self.get(&url)?.text()?;
+        log::trace!("{text}");
+
+        // Null and empty properties, like `<d:PackageHash m:null="true" />`,
+        // mean the feed has no hash
+        let feed = parse_odata(&text, &self.odata_aliases)?;
+        let properties = feed.package_entry(package_name, version)?;
+        let property = |key: &str| {
+            properties
+                .get(key)
+                .map(|value| value.trim())
+                .filter(|value| !value.is_empty())
+        };
+        match (property("PackageHash"), property("PackageHashAlgorithm")) {
+            (Some(hash), Some(algorithm)) => Ok(PackageHash {
+                hash: hash.to_owned(),
+                algorithm: HashAlgorithm::from_string(algorithm.to_owned()),
+            }),
+            _ => Err(Box::new(NugetError::HashUnavailable {
+                package_name: package_name.to_owned(),
+                version: version.to_owned(),
+            })),
+        }
+    }
+}
+
+// Persists a rename within the directory. Only done on Unix, directories
+// can't be opened this way on Windows.
+fn sync_dir(dir: &Path) -> std::io::Result<()> {
+    #[cfg(unix)]
+    File::open(dir)?.sync_all()?;
+    #[cfg(not(unix))]
+    let _ = dir;
+    Ok(())
+}
+
+fn is_sensitive_header(name: &HeaderName) -> bool {
+    let name = name.as_str();
+    name == "authorization"
+        || name == "proxy-authorization"
+        || name == "cookie"
+        || ["key", "token", "secret", "password"]
+            .iter()
+            .any(|word| name.contains(word))
+}
+
+// What the feed says a package should look like. `hash` is `None` when the
+// unsupported hash policy says to skip verification, or when the metadata is
+// unavailable and `with_allow_unverified_on_metadata_failure` lets the
+// package through. Nothing is checked then, so no sidecar or stamp is written
+// and the package is verified again once the feed has a hash.
+struct ExpectedPackage {
+    size: Option<u64>,
+    hash: Option<PackageHash>,
+}
+
+// A response from the feed, holding the host's connection permit until it's
+// dropped.
+struct FeedResponse<'a> {
+    response: reqwest::blocking::Response,
+    // Only applies to metadata read through `text` and `json`
+    max_size: u64,
+    _permit: Option<HostPermit<'a>>,
+}
+
+impl FeedResponse<'_> {
+    fn text(self) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+        let url = self.response.url().to_string();
+        let too_large = || NugetError::MetadataTooLarge {
+            url: url.clone(),
+            limit: self.max_size,
+        };
+        if self
+            .response
+            .content_length()
+            .is_some_and(|len| len > self.max_size)
+        {
+            return Err(Box::new(too_large()));
+        }
+        let mut bytes = Vec::new();
+        self.response
+            .take(self.max_size.saturating_add(1))
+            .read_to_end(&mut bytes)?;
+        if bytes.len() as u64 > self.max_size {
+            return Err(Box::new(too_large()));
+        }
+        Ok(String::from_utf8(bytes)?)
+    }
+
+    fn json<T: serde::de::DeserializeOwned>(
+        self,
+    ) -> Result<T, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(serde_json::from_str(&self.text()?)?)
+    }
+
+    fn copy_to<W: Write + ?Sized>(&mut self, writer: &mut W) -> reqwest::Result<u64> {
+        self.response.copy_to(writer)
+    }
+
+    fn content_length(&self) -> Option<u64> {
+        self.response.content_length()
+    }
+
+    fn headers(&self) -> &HeaderMap {
+        self.response.headers()
+    }
+
+    // Whether this is the part of a ranged response starting at `start`.
+    fn continues_at(&self, start: u64) -> bool {
+        self.response.status() == reqwest::StatusCode::PARTIAL_CONTENT
+            && self
+                .headers()
+                .get(reqwest::header::CONTENT_RANGE)
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.strip_prefix("bytes "))
+                .and_then(|value| value.split('-').next())
+                .and_then(|first| first.trim().parse::<u64>().ok())
+                == Some(start)
+    }
+}
+
+// Holds each write back until the rate limits allow it.
+struct ThrottledWriter<'a> {
+    writer: &'a mut dyn Write,
+    limiters: [Option<&'a RateLimiter>; 2],
+}
+
+impl Write for ThrottledWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let written = self.writer.write(buf)?;
+        for limiter in self.limiters.into_iter().flatten() {
+            limiter.take(written as u64);
+        }
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+// Reports each write to the client's progress callback, if it has one.
+struct ProgressWriter<'a> {
+    writer: &'a mut dyn Write,
+    progress: Option<&'a (dyn Fn(&DownloadProgress) + Send + Sync)>,
+    package_name: &'a str,
+    version: &'a str,
+    downloaded: u64,
+    total: Option<u64>,
+}
+
+impl Write for ProgressWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let written = self.writer.write(buf)?;
+        self.downloaded += written as u64;
+        if let Some(progress) = self.progress {
+            progress(&DownloadProgress {
+                package_name: self.package_name,
+                version: self.version,
+                downloaded: self.downloaded,
+                total: self.total,
+            });
+        }
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+fn audit_failure(error: Box<dyn std::error::Error + Send + Sync>) -> AuditStatus {
+    let not_found = error
+        .downcast_ref::<reqwest::Error>()
+        .and_then(reqwest::Error::status)
+        == Some(reqwest::StatusCode::NOT_FOUND);
+    match error.downcast_ref::<NugetError>() {
+        Some(NugetError::HashMismatch {
+            expected, actual, ..
+        }) => AuditStatus::HashMismatch {
+            expected: expected.clone(),
+            actual: actual.clone(),
+        },
+        Some(NugetError::NoMatchingVersion { .. } | NugetError::PackageNotFound { .. }) => {
+            AuditStatus::Missing(error.to_string())
+        }
+        _ if not_found => AuditStatus::Missing(error.to_string()),
+        _ => AuditStatus::Failed(error.to_string()),
+    }
+}
+
+// Checked before the package is moved into the cache, so an error page
+// served as a package is never cached
+fn check_package_format(
+    package_name: &str,
+    version: &str,
+    mut file: &File,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let mut start = Vec::with_capacity(64);
+    file.seek(SeekFrom::Start(0))?;
+    file.take(64).read_to_end(&mut start)?;
+    if let Err(NugetError::InvalidPackage(reason)) = detect_package_format(&start) {
+        return Err(Box::new(NugetError::InvalidPackage(format!(
+            "{package_name} {version}: {reason}"
+        ))));
+    }
+    Ok(())
+}
+
+// Hashes everything written through it, when there's a hash to compare
+// against.
+struct HashingWriter<'a, W: Write + ?Sized> {
+    inner: &'a mut W,
+    digest: Option<Box<dyn DynDigest + Send>>,
+}
+
+impl<'a, W: Write + ?Sized> HashingWriter<'a, W> {
+    fn new(inner: &'a mut W, algorithm: Option<&HashAlgorithm>) -> Self {
+        Self {
+            inner,
+            digest: algorithm.and_then(digest_for),
+        }
+    }
+
+    fn finalize(self) -> Option<Vec<u8>> {
+        self.digest.map(|digest| digest.finalize().into_vec())
+    }
+}
+
+impl<W: Write + ?Sized> Write for HashingWriter<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        if let Some(digest) = &mut self.digest {
+            digest.update(&buf[..written]);
+        }
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+// The checksum in a package response's headers, see `with_header_checksums`.
+fn header_checksum(headers: &HeaderMap) -> Option<PackageHash> {
+    [
+        ("x-ms-meta-sha512", HashAlgorithm::SHA512, 64),
+        ("x-ms-meta-sha256", HashAlgorithm::SHA256, 32),
+    ]
+    .into_iter()
+    .find_map(|(header, algorithm, len)| {
+        let value = headers.get(header)?.to_str().ok()?.trim();
+        let bytes = match base64::decode(value) {
+            Ok(bytes) if bytes.len() == len => bytes,
+            _ => decode_hex(value).filter(|bytes| bytes.len() == len)?,
+        };
+        Some(PackageHash {
+            hash: base64::encode(bytes),
+            algorithm,
+        })
+    })
+}
+
+fn decode_hex(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) || !text.is_ascii() {
+        return None;
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|index| u8::from_str_radix(&text[index..index + 2], 16).ok())
+        .collect()
+}
+
+// Where a V3 flat container serves the package. It only knows lowercase ids
+// and normalized, lowercase versions, e.g. `1.0.0` for `1.0`.
+fn flat_container_url(base_url: &str, package_name: &str, version: &str) -> String {
+    let version = match version.parse::<NugetVersion>() {
+        Ok(parsed) => parsed.normalized(),
+        Err(_) => version.to_owned(),
+    };
+    let (id, version) = (package_name.to_lowercase(), version.to_lowercase());
+    format!("{base_url}/{id}/{version}/{id}.{version}.nupkg")
+}
+
+fn basic_auth(credentials: &SourceCredentials) -> HashMap<String, String> {
+    let token = base64::encode(format!("{}:{}", credentials.username, credentials.password));
+    HashMap::from([("Authorization".to_owned(), format!("Basic {token}"))])
+}
+
+fn warn_advisories(metadata: &PackageMetadata) {
+    if let Some(deprecation) = &metadata.deprecation {
+        log::warn!(
+            "{} {} is deprecated ({}){}",
+            metadata.id,
+            metadata.version,
+            deprecation.reasons.join(", "),
+            deprecation
+                .message
+                .as_ref()
+                .map(|message| format!(": {message}"))
+                .unwrap_or_default()
+        );
+    }
+    for vulnerability in &metadata.vulnerabilities {
+        log::warn!(
+            "{} {} has a {:?} severity vulnerability: {}",
+            metadata.id,
+            metadata.version,
+            vulnerability.severity,
+            vulnerability.advisory_url
+        );
+    }
+}
+
+// Whether files can be created in `dir`, creating it if needed. Only
+// permission errors count as unwritable, anything else is returned.
+fn is_writable(dir: &Path) -> std::io::Result<bool> {
+    fn denied(error: &std::io::Error) -> bool {
+        matches!(
+            error.kind(),
+            std::io::ErrorKind::PermissionDenied | std::io::ErrorKind::ReadOnlyFilesystem
+        )
+    }
+    let dir = long_path(dir);
+    match std::fs::create_dir_all(&dir) {
+        Err(error) if denied(&error) => return Ok(false),
+        result => result?,
+    }
+    let probe = dir.join(format!(".write-test-{}", std::process::id()));
+    match File::create(&probe) {
+        Ok(_) => {
+            let _ = std::fs::remove_file(&probe);
+            Ok(true)
+        }
+        Err(error) if denied(&error) => Ok(false),
+        Err(error) => Err(error),
+    }
+}
+
+fn stamp_path(package_path: &Path) -> PathBuf {
+    let mut stamp = package_path.as_os_str().to_owned();
+    stamp.push(".verified");
+    PathBuf::from(stamp)
+}
+
+// The package's size and modification time in nanoseconds, as recorded in
+// its stamp.
+fn file_stamp(package_path: &Path) -> std::io::Result<String> {
+    let metadata = std::fs::metadata(package_path)?;
+    let modified = metadata
+        .modified()?
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default();
+    Ok(format!("{} {}", metadata.len(), modified.as_nanos()))
+}
+
+// Records that the package matched `hash`. The line is the size,
+// modification time, hash algorithm and hash.
+fn write_stamp(package_path: &Path, hash: &PackageHash) -> std::io::Result<()> {
+    let stamp = file_stamp(package_path)?;
+    std::fs::write(
+        stamp_path(package_path),
+        format!("{stamp} {} {}\n", hash.algorithm.as_str(), hash.hash),
+    )
+}
+
+// The hash the package matched, if its stamp is readable and it hasn't
+// changed since.
+fn read_stamp(package_path: &Path) -> Option<PackageHash> {
+    let text = std::fs::read_to_string(stamp_path(package_path)).ok()?;
+    let fields: Vec<&str> = text.split_whitespace().collect();
+    let [len, modified, algorithm, hash] = fields.as_slice() else {
+        return None;
+    };
+    if file_stamp(package_path).ok()? != format!("{len} {modified}") {
+        return None;
+    }
+    Some(PackageHash {
+        hash: hash.to_string(),
+        algorithm: HashAlgorithm::from_string(algorithm.to_string()),
+    })
+}
+
+fn sidecar_path(package_path: &Path) -> PathBuf {
+    let mut sidecar = package_path.as_os_str().to_owned();
+    sidecar.push(".sha512");
+    PathBuf::from(sidecar)
+}
+
+// Writes the package's sidecar when its bytes were verified against
+// `verified`, a SHA512 from the feed or a pin. Otherwise any sidecar can only
+// be stale and is removed, so unverified bytes are never trusted later.
+fn update_sidecar(package_path: &Path, verified: Option<&PackageHash>) -> std::io::Result<()> {
+    let sidecar = sidecar_path(package_path);
+    match verified.filter(|hash| hash.algorithm == HashAlgorithm::SHA512) {
+        Some(hash) => std::fs::write(sidecar, &hash.hash),
+        None => match std::fs::remove_file(sidecar) {
+            Err(error) if error.kind() != std::io::ErrorKind::NotFound => Err(error),
+            _ => Ok(()),
+        },
+    }
+}
+
+// The hash in the package's sidecar, if it has a readable one.
+fn read_sidecar(package_path: &Path) -> Option<PackageHash> {
+    let hash = std::fs::read_to_string(sidecar_path(package_path)).ok()?;
+    Some(PackageHash {
+        hash: hash.trim().to_owned(),
+        algorithm: HashAlgorithm::SHA512,
+    })
+}
@@ -0,0 +1,205 @@
+use std::{
+    collections::HashMap,
+    sync::{Condvar, Mutex},
+    time::{Duration, Instant},
+};
+
+pub(crate) struct Semaphore {
+    state: Mutex<SemaphoreState>,
+    condvar: Condvar,
+}
+
+struct SemaphoreState {
+    in_use: usize,
+    max: usize,
+}
+
+pub(crate) struct SemaphorePermit<'a> {
+    semaphore: &'a Semaphore,
+}
+
+impl Semaphore {
+    pub const fn new(max: usize) -> Self {
+        Self {
+            state: Mutex::new(SemaphoreState { in_use: 0, max }),
+            condvar: Condvar::new(),
+        }
+    }
+
+    pub fn acquire(&self) -> SemaphorePermit<'_> {
+        let mut state = self.state.lock().unwrap();
+        while state.in_use >= state.max {
+            state = self.condvar.wait(state).unwrap();
+        }
+        state.in_use += 1;
+        SemaphorePermit { semaphore: self }
+    }
+
+    pub fn max(&self) -> usize {
+        self.state.lock().unwrap().max
+    }
+
+    pub fn set_max(&self, max: usize) {
+        let mut state = self.state.lock().unwrap();
+        state.max = max.max(1);
+        // A higher limit may let waiters through
+        self.condvar.notify_all();
+    }
+}
+
+impl Drop for SemaphorePermit<'_> {
+    fn drop(&mut self) {
+        let mut state = self.semaphore.state.lock().unwrap();
+        state.in_use -= 1;
+        self.semaphore.condvar.notify_one();
+    }
+}
+
+/// Like `Semaphore`, but with a separate count for each host.
+pub(crate) struct HostLimiter {
+    max: usize,
+    in_use: Mutex<HashMap<String, usize>>,
+    condvar: Condvar,
+}
+
+pub(crate) struct HostPermit<'a> {
+    limiter: &'a HostLimiter,
+    host: String,
+}
+
+impl HostLimiter {
+    pub fn new(max: usize) -> Self {
+        Self {
+            max: max.max(1),
+            in_use: Mutex::new(HashMap::new()),
+            condvar: Condvar::new(),
+        }
+    }
+
+    pub fn acquire(&self, host: &str) -> HostPermit<'_> {
+        let mut in_use = self.in_use.lock().unwrap();
+        while in_use.get(host).copied().unwrap_or(0) >= self.max {
+            in_use = self.condvar.wait(in_use).unwrap();
+        }
+        *in_use.entry(host.to_owned()).or_insert(0) += 1;
+        HostPermit {
+            limiter: self,
+            host: host.to_owned(),
+        }
+    }
+}
+
+impl Drop for HostPermit<'_> {
+    fn drop(&mut self) {
+        let mut in_use = self.limiter.in_use.lock().unwrap();
+        if let Some(count) = in_use.get_mut(&self.host) {
+            *count -= 1;
+            if *count == 0 {
+                in_use.remove(&self.host);
+            }
+        }
+        // Waiters may be waiting on other hosts, so wake them all
+        self.limiter.condvar.notify_all();
+    }
+}
+
+/// A token bucket of bytes, refilled at the rate and holding up to a
+/// second's worth. Takes that overdraw it sleep off the debt, so threads
+/// sharing a limiter share the rate.
+pub(crate) struct RateLimiter {
+    bucket: Mutex<Option<Bucket>>,
+}
+
+struct Bucket {
+    bytes_per_second: u64,
+    available: f64,
+    updated: Instant,
+}
+
+impl RateLimiter {
+    pub const fn unlimited() -> Self {
+        Self {
+            bucket: Mutex::new(None),
+        }
+    }
+
+    pub fn new(bytes_per_second: u64) -> Self {
+        let limiter = Self::unlimited();
+        limiter.set_rate(Some(bytes_per_second));
+        limiter
+    }
+
+    pub fn rate(&self) -> Option<u64> {
+        self.bucket
+            .lock()
+            .unwrap()
+            .as_ref()
+            .map(|bucket| bucket.bytes_per_second)
+    }
+
+    pub fn set_rate(&self, bytes_per_second: Option<u64>) {
+        *self.bucket.lock().unwrap() = bytes_per_second.map(|bytes_per_second| Bucket {
+            bytes_per_second: bytes_per_second.max(1),
+            available: 0.0,
+            updated: Instant::now(),
+        });
+    }
+
+    /// Blocks until `bytes` fit in the rate.
+    pub fn take(&self, bytes: u64) {
+        if let Some(wait) = self.reserve(bytes) {
+            std::thread::sleep(wait);
+        }
+    }
+
+    /// Takes `bytes` from the bucket, returning how long to wait before
+    /// using them if that overdraws it. For callers that can't block.
+    pub fn reserve(&self, bytes: u64) -> Option<Duration> {
+        let mut bucket = self.bucket.lock().unwrap();
+        let bucket = bucket.as_mut()?;
+        let rate = bucket.bytes_per_second as f64;
+        let now = Instant::now();
+        let refill = now.duration_since(bucket.updated).as_secs_f64() * rate;
+        bucket.available = (bucket.available + refill).min(rate) - bytes as f64;
+        bucket.updated = now;
+        if bucket.available >= 0.0 {
+            return None;
+        }
+        Some(Duration::from_secs_f64(-bucket.available / rate))
+    }
+}
+
+static DOWNLOADS: Semaphore = Semaphore::new(4);
+static DOWNLOAD_RATE: RateLimiter = RateLimiter::unlimited();
+
+pub(crate) fn download_rate_limiter() -> &'static RateLimiter {
+    &DOWNLOAD_RATE
+}
+
+pub(crate) fn acquire_download_permit() -> SemaphorePermit<'static> {
+    DOWNLOADS.acquire()
+}
+
+/// Caps how many package downloads may run at once across the whole process,
+/// regardless of how many clients, batches or `nuget_packages!` invocations
+/// are active. Defaults to 4. Separate processes (e.g. several build scripts)
+/// each have their own limit.
+pub fn set_max_concurrent_downloads(max: usize) {
+    DOWNLOADS.set_max(max);
+}
+
+pub fn max_concurrent_downloads() -> usize {
+    DOWNLOADS.max()
+}
+
+/// Caps the combined download rate, in bytes per second, of every package
+/// download in the process, on top of any client's
+/// `NugetClient::with_download_rate_limit`. `None`, the default, removes the
+/// cap. Local feeds aren't throttled.
+pub fn set_max_download_rate(bytes_per_second: Option<u64>) {
+    DOWNLOAD_RATE.set_rate(bytes_per_second);
+}
+
+pub fn max_download_rate() -> Option<u64> {
+    DOWNLOAD_RATE.rate()
+}
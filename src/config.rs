@@ -0,0 +1,618 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    path::{Path, PathBuf},
+};
+
+use serde::Deserialize;
+
+#[cfg(feature = "blocking")]
+use crate::NugetClient;
+use crate::{
+    async_client::AsyncClient, packages_subdir, target::target_matches, HashAlgorithm,
+    LockedPackage, Lockfile, NugetError, PackageHash, PackagePolicy, VersionRange,
+};
+
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) struct NugetConfig {
+    /// Other configs to merge in, relative to this one. Later includes
+    /// override earlier ones, and this config overrides all of them.
+    #[serde(default)]
+    pub include: Vec<PathBuf>,
+    pub packages_dir: Option<PathBuf>,
+    #[serde(default)]
+    pub vars: HashMap<String, String>,
+    /// Picks the dependency groups used when `include-dependencies` is set.
+    pub target_framework: Option<String>,
+    pub include_dependencies: Option<bool>,
+    /// Where `install_config` writes a CycloneDX SBOM of what it installed.
+    pub sbom: Option<PathBuf>,
+    /// Whether a package resolving to a lower version than the lockfile has
+    /// is expected. Otherwise it's warned about.
+    pub allow_downgrade: Option<bool>,
+    /// Which packages the config may download, see `PackagePolicy`.
+    pub policy: Option<PackagePolicy>,
+    /// What to do about a package listed more than once with the same
+    /// requirement, e.g. under names differing only in case, or both here
+    /// and in an include.
+    pub duplicates: Option<DuplicatePolicy>,
+    /// The duplicates found while loading, only the first of each is kept.
+    #[serde(skip)]
+    pub duplicate_entries: Vec<String>,
+    // Sorted so resolution errors and downloads happen in a stable order
+    #[serde(default)]
+    pub dependencies: BTreeMap<String, NugetPackageRef>,
+    /// Dependencies only needed on some platforms, keyed like Cargo's
+    /// `[target.'cfg(windows)'.dependencies]`. Sections that apply are merged
+    /// into `dependencies` when the config is loaded.
+    #[serde(default)]
+    pub target: BTreeMap<String, TargetConfig>,
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum DuplicatePolicy {
+    Dedupe,
+    /// Dedupe, logging a warning for each duplicate.
+    #[default]
+    Warn,
+    /// Fail to load the config.
+    Error,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct TargetConfig {
+    #[serde(default)]
+    pub dependencies: BTreeMap<String, NugetPackageRef>,
+}
+
+#[derive(Deserialize, PartialEq)]
+#[serde(untagged)]
+pub(crate) enum NugetPackageRef {
+    Version(String),
+    Detailed(DetailedPackageRef),
+}
+
+#[derive(Deserialize, PartialEq)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub(crate) struct DetailedPackageRef {
+    pub version: Option<String>,
+    pub version_from_env: Option<String>,
+    pub version_var: Option<String>,
+    /// A base64 hash to verify the package against instead of the feed's.
+    pub sha512: Option<String>,
+    pub sha256: Option<String>,
+    /// Files to extract once the package is downloaded.
+    #[serde(default)]
+    pub extract: Vec<ExtractRule>,
+}
+
+/// Extracts the entries matching the `from` glob, e.g.
+/// `runtimes/win-x64/native/*.dll`, into the `to` directory, relative to the
+/// config's directory. Only the file names are kept.
+#[derive(Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct ExtractRule {
+    pub from: String,
+    pub to: PathBuf,
+}
+
+/// The format a config is written in, picked by its extension. Anything that
+/// isn't `.json`, `.yaml` or `.yml` is TOML.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum ConfigFormat {
+    Toml,
+    Json,
+    Yaml,
+}
+
+impl ConfigFormat {
+    pub fn from_path(config_path: &Path) -> Result<Self, NugetError> {
+        let extension = config_path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .unwrap_or_default()
+            .to_lowercase();
+        let (format, enabled, feature) = match extension.as_str() {
+            "json" => (Self::Json, cfg!(feature = "json-config"), "json-config"),
+            "yaml" | "yml" => (Self::Yaml, cfg!(feature = "yaml-config"), "yaml-config"),
+            _ => return Ok(Self::Toml),
+        };
+        if !enabled {
+            return Err(NugetError::InvalidConfig(format!(
+                "{} needs the `{feature}` feature",
+                config_path.display()
+            )));
+        }
+        Ok(format)
+    }
+
+    pub fn parse<T: serde::de::DeserializeOwned>(
+        self,
+        text: &str,
+    ) -> Result<T, Box<dyn std::error::Error + Send + Sync>> {
+        match self {
+            Self::Toml => Ok(toml::from_str(text)?),
+            #[cfg(feature = "json-config")]
+            Self::Json => Ok(serde_json::from_str(text)?),
+            #[cfg(feature = "yaml-config")]
+            Self::Yaml => Ok(serde_yaml::from_str(text)?),
+            // `from_path` only picks formats whose feature is enabled
+            #[allow(unreachable_patterns)]
+            _ => unreachable!(),
+        }
+    }
+
+    /// Re-emits the config in the same format with every table's keys
+    /// sorted. Comments and formatting aren't kept.
+    pub fn canonicalize(
+        self,
+        text: &str,
+    ) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+        match self {
+            Self::Toml => {
+                let value = self.parse::<toml::value::Table>(text)?;
+                let mut document = toml_edit::DocumentMut::new();
+                *document.as_table_mut() = canonical_table(&value, false);
+                Ok(document.to_string())
+            }
+            #[cfg(feature = "json-config")]
+            Self::Json => {
+                let value = self.parse::<serde_json::Value>(text)?;
+                Ok(format!("{}\n", serde_json::to_string_pretty(&value)?))
+            }
+            // Parsed as JSON values, since YAML mappings keep their order
+            #[cfg(feature = "yaml-config")]
+            Self::Yaml => Ok(serde_yaml::to_string(
+                &self.parse::<serde_json::Value>(text)?,
+            )?),
+            #[allow(unreachable_patterns)]
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl NugetConfig {
+    pub fn from_path<P: AsRef<Path>>(
+        config_path: P,
+    ) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let mut config = Self::from_path_including(config_path.as_ref(), &mut Vec::new())?;
+        config.check_inputs()?;
+        for (spec, target) in std::mem::take(&mut config.target) {
+            if !target_matches(&spec)? {
+                log::debug!("Skipping the dependencies for {spec}");
+                continue;
+            }
+            let duplicates = merge_dependencies(&mut config.dependencies, target.dependencies);
+            config.duplicate_entries.extend(duplicates);
+        }
+        config.check_duplicates(config_path.as_ref())?;
+        Ok(config)
+    }
+
+    fn check_duplicates(&self, config_path: &Path) -> Result<(), NugetError> {
+        if self.duplicate_entries.is_empty() {
+            return Ok(());
+        }
+        match self.duplicates.unwrap_or_default() {
+            DuplicatePolicy::Dedupe => {}
+            DuplicatePolicy::Warn => {
+                for name in &self.duplicate_entries {
+                    log::warn!("{name} is listed more than once, only downloading it once");
+                }
+            }
+            DuplicatePolicy::Error => {
+                return Err(NugetError::InvalidConfig(format!(
+                    "{}: listed more than once: {}",
+                    config_path.display(),
+                    self.duplicate_entries.join(", ")
+                )))
+            }
+        }
+        Ok(())
+    }
+
+    // `stack` holds the configs currently being loaded, to detect cycles.
+    fn from_path_including(
+        config_path: &Path,
+        stack: &mut Vec<PathBuf>,
+    ) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let canonical = std::fs::canonicalize(config_path)?;
+        if stack.contains(&canonical) {
+            let cycle: Vec<String> = stack
+                .iter()
+                .chain(std::iter::once(&canonical))
+                .map(|path| path.display().to_string())
+                .collect();
+            return Err(Box::new(NugetError::InvalidConfig(format!(
+                "include cycle: {}",
+                cycle.join(" -> ")
+            ))));
+        }
+
+        let config_text = std::fs::read_to_string(config_path)?;
+        let mut config: Self = ConfigFormat::from_path(config_path)?.parse(&config_text)?;
+        let mut duplicates = dedupe_dependencies(&mut config.dependencies);
+        for target in config.target.values_mut() {
+            duplicates.extend(dedupe_dependencies(&mut target.dependencies));
+        }
+        config.duplicate_entries = duplicates;
+        if config.include.is_empty() {
+            return Ok(config);
+        }
+
+        stack.push(canonical);
+        let base_dir = config_path.parent().unwrap_or(Path::new(""));
+        let mut merged: Option<Self> = None;
+        for include in std::mem::take(&mut config.include) {
+            let included = Self::from_path_including(&base_dir.join(include), stack)?;
+            merged = Some(match merged {
+                Some(base) => included.merge_over(base),
+                None => included,
+            });
+        }
+        stack.pop();
+
+        Ok(match merged {
+            Some(base) => config.merge_over(base),
+            None => config,
+        })
+    }
+
+    // Blank names and versions would otherwise only fail once they reach the
+    // feed. Versions from the environment or `[vars]` are checked as they're
+    // resolved.
+    fn check_inputs(&self) -> Result<(), NugetError> {
+        let targets = self.target.values().flat_map(|target| &target.dependencies);
+        for (name, package_ref) in self.dependencies.iter().chain(targets) {
+            if name.trim().is_empty() {
+                return Err(NugetError::InvalidInput {
+                    field: "a dependency name".to_owned(),
+                });
+            }
+            let version = match package_ref {
+                NugetPackageRef::Version(version) => Some(version),
+                NugetPackageRef::Detailed(detailed) => detailed.version.as_ref(),
+            };
+            if version.is_some_and(|version| version.trim().is_empty()) {
+                return Err(blank_version(name));
+            }
+        }
+        Ok(())
+    }
+
+    /// Why each dependency in the config fails to parse, for configs
+    /// `from_path` rejects. Only the config itself is checked, not its
+    /// includes.
+    pub fn dependency_errors(config_path: &Path) -> Vec<(String, String)> {
+        let Ok(config_text) = std::fs::read_to_string(config_path) else {
+            return Vec::new();
+        };
+        let Ok(format) = ConfigFormat::from_path(config_path) else {
+            return Vec::new();
+        };
+        let Ok(value) = format.parse::<toml::Value>(&config_text) else {
+            return Vec::new();
+        };
+        let Some(dependencies) = value.get("dependencies").and_then(toml::Value::as_table) else {
+            return Vec::new();
+        };
+        dependencies
+            .iter()
+            .filter_map(|(name, package_ref)| {
+                // The untagged enum's own error doesn't say what's wrong
+                let error = match package_ref {
+                    toml::Value::String(_) => return None,
+                    toml::Value::Table(_) => DetailedPackageRef::deserialize(package_ref.clone())
+                        .err()?
+                        .to_string(),
+                    _ => "expected a version string or a table".to_owned(),
+                };
+                Some((name.clone(), error))
+            })
+            .collect()
+    }
+
+    // Settings in `self` win over those in `base`, dependencies and vars are
+    // merged key by key.
+    fn merge_over(self, mut base: Self) -> Self {
+        base.vars.extend(self.vars);
+        base.duplicate_entries.extend(self.duplicate_entries);
+        let duplicates = merge_dependencies(&mut base.dependencies, self.dependencies);
+        base.duplicate_entries.extend(duplicates);
+        for (spec, target) in self.target {
+            let base_target = base.target.entry(spec).or_insert_with(|| TargetConfig {
+                dependencies: BTreeMap::new(),
+            });
+            let duplicates = merge_dependencies(&mut base_target.dependencies, target.dependencies);
+            base.duplicate_entries.extend(duplicates);
+        }
+        Self {
+            include: Vec::new(),
+            packages_dir: self.packages_dir.or(base.packages_dir),
+            vars: base.vars,
+            target_framework: self.target_framework.or(base.target_framework),
+            include_dependencies: self.include_dependencies.or(base.include_dependencies),
+            sbom: self.sbom.or(base.sbom),
+            allow_downgrade: self.allow_downgrade.or(base.allow_downgrade),
+            policy: self.policy.or(base.policy),
+            duplicates: self.duplicates.or(base.duplicates),
+            duplicate_entries: base.duplicate_entries,
+            dependencies: base.dependencies,
+            target: base.target,
+        }
+    }
+
+    /// Where packages go. Without an explicit packages-dir, packages are kept
+    /// in `packages_subdir()` next to the config file.
+    pub fn packages_dir(&self, config_path: &Path) -> PathBuf {
+        if let Some(packages_dir) = &self.packages_dir {
+            packages_dir.clone()
+        } else {
+            let mut packages_dir = config_path.parent().unwrap_or(Path::new("")).to_owned();
+            packages_dir.push(packages_subdir());
+            packages_dir
+        }
+    }
+
+    /// Every package the config asks for, including transitive dependencies
+    /// when `include-dependencies` is set. Floating versions like `1.2.*`
+    /// resolve to the highest matching stable version, unless the lockfile
+    /// already has a version for the same requirement.
+    pub async fn resolve_packages(
+        &self,
+        client: &AsyncClient,
+        lockfile: Option<&Lockfile>,
+    ) -> Result<Vec<LockedPackage>, Box<dyn std::error::Error + Send + Sync>> {
+        // Resolve every version up front so a bad reference fails before any download
+        let mut packages = Vec::new();
+        for (name, package_ref) in &self.dependencies {
+            let requirement = self.resolve_version(name, package_ref)?;
+            let locked = lockfile.and_then(|lockfile| lockfile.locked_package(name, &requirement));
+            let version = match locked {
+                Some(locked) => locked.version.clone(),
+                None if is_floating(&requirement) => {
+                    let range: VersionRange = requirement.parse()?;
+                    client.resolve_version_range(name, &range).await?
+                }
+                None => requirement.clone(),
+            };
+            packages.push(LockedPackage {
+                name: name.clone(),
+                version,
+                requirement: Some(requirement),
+                // Kept so `refresh_config` can tell whether anything newer was published
+                published: locked.and_then(|locked| locked.published.clone()),
+            });
+        }
+        // Ids are case-insensitive, so sort them that way too. This keeps the
+        // order, and so the lockfile, stable between runs.
+        packages.sort_by_key(|package| package.name.to_lowercase());
+
+        if self.include_dependencies.unwrap_or(false) {
+            // Packages listed in the config take precedence over transitive ones
+            let mut seen: HashSet<String> = packages
+                .iter()
+                .map(|package| package.name.to_lowercase())
+                .collect();
+            let mut transitive = Vec::new();
+            for package in &packages {
+                let resolved = client
+                    .resolve_dependencies(
+                        &package.name,
+                        &package.version,
+                        self.target_framework.as_deref(),
+                    )
+                    .await?;
+                for (id, version) in resolved {
+                    if seen.insert(id.to_lowercase()) {
+                        transitive.push(LockedPackage {
+                            name: id,
+                            version,
+                            requirement: None,
+                            published: None,
+                        });
+                    }
+                }
+            }
+            packages.extend(transitive);
+        }
+        Ok(packages)
+    }
+
+    fn detailed_ref(&self, package_name: &str) -> Option<&DetailedPackageRef> {
+        self.dependencies
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(package_name))
+            .and_then(|(_, package_ref)| match package_ref {
+                NugetPackageRef::Version(_) => None,
+                NugetPackageRef::Detailed(detailed) => Some(detailed),
+            })
+    }
+
+    /// The `extract` rules for a package listed in the config.
+    pub fn extract_rules(&self, package_name: &str) -> &[ExtractRule] {
+        self.detailed_ref(package_name)
+            .map(|detailed| detailed.extract.as_slice())
+            .unwrap_or_default()
+    }
+
+    /// The hash pinned for a package listed in the config, if any.
+    pub fn pinned_hash(&self, package_name: &str) -> Result<Option<PackageHash>, NugetError> {
+        let Some(detailed) = self.detailed_ref(package_name) else {
+            return Ok(None);
+        };
+        let (hash, algorithm) = match (&detailed.sha512, &detailed.sha256) {
+            (Some(hash), None) => (hash, HashAlgorithm::SHA512),
+            (None, Some(hash)) => (hash, HashAlgorithm::SHA256),
+            (None, None) => return Ok(None),
+            _ => {
+                return Err(NugetError::InvalidConfig(format!(
+                    "{package_name}: only one of sha512 or sha256 may be set"
+                )))
+            }
+        };
+        if base64::decode(hash).is_err() {
+            return Err(NugetError::InvalidConfig(format!(
+                "{package_name}: {} is not valid base64",
+                algorithm.as_str().to_lowercase()
+            )));
+        }
+        Ok(Some(PackageHash {
+            hash: hash.clone(),
+            algorithm,
+        }))
+    }
+
+    pub fn resolve_version(
+        &self,
+        package_name: &str,
+        package_ref: &NugetPackageRef,
+    ) -> Result<String, NugetError> {
+        let detailed = match package_ref {
+            NugetPackageRef::Version(version) => return Ok(version.clone()),
+            NugetPackageRef::Detailed(detailed) => detailed,
+        };
+        let invalid = |message: String| NugetError::InvalidConfig(message);
+        let version = match (
+            &detailed.version,
+            &detailed.version_from_env,
+            &detailed.version_var,
+        ) {
+            (Some(version), None, None) => Ok(version.clone()),
+            (None, Some(env_var), None) => std::env::var(env_var).map_err(|_| {
+                invalid(format!(
+                    "{package_name}: environment variable \"{env_var}\" is not set"
+                ))
+            }),
+            (None, None, Some(var)) => self.vars.get(var).cloned().ok_or_else(|| {
+                invalid(format!(
+                    "{package_name}: \"{var}\" is not defined in the [vars] table"
+                ))
+            }),
+            (None, None, None) => Err(invalid(format!(
+                "{package_name}: one of version, version-from-env or version-var is required"
+            ))),
+            _ => Err(invalid(format!(
+                "{package_name}: only one of version, version-from-env or version-var may be set"
+            ))),
+        }?;
+        if version.trim().is_empty() {
+            return Err(blank_version(package_name));
+        }
+        Ok(version)
+    }
+}
+
+fn blank_version(package_name: &str) -> NugetError {
+    NugetError::InvalidInput {
+        field: format!("the version of {package_name}"),
+    }
+}
+
+// Tables are sorted maps already. Dependency tables keep each dependency on
+// one line, as an inline table when it's detailed.
+fn canonical_table(table: &toml::value::Table, inline_values: bool) -> toml_edit::Table {
+    let mut canonical = toml_edit::Table::new();
+    canonical.set_implicit(true);
+    for (key, value) in table {
+        let item = match value {
+            toml::Value::Table(child) if !inline_values => {
+                toml_edit::Item::Table(canonical_table(child, key == "dependencies"))
+            }
+            value => toml_edit::Item::Value(canonical_value(value)),
+        };
+        canonical.insert(key, item);
+    }
+    canonical
+}
+
+fn canonical_value(value: &toml::Value) -> toml_edit::Value {
+    match value {
+        toml::Value::String(value) => value.as_str().into(),
+        toml::Value::Integer(value) => (*value).into(),
+        toml::Value::Float(value) => (*value).into(),
+        toml::Value::Boolean(value) => (*value).into(),
+        toml::Value::Datetime(value) => value
+            .to_string()
+            .parse::<toml_edit::Datetime>()
+            .map(Into::into)
+            .unwrap_or_else(|_| value.to_string().into()),
+        toml::Value::Array(values) => values
+            .iter()
+            .map(canonical_value)
+            .collect::<toml_edit::Array>()
+            .into(),
+        toml::Value::Table(table) => table
+            .iter()
+            .map(|(key, value)| (key.clone(), canonical_value(value)))
+            .collect::<toml_edit::InlineTable>()
+            .into(),
+    }
+}
+
+// Ids are case-insensitive, so `Foo` overrides an existing `foo`
+// Returns the overrides that repeat what they override.
+fn merge_dependencies(
+    dependencies: &mut BTreeMap<String, NugetPackageRef>,
+    overrides: BTreeMap<String, NugetPackageRef>,
+) -> Vec<String> {
+    let mut duplicates = Vec::new();
+    for (name, package_ref) in overrides {
+        let mut repeated = false;
+        dependencies.retain(|existing, existing_ref| {
+            let same = existing.eq_ignore_ascii_case(&name);
+            repeated |= same && *existing_ref == package_ref;
+            !same
+        });
+        if repeated {
+            duplicates.push(name.clone());
+        }
+        dependencies.insert(name, package_ref);
+    }
+    duplicates
+}
+
+// Names are case-insensitive, but the keys only clash if they're identical.
+// Removes the entries repeating an earlier one's requirement under another
+// case, returning their names. Entries with different requirements are left
+// for resolution to deal with.
+fn dedupe_dependencies(dependencies: &mut BTreeMap<String, NugetPackageRef>) -> Vec<String> {
+    let names: Vec<String> = dependencies.keys().cloned().collect();
+    let mut duplicates = Vec::new();
+    for (index, name) in names.iter().enumerate() {
+        let repeats = names[..index].iter().any(|earlier| {
+            earlier.eq_ignore_ascii_case(name)
+                && dependencies.contains_key(earlier)
+                && dependencies.get(earlier) == dependencies.get(name)
+        });
+        if repeats {
+            dependencies.remove(name);
+            duplicates.push(name.clone());
+        }
+    }
+    duplicates
+}
+
+/// Whether a requirement from the config resolves to the newest matching
+/// version, like `1.2.*` or `>=1.2.0`, rather than naming one.
+pub(crate) fn is_floating(requirement: &str) -> bool {
+    let requirement = requirement.trim();
+    requirement.ends_with('*') || requirement.starts_with(">=")
+}
+
+/// The exact version for a requirement from the config, resolving floating
+/// versions like `1.2.*` against the feed.
+#[cfg(feature = "blocking")]
+pub(crate) fn resolve_requirement(
+    client: &NugetClient,
+    package_name: &str,
+    requirement: &str,
+) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+    if is_floating(requirement) {
+        let range: VersionRange = requirement.parse()?;
+        client.resolve_version_range(package_name, &range)
+    } else {
+        Ok(requirement.to_owned())
+    }
+}
@@ -0,0 +1,208 @@
+use std::{
+    fs::File,
+    io::{Read, Write},
+    path::{Component, Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    hash::{digest_for, hash_buffer_size},
+    ExtractOptions, HashAlgorithm, NugetError, NugetPackageFile,
+};
+
+/// Extracted package files stored by content, so a file shared by many
+/// packages or versions is only kept once. Files live under `objects/`, named
+/// by their SHA256. This is separate from the package cache `download_package`
+/// uses.
+#[derive(Clone, Debug)]
+pub struct ContentStore {
+    dir: PathBuf,
+}
+
+/// Maps a package's file paths to their contents in a `ContentStore`.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ContentManifest {
+    #[serde(default, rename = "file")]
+    pub files: Vec<ContentEntry>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ContentEntry {
+    /// The path inside the package, e.g. `lib/net6.0/Foo.dll`.
+    pub path: String,
+    /// The lowercase hex SHA256 of the file.
+    pub sha256: String,
+    pub size: u64,
+}
+
+impl ContentManifest {
+    pub fn from_path<P: AsRef<Path>>(
+        path: P,
+    ) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let text = std::fs::read_to_string(path)?;
+        Ok(toml::from_str(&text)?)
+    }
+
+    pub fn write<P: AsRef<Path>>(
+        &self,
+        path: P,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        std::fs::write(path, toml::to_string(self)?)?;
+        Ok(())
+    }
+}
+
+// Tells apart temporary files written by concurrent stores in this process
+static NEXT_TEMP_ID: AtomicUsize = AtomicUsize::new(0);
+
+impl ContentStore {
+    pub fn new<P: Into<PathBuf>>(dir: P) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// Where the file with the given SHA256 is stored, e.g.
+    /// `objects/ab/abcd...`.
+    pub fn object_path(&self, sha256: &str) -> PathBuf {
+        let mut path = self.dir.join("objects");
+        path.push(sha256.get(..2).unwrap_or(sha256));
+        path.push(sha256);
+        path
+    }
+
+    /// Stores every file in the package that isn't stored already, returning
+    /// the package's manifest. Extraction limits apply as they do for
+    /// `extract_package`.
+    pub fn store_package(
+        &self,
+        file: &NugetPackageFile,
+        options: &ExtractOptions,
+    ) -> Result<ContentManifest, Box<dyn std::error::Error + Send + Sync>> {
+        let objects_dir = self.dir.join("objects");
+        std::fs::create_dir_all(&objects_dir)?;
+
+        let mut archive = zip::ZipArchive::new(&file.file)?;
+        let mut total_size = 0u64;
+        let mut manifest = ContentManifest::default();
+        for index in 0..archive.len() {
+            let mut entry = archive.by_index(index)?;
+            if entry.enclosed_name().is_none() {
+                return Err(format!("{} has an unsafe path", entry.name()).into());
+            }
+            if entry.is_dir() {
+                continue;
+            }
+
+            let entry_name = entry.name().to_owned();
+            let limit_exceeded = |limit| NugetError::ExtractionLimitExceeded {
+                entry: entry_name.clone(),
+                limit,
+            };
+            if entry.size() > options.max_entry_size {
+                return Err(Box::new(limit_exceeded(options.max_entry_size)));
+            }
+            let remaining = options.max_total_size.saturating_sub(total_size);
+            let limit = options.max_entry_size.min(remaining);
+
+            let temp_path = objects_dir.join(format!(
+                "{}.store-{}",
+                NEXT_TEMP_ID.fetch_add(1, Ordering::Relaxed),
+                std::process::id()
+            ));
+            let stored = store_object(&mut (&mut entry).take(limit.saturating_add(1)), &temp_path);
+            let (sha256, size) = match stored {
+                Ok((_, size)) if size > limit => {
+                    let _ = std::fs::remove_file(&temp_path);
+                    let limit = if size > options.max_entry_size {
+                        options.max_entry_size
+                    } else {
+                        options.max_total_size
+                    };
+                    return Err(Box::new(limit_exceeded(limit)));
+                }
+                Ok(stored) => stored,
+                Err(error) => {
+                    let _ = std::fs::remove_file(&temp_path);
+                    return Err(error.into());
+                }
+            };
+
+            let object_path = self.object_path(&sha256);
+            if object_path.exists() {
+                std::fs::remove_file(&temp_path)?;
+            } else {
+                if let Some(parent) = object_path.parent() {
+                    std::fs::create_dir_all(parent)?;
+                }
+                std::fs::rename(&temp_path, &object_path)?;
+            }
+            total_size += size;
+            manifest.files.push(ContentEntry {
+                path: entry_name,
+                sha256,
+                size,
+            });
+        }
+        Ok(manifest)
+    }
+
+    /// Rebuilds the package's file tree in `dest_dir` from the store,
+    /// returning the paths of the files written.
+    pub fn materialize<P: AsRef<Path>>(
+        &self,
+        manifest: &ContentManifest,
+        dest_dir: P,
+    ) -> Result<Vec<PathBuf>, Box<dyn std::error::Error + Send + Sync>> {
+        let dest_dir = dest_dir.as_ref();
+        let mut written = Vec::with_capacity(manifest.files.len());
+        for entry in &manifest.files {
+            // Manifests may come from elsewhere, keep their paths inside dest_dir
+            let relative_path = Path::new(&entry.path);
+            if !relative_path
+                .components()
+                .all(|component| matches!(component, Component::Normal(_)))
+            {
+                return Err(format!("{} is an unsafe path", entry.path).into());
+            }
+            let object_path = self.object_path(&entry.sha256);
+            if !object_path.is_file() {
+                return Err(
+                    format!("{} ({}) is not in the store", entry.path, entry.sha256).into(),
+                );
+            }
+            let path = dest_dir.join(relative_path);
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            std::fs::copy(&object_path, &path)?;
+            written.push(path);
+        }
+        Ok(written)
+    }
+}
+
+// Copies the data into `path`, returning its hex SHA256 and size.
+fn store_object<R: Read>(reader: &mut R, path: &Path) -> std::io::Result<(String, u64)> {
+    let mut digest = digest_for(&HashAlgorithm::SHA256).expect("SHA256 can always be computed");
+    let mut file = File::create(path)?;
+    let mut buffer = vec![0; hash_buffer_size()];
+    let mut size = 0u64;
+    loop {
+        let read = match reader.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(error) if error.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(error) => return Err(error),
+        };
+        digest.update(&buffer[..read]);
+        file.write_all(&buffer[..read])?;
+        size += read as u64;
+    }
+    let sha256 = digest
+        .finalize()
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect();
+    Ok((sha256, size))
+}
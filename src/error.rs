@@ -0,0 +1,192 @@
+use std::fmt;
+use std::path::PathBuf;
+
+use crate::VulnerabilitySeverity;
+
+#[derive(Debug)]
+pub enum NugetError {
+    HashMismatch {
+        package_name: String,
+        version: String,
+        expected: String,
+        actual: String,
+    },
+    InvalidConfig(String),
+    UnsupportedHashAlgorithm(String),
+    InvalidVersion(String),
+    /// A required argument or config value, e.g. a package name, is empty or
+    /// only whitespace.
+    InvalidInput {
+        field: String,
+    },
+    InvalidPackageId(String),
+    InvalidHeader(String),
+    /// The download isn't a package.
+    InvalidPackage(String),
+    ExtractionLimitExceeded {
+        entry: String,
+        limit: u64,
+    },
+    /// Downloads can't be written to the cache directory and there's no
+    /// fallback cache directory.
+    CacheNotWritable {
+        path: PathBuf,
+    },
+    /// The lockfile is missing or doesn't match the config, and the install
+    /// isn't allowed to update it.
+    LockfileOutOfDate {
+        path: PathBuf,
+    },
+    /// A metadata response is bigger than the client's limit.
+    MetadataTooLarge {
+        url: String,
+        limit: u64,
+    },
+    /// The client's deadline passed before the request finished.
+    DeadlineExceeded,
+    RedirectLoop {
+        url: String,
+    },
+    TooManyRedirects {
+        max_redirects: usize,
+    },
+    NoMatchingVersion {
+        package_name: String,
+        requirement: String,
+    },
+    /// The package policy doesn't allow downloading this package.
+    PolicyViolation {
+        package_name: String,
+    },
+    /// The feed answered, but doesn't have this version of the package.
+    PackageNotFound {
+        package_name: String,
+        version: String,
+    },
+    /// The feed has the package but reports no hash for it.
+    HashUnavailable {
+        package_name: String,
+        version: String,
+    },
+    /// The package has no `.signature.p7s` entry, see
+    /// `NugetClient::with_require_signature_entry`.
+    UnsignedPackage {
+        package_name: String,
+        version: String,
+    },
+    /// The package's `.nuspec` names a different package than the one
+    /// requested.
+    IdentityMismatch {
+        package_name: String,
+        version: String,
+        actual_id: String,
+        actual_version: String,
+    },
+    InconsistentHash {
+        package_name: String,
+        version: String,
+        /// Each endpoint's URL and the hash it returned.
+        endpoints: Vec<(String, String)>,
+    },
+    VulnerablePackage {
+        package_name: String,
+        version: String,
+        severity: VulnerabilitySeverity,
+        advisory_url: String,
+    },
+}
+
+impl fmt::Display for NugetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NugetError::HashMismatch {
+                package_name,
+                version,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "hash mismatch for {package_name} {version}: expected {expected}, got {actual}"
+            ),
+            NugetError::InvalidConfig(message) => write!(f, "invalid config: {message}"),
+            NugetError::UnsupportedHashAlgorithm(algorithm) => {
+                write!(f, "unsupported hash algorithm: {algorithm}")
+            }
+            NugetError::InvalidVersion(version) => write!(f, "invalid version: {version}"),
+            NugetError::InvalidInput { field } => write!(f, "{field} is empty"),
+            NugetError::InvalidPackageId(id) => {
+                write!(f, "invalid package id: {id}, expected name@version")
+            }
+            NugetError::InvalidHeader(name) => write!(f, "invalid header: {name}"),
+            NugetError::InvalidPackage(reason) => write!(f, "invalid package: {reason}"),
+            NugetError::ExtractionLimitExceeded { entry, limit } => {
+                write!(f, "extracting {entry} exceeds the {limit} byte limit")
+            }
+            NugetError::CacheNotWritable { path } => {
+                write!(f, "cache directory {} is not writable", path.display())
+            }
+            NugetError::LockfileOutOfDate { path } => {
+                write!(f, "{} is missing or out of date", path.display())
+            }
+            NugetError::MetadataTooLarge { url, limit } => {
+                write!(f, "the response from {url} is over the {limit} byte limit")
+            }
+            NugetError::DeadlineExceeded => write!(f, "the deadline passed"),
+            NugetError::RedirectLoop { url } => write!(f, "redirect loop at {url}"),
+            NugetError::TooManyRedirects { max_redirects } => {
+                write!(f, "more than {max_redirects} redirects")
+            }
+            NugetError::NoMatchingVersion {
+                package_name,
+                requirement,
+            } => write!(f, "no version of {package_name} matches {requirement}"),
+            NugetError::PolicyViolation { package_name } => {
+                write!(f, "{package_name} is not allowed by the package policy")
+            }
+            NugetError::PackageNotFound {
+                package_name,
+                version,
+            } => write!(f, "{package_name} {version} was not found"),
+            NugetError::HashUnavailable {
+                package_name,
+                version,
+            } => write!(f, "no hash is available for {package_name} {version}"),
+            NugetError::UnsignedPackage {
+                package_name,
+                version,
+            } => write!(f, "{package_name} {version} has no signature"),
+            NugetError::IdentityMismatch {
+                package_name,
+                version,
+                actual_id,
+                actual_version,
+            } => write!(
+                f,
+                "requested {package_name} {version}, but the package is {actual_id} {actual_version}"
+            ),
+            NugetError::InconsistentHash {
+                package_name,
+                version,
+                endpoints,
+            } => {
+                write!(f, "inconsistent hashes for {package_name} {version}:")?;
+                for (index, (url, hash)) in endpoints.iter().enumerate() {
+                    let separator = if index == 0 { "" } else { "," };
+                    write!(f, "{separator} {url} reported {hash}")?;
+                }
+                Ok(())
+            }
+            NugetError::VulnerablePackage {
+                package_name,
+                version,
+                severity,
+                advisory_url,
+            } => write!(
+                f,
+                "{package_name} {version} has a {severity:?} severity vulnerability: {advisory_url}"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for NugetError {}
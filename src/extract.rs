@@ -0,0 +1,273 @@
+use std::{
+    fs::File,
+    io::{Read, Write},
+    path::{Component, Path, PathBuf},
+};
+
+use crate::{NugetError, NugetPackageFile};
+
+/// Limits that guard extraction against zip bombs. Sizes are measured from
+/// the decompressed data rather than trusted from the zip headers.
+#[derive(Clone, Debug)]
+pub struct ExtractOptions {
+    pub max_entry_size: u64,
+    pub max_total_size: u64,
+}
+
+impl Default for ExtractOptions {
+    fn default() -> Self {
+        Self {
+            max_entry_size: 2 * 1024 * 1024 * 1024,
+            max_total_size: 8 * 1024 * 1024 * 1024,
+        }
+    }
+}
+
+/// Decides which package entries `extract_package_files` extracts, given
+/// their path inside the package, e.g. `runtimes/win-x64/native/foo.dll`.
+/// Implemented for closures and for `globset::GlobSet`, see `glob_filter`.
+pub trait EntryFilter {
+    fn matches(&self, entry_path: &str) -> bool;
+}
+
+impl<F: Fn(&str) -> bool> EntryFilter for F {
+    fn matches(&self, entry_path: &str) -> bool {
+        self(entry_path)
+    }
+}
+
+impl EntryFilter for globset::GlobSet {
+    fn matches(&self, entry_path: &str) -> bool {
+        self.is_match(entry_path)
+    }
+}
+
+/// Builds a filter matching entries against any of the glob patterns, e.g.
+/// `runtimes/win-x64/native/*.dll` or `lib/**/*.winmd`. `*` doesn't cross
+/// `/`, use `**` for that.
+pub fn glob_filter(
+    patterns: &[&str],
+) -> Result<globset::GlobSet, Box<dyn std::error::Error + Send + Sync>> {
+    let mut builder = globset::GlobSetBuilder::new();
+    for pattern in patterns {
+        let glob = globset::GlobBuilder::new(pattern)
+            .literal_separator(true)
+            .build()?;
+        builder.add(glob);
+    }
+    Ok(builder.build()?)
+}
+
+/// Extracts every entry of the package into `dest_dir`, returning the paths
+/// of the extracted files.
+pub fn extract_package<P: AsRef<Path>>(
+    file: &NugetPackageFile,
+    dest_dir: P,
+    options: &ExtractOptions,
+) -> Result<Vec<PathBuf>, Box<dyn std::error::Error + Send + Sync>> {
+    extract_package_files(file, dest_dir, options, |_: &str| true)
+}
+
+/// Like `extract_package`, but only extracts the entries the filter matches.
+/// Only matching entries count towards `max_total_size`.
+pub fn extract_package_files<P: AsRef<Path>, F: EntryFilter>(
+    file: &NugetPackageFile,
+    dest_dir: P,
+    options: &ExtractOptions,
+    filter: F,
+) -> Result<Vec<PathBuf>, Box<dyn std::error::Error + Send + Sync>> {
+    extract_entries(
+        file,
+        dest_dir.as_ref(),
+        options,
+        |entry_name, relative_path| {
+            Ok(filter.matches(entry_name).then(|| relative_path.to_owned()))
+        },
+    )
+}
+
+/// Like `extract_package`, but `map` decides where each entry goes, given its
+/// path inside the package. It returns the path relative to `dest_dir` to
+/// extract the entry to, e.g. just the file name to flatten the package, or
+/// `None` to skip the entry. Mapped paths that would leave `dest_dir` are
+/// rejected, and entries mapped to the same path overwrite each other.
+pub fn extract_package_mapped<P, M>(
+    file: &NugetPackageFile,
+    dest_dir: P,
+    options: &ExtractOptions,
+    map: M,
+) -> Result<Vec<PathBuf>, Box<dyn std::error::Error + Send + Sync>>
+where
+    P: AsRef<Path>,
+    M: Fn(&Path) -> Option<PathBuf>,
+{
+    extract_entries(
+        file,
+        dest_dir.as_ref(),
+        options,
+        |entry_name, relative_path| {
+            let Some(mapped) = map(relative_path) else {
+                return Ok(None);
+            };
+            let mut components = mapped.components().peekable();
+            let is_safe = components.peek().is_some()
+                && components.all(|component| matches!(component, Component::Normal(_)));
+            if !is_safe {
+                return Err(format!(
+                    "{entry_name} is mapped to an unsafe path {}",
+                    mapped.display()
+                )
+                .into());
+            }
+            Ok(Some(mapped))
+        },
+    )
+}
+
+// Extracts the entries `output_path` returns a path for, relative to
+// `dest_dir`. It's given the entry's name and its path once checked to stay
+// inside `dest_dir`.
+fn extract_entries<O>(
+    file: &NugetPackageFile,
+    dest_dir: &Path,
+    options: &ExtractOptions,
+    output_path: O,
+) -> Result<Vec<PathBuf>, Box<dyn std::error::Error + Send + Sync>>
+where
+    O: Fn(&str, &Path) -> Result<Option<PathBuf>, Box<dyn std::error::Error + Send + Sync>>,
+{
+    let mut archive = zip::ZipArchive::new(&file.file)?;
+    let mut total_size = 0u64;
+    let mut extracted = Vec::new();
+    for index in 0..archive.len() {
+        let mut entry = archive.by_index(index)?;
+        let relative_path = match entry.enclosed_name() {
+            Some(relative_path) => relative_path.to_owned(),
+            None => return Err(format!("{} has an unsafe path", entry.name()).into()),
+        };
+        let path = match output_path(entry.name(), &relative_path)? {
+            Some(relative_path) => dest_dir.join(relative_path),
+            None => continue,
+        };
+        if entry.is_dir() {
+            std::fs::create_dir_all(&path)?;
+            continue;
+        }
+
+        let entry_name = entry.name().to_owned();
+        let limit_exceeded = |limit| NugetError::ExtractionLimitExceeded {
+            entry: entry_name.clone(),
+            limit,
+        };
+        if entry.size() > options.max_entry_size {
+            return Err(Box::new(limit_exceeded(options.max_entry_size)));
+        }
+        let remaining = options.max_total_size.saturating_sub(total_size);
+        let limit = options.max_entry_size.min(remaining);
+
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let mut dest_file = File::create(&path)?;
+        let written = std::io::copy(
+            &mut (&mut entry).take(limit.saturating_add(1)),
+            &mut dest_file,
+        )?;
+        if written > limit {
+            drop(dest_file);
+            let _ = std::fs::remove_file(&path);
+            let limit = if written > options.max_entry_size {
+                options.max_entry_size
+            } else {
+                options.max_total_size
+            };
+            return Err(Box::new(limit_exceeded(limit)));
+        }
+        total_size += written;
+        extracted.push(path);
+    }
+    Ok(extracted)
+}
+
+/// Extracts a single entry (e.g. `runtimes/win-x64/native/foo.dll`) from the
+/// package into `dest`, without unpacking anything else.
+pub fn extract_entry<P: AsRef<Path>>(
+    file: &NugetPackageFile,
+    entry_path: &str,
+    dest: P,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let dest = dest.as_ref();
+
+    let mut archive = zip::ZipArchive::new(&file.file)?;
+    let mut entry = find_entry(&mut archive, file, entry_path)?;
+
+    if let Some(parent) = dest.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let mut dest_file = File::create(dest)?;
+    std::io::copy(&mut entry, &mut dest_file)?;
+
+    Ok(())
+}
+
+/// Decompresses a single entry straight into the writer, returning how many
+/// bytes were written.
+pub fn extract_entry_to_writer<W: Write + ?Sized>(
+    file: &NugetPackageFile,
+    entry_path: &str,
+    writer: &mut W,
+) -> Result<u64, Box<dyn std::error::Error + Send + Sync>> {
+    let mut archive = zip::ZipArchive::new(&file.file)?;
+    let mut entry = find_entry(&mut archive, file, entry_path)?;
+    Ok(std::io::copy(&mut entry, writer)?)
+}
+
+fn find_entry<'a>(
+    archive: &'a mut zip::ZipArchive<&File>,
+    file: &NugetPackageFile,
+    entry_path: &str,
+) -> Result<zip::read::ZipFile<'a>, Box<dyn std::error::Error + Send + Sync>> {
+    archive
+        .by_name(entry_path)
+        .map_err(|error| format!("{entry_path} not found in {}: {error}", file.stem).into())
+}
+
+#[derive(Clone, Debug)]
+pub struct PackageEntry {
+    pub path: String,
+    pub size: u64,
+    pub compressed_size: u64,
+}
+
+/// Lists the files in the package using only the zip central directory,
+/// nothing is decompressed.
+pub fn list_package_contents(
+    file: &NugetPackageFile,
+) -> Result<Vec<PackageEntry>, Box<dyn std::error::Error + Send + Sync>> {
+    let mut archive = zip::ZipArchive::new(&file.file)?;
+    let mut entries = Vec::with_capacity(archive.len());
+    for index in 0..archive.len() {
+        let entry = archive.by_index_raw(index)?;
+        if entry.is_dir() {
+            continue;
+        }
+        entries.push(PackageEntry {
+            path: entry.name().to_owned(),
+            size: entry.size(),
+            compressed_size: entry.compressed_size(),
+        });
+    }
+    Ok(entries)
+}
+
+// Signed packages carry their PKCS#7 signature as a file at the root.
+const SIGNATURE_ENTRY: &str = ".signature.p7s";
+
+// Only whether the entry is there, its contents aren't looked at.
+pub(crate) fn has_signature_entry(
+    file: &File,
+) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+    let archive = zip::ZipArchive::new(file)?;
+    let has_signature_entry = archive.file_names().any(|name| name == SIGNATURE_ENTRY);
+    Ok(has_signature_entry)
+}
@@ -0,0 +1,290 @@
+use std::{
+    fs::File,
+    io::Read,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    get_package_file_name,
+    hash::hash_file,
+    metadata::{parse_nuspec, PackageMetadata},
+    HashAlgorithm, NugetVersion, PackageHash, PackageId,
+};
+
+pub(crate) const NUGET_V2_URL: &str = "https://www.nuget.org/api/v2";
+
+/// Where the client gets packages and their metadata from.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FeedSource {
+    /// A NuGet V2 feed, e.g. `https://www.nuget.org/api/v2`.
+    Url(String),
+    /// A NuGet V3 feed, given by its service index, e.g.
+    /// `https://api.nuget.org/v3/index.json`.
+    V3(String),
+    /// A folder of `.nupkg` files, laid out either flat
+    /// (`{id}.{version}.nupkg`) or hierarchically like `nuget add` does
+    /// (`{id}/{version}/{id}.{version}.nupkg`, lowercase). Hashes are
+    /// computed from the files, so nothing is fetched over the network.
+    LocalDir(PathBuf),
+}
+
+impl FeedSource {
+    /// Parses `file://` URLs into `LocalDir` and URLs ending in `index.json`
+    /// into `V3`, anything else is a V2 feed URL. Construct the variant
+    /// directly for feeds whose URLs don't follow these conventions.
+    pub fn parse(url: &str) -> Self {
+        if let Some(path) = url.strip_prefix("file://") {
+            return Self::LocalDir(file_url_path(path));
+        }
+        let url = url.trim_end_matches('/');
+        if url.ends_with("index.json") {
+            Self::V3(url.to_owned())
+        } else {
+            Self::Url(url.to_owned())
+        }
+    }
+}
+
+impl From<&str> for FeedSource {
+    fn from(url: &str) -> Self {
+        Self::parse(url)
+    }
+}
+
+impl From<String> for FeedSource {
+    fn from(url: String) -> Self {
+        Self::parse(&url)
+    }
+}
+
+// `file:///C:/packages` and `file://localhost/srv/packages` are both valid
+fn file_url_path(path: &str) -> PathBuf {
+    let path = path.strip_prefix("localhost").unwrap_or(path);
+    let bytes = path.as_bytes();
+    let path = if bytes.len() >= 3 && bytes[0] == b'/' && bytes[2] == b':' {
+        &path[1..]
+    } else {
+        path
+    };
+    // Undo percent-encoding, e.g. `%20` for spaces
+    let mut decoded = Vec::with_capacity(path.len());
+    let mut bytes = path.bytes();
+    while let Some(byte) = bytes.next() {
+        if byte == b'%' {
+            let digits = [bytes.next(), bytes.next()];
+            if let [Some(high), Some(low)] = digits {
+                if let Ok(value) = u8::from_str_radix(&String::from_utf8_lossy(&[high, low]), 16) {
+                    decoded.push(value);
+                    continue;
+                }
+            }
+            decoded.push(byte);
+            decoded.extend(digits.into_iter().flatten());
+        } else {
+            decoded.push(byte);
+        }
+    }
+    PathBuf::from(String::from_utf8_lossy(&decoded).into_owned())
+}
+
+pub(crate) fn find_package(dir: &Path, package_name: &str, version: &str) -> Option<PathBuf> {
+    let (id, version_lower) = (package_name.to_lowercase(), version.to_lowercase());
+    let mut hierarchical = dir.to_owned();
+    hierarchical.push(&id);
+    hierarchical.push(&version_lower);
+    hierarchical.push(get_package_file_name(&id, &version_lower));
+    if hierarchical.is_file() {
+        return Some(hierarchical);
+    }
+
+    let flat = dir.join(get_package_file_name(package_name, version));
+    if flat.is_file() {
+        return Some(flat);
+    }
+    // Ids are case-insensitive, but file systems may not be
+    let file_name = get_package_file_name(&id, &version_lower);
+    std::fs::read_dir(dir)
+        .ok()?
+        .filter_map(Result::ok)
+        .find(|entry| entry.file_name().to_string_lossy().to_lowercase() == file_name)
+        .map(|entry| entry.path())
+}
+
+fn not_found(package_name: &str, version: &str, dir: &Path) -> String {
+    format!("{package_name} {version} not found in {}", dir.display())
+}
+
+pub(crate) fn open_package(
+    dir: &Path,
+    package_name: &str,
+    version: &str,
+) -> Result<File, Box<dyn std::error::Error + Send + Sync>> {
+    let path = find_package(dir, package_name, version)
+        .ok_or_else(|| not_found(package_name, version, dir))?;
+    Ok(File::open(path)?)
+}
+
+/// The `.nuspec` of the package in the local feed.
+pub(crate) fn package_nuspec(
+    dir: &Path,
+    package_name: &str,
+    version: &str,
+) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+    let path = find_package(dir, package_name, version)
+        .ok_or_else(|| not_found(package_name, version, dir))?;
+    read_nuspec(&path)
+}
+
+pub(crate) fn read_nuspec(path: &Path) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+    let mut archive = zip::ZipArchive::new(File::open(path)?)?;
+    let nuspec_name = archive
+        .file_names()
+        .find(|name| !name.contains('/') && name.to_lowercase().ends_with(".nuspec"))
+        .map(str::to_owned)
+        .ok_or_else(|| format!("{} has no .nuspec", path.display()))?;
+    let mut nuspec = String::new();
+    archive.by_name(&nuspec_name)?.read_to_string(&mut nuspec)?;
+    Ok(nuspec)
+}
+
+/// Builds the metadata a feed would report from the package itself.
+pub(crate) fn package_metadata(
+    dir: &Path,
+    package_name: &str,
+    version: &str,
+) -> Result<PackageMetadata, Box<dyn std::error::Error + Send + Sync>> {
+    let path = find_package(dir, package_name, version)
+        .ok_or_else(|| not_found(package_name, version, dir))?;
+    let size = std::fs::metadata(&path)?.len();
+    let nuspec = parse_nuspec(&read_nuspec(&path)?)?;
+
+    let algorithm = HashAlgorithm::SHA512;
+    let hash = hash_file(&path, &algorithm)?.map(|hash| PackageHash {
+        hash: base64::encode(hash),
+        algorithm,
+    });
+    let parsed_version = nuspec.version.parse::<NugetVersion>().ok();
+    Ok(PackageMetadata {
+        id: nuspec.id,
+        is_prerelease: parsed_version.is_some_and(|version| version.is_prerelease()),
+        version: nuspec.version,
+        listed: true,
+        size: Some(size),
+        hash,
+        dependency_groups: nuspec.dependency_groups,
+        deprecation: None,
+        published: None,
+        last_updated: None,
+        download_count: None,
+        version_download_count: None,
+        vulnerabilities: Vec::new(),
+    })
+}
+
+/// Hashes the package with `algorithm`, `None` if we can't compute it.
+pub(crate) fn package_hash(
+    dir: &Path,
+    package_name: &str,
+    version: &str,
+    algorithm: &HashAlgorithm,
+) -> Result<Option<PackageHash>, Box<dyn std::error::Error + Send + Sync>> {
+    let path = find_package(dir, package_name, version)
+        .ok_or_else(|| not_found(package_name, version, dir))?;
+    Ok(hash_file(&path, algorithm)?.map(|hash| PackageHash {
+        hash: base64::encode(hash),
+        algorithm: algorithm.clone(),
+    }))
+}
+
+/// Every package cached in `dir`, in either `FileNaming` layout, sorted by
+/// name and version. Files whose names don't parse are skipped.
+pub fn list_cached_packages<P: AsRef<Path>>(
+    dir: P,
+) -> Result<Vec<PackageId>, Box<dyn std::error::Error + Send + Sync>> {
+    let dir = dir.as_ref();
+    let mut packages = Vec::new();
+    if !dir.exists() {
+        return Ok(packages);
+    }
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let file_name = entry.file_name();
+        let Some(file_name) = file_name.to_str() else {
+            continue;
+        };
+        if entry.file_type()?.is_dir() {
+            // `{id}/{version}/{id}.{version}.nupkg`
+            for version in std::fs::read_dir(entry.path())? {
+                let version = version?.file_name();
+                let Some(version) = version.to_str() else {
+                    continue;
+                };
+                let package = entry
+                    .path()
+                    .join(version)
+                    .join(get_package_file_name(file_name, version));
+                if package.is_file() {
+                    if let Ok(package) = PackageId::new(file_name, version) {
+                        packages.push(package);
+                    }
+                }
+            }
+        } else if let Some(package) = PackageId::from_file_name(file_name) {
+            packages.push(package);
+        }
+    }
+    packages.sort_by(|a, b| {
+        a.name
+            .to_lowercase()
+            .cmp(&b.name.to_lowercase())
+            .then_with(|| a.version.cmp(&b.version))
+    });
+    Ok(packages)
+}
+
+/// Every version of the package in the directory, in either layout.
+pub(crate) fn list_versions(
+    dir: &Path,
+    package_name: &str,
+) -> Result<Vec<String>, Box<dyn std::error::Error + Send + Sync>> {
+    let mut versions = flat_versions(dir, package_name)?;
+    let id = package_name.to_lowercase();
+    let id_dir = dir.join(&id);
+    if id_dir.is_dir() {
+        for entry in std::fs::read_dir(&id_dir)? {
+            let entry = entry?;
+            let version = entry.file_name().to_string_lossy().into_owned();
+            let package = entry.path().join(get_package_file_name(&id, &version));
+            if package.is_file() && !versions.contains(&version) {
+                versions.push(version);
+            }
+        }
+    }
+    versions.sort();
+    Ok(versions)
+}
+
+/// Versions of the package present as `{id}.{version}.nupkg` files in `dir`.
+pub(crate) fn flat_versions(
+    dir: &Path,
+    package_name: &str,
+) -> Result<Vec<String>, Box<dyn std::error::Error + Send + Sync>> {
+    let mut versions = Vec::new();
+    if !dir.exists() {
+        return Ok(versions);
+    }
+    for entry in std::fs::read_dir(dir)? {
+        let file_name = entry?.file_name();
+        let Some(file_name) = file_name.to_str() else {
+            continue;
+        };
+        // Guards against ids that share a prefix, e.g. `Foo` and `Foo.Bar`
+        if let Some(package) = PackageId::from_file_name(file_name) {
+            if package.name.eq_ignore_ascii_case(package_name) {
+                versions.push(package.version.to_string());
+            }
+        }
+    }
+    versions.sort();
+    Ok(versions)
+}
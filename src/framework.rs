@@ -0,0 +1,142 @@
+use std::cmp::Ordering;
+
+/// A minimal model of NuGet target framework monikers (`net6.0`, `net472`,
+/// `netstandard2.0`, `native`, ...), enough to pick a dependency group.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct TargetFramework {
+    family: FrameworkFamily,
+    version: Vec<u32>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum FrameworkFamily {
+    NetFramework,
+    NetStandard,
+    // .NET Core and .NET 5+
+    NetCoreApp,
+    Native,
+    Other(String),
+}
+
+impl TargetFramework {
+    pub fn parse(moniker: &str) -> Self {
+        let moniker = moniker.trim().to_lowercase();
+        // Platform suffixes (`net6.0-windows`) don't affect dependency groups
+        let moniker = moniker.split('-').next().unwrap_or_default();
+        let moniker = moniker.strip_prefix('.').unwrap_or(moniker);
+
+        let split_version = |prefix: &str| -> Option<Vec<u32>> {
+            let version = moniker.strip_prefix(prefix)?;
+            if version.is_empty() || !version.starts_with(|c: char| c.is_ascii_digit()) {
+                return None;
+            }
+            if version.contains('.') {
+                version.split('.').map(|part| part.parse().ok()).collect()
+            } else {
+                // `net472` style, one digit per part
+                version.chars().map(|c| c.to_digit(10)).collect()
+            }
+        };
+
+        let parsed = if moniker == "native" {
+            Some((FrameworkFamily::Native, Vec::new()))
+        } else {
+            split_version("netstandard")
+                .map(|version| (FrameworkFamily::NetStandard, version))
+                .or_else(|| {
+                    split_version("netcoreapp")
+                        .map(|version| (FrameworkFamily::NetCoreApp, version))
+                })
+                .or_else(|| {
+                    split_version("netframework")
+                        .map(|version| (FrameworkFamily::NetFramework, version))
+                })
+                .or_else(|| {
+                    split_version("net").map(|version| {
+                        // `net5.0` and later are .NET Core, `net48` is .NET Framework
+                        if moniker.contains('.') && version.first().copied().unwrap_or(0) >= 5 {
+                            (FrameworkFamily::NetCoreApp, version)
+                        } else {
+                            (FrameworkFamily::NetFramework, version)
+                        }
+                    })
+                })
+        };
+
+        match parsed {
+            Some((family, version)) => Self { family, version },
+            None => Self {
+                family: FrameworkFamily::Other(moniker.to_owned()),
+                version: Vec::new(),
+            },
+        }
+    }
+
+    fn version_cmp(&self, other: &Self) -> Ordering {
+        let len = self.version.len().max(other.version.len());
+        let part = |version: &[u32], index: usize| version.get(index).copied().unwrap_or(0);
+        (0..len)
+            .map(|index| part(&self.version, index).cmp(&part(&other.version, index)))
+            .find(|ordering| *ordering != Ordering::Equal)
+            .unwrap_or(Ordering::Equal)
+    }
+
+    fn version_at_least(&self, version: &[u32]) -> bool {
+        let other = Self {
+            family: self.family.clone(),
+            version: version.to_vec(),
+        };
+        self.version_cmp(&other) != Ordering::Less
+    }
+
+    /// How well a package built for `candidate` fits a project targeting
+    /// `self`. `None` means incompatible; higher is a closer match.
+    pub fn compatibility(&self, candidate: &Self) -> Option<u32> {
+        use FrameworkFamily::*;
+        match (&self.family, &candidate.family) {
+            (a, b) if a == b => (self.version_cmp(candidate) != Ordering::Less).then_some(3),
+            (NetCoreApp, NetStandard) => Some(2),
+            // .NET Framework 4.6.1 and later implement .NET Standard up to 2.0
+            (NetFramework, NetStandard)
+                if self.version_at_least(&[4, 6, 1]) && !candidate.version_at_least(&[2, 1]) =>
+            {
+                Some(2)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Picks the index of the group whose framework is the nearest compatible
+/// match for `target`. Groups without a framework apply to everything but are
+/// only used when no framework specific group fits.
+pub(crate) fn nearest_framework<'a, I>(target: &TargetFramework, frameworks: I) -> Option<usize>
+where
+    I: IntoIterator<Item = Option<&'a str>>,
+{
+    let mut best: Option<(usize, u32, TargetFramework)> = None;
+    for (index, framework) in frameworks.into_iter().enumerate() {
+        let (score, candidate) = match framework {
+            Some(framework) => {
+                let candidate = TargetFramework::parse(framework);
+                match target.compatibility(&candidate) {
+                    Some(score) => (score, candidate),
+                    None => continue,
+                }
+            }
+            None => (1, TargetFramework::parse("")),
+        };
+        let better = match &best {
+            None => true,
+            Some((_, best_score, best_candidate)) => {
+                score > *best_score
+                    || (score == *best_score
+                        && candidate.version_cmp(best_candidate) == Ordering::Greater)
+            }
+        };
+        if better {
+            best = Some((index, score, candidate));
+        }
+    }
+    best.map(|(index, _, _)| index)
+}
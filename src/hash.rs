@@ -0,0 +1,134 @@
+use std::{
+    fs::File,
+    io::Read,
+    path::Path,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+pub(crate) use sha2::digest::DynDigest;
+use sha2::{Sha256, Sha512};
+
+use crate::HashAlgorithm;
+
+static BUFFER_SIZE: AtomicUsize = AtomicUsize::new(64 * 1024);
+
+/// Sets how many bytes are read at a time when hashing files, for the whole
+/// process. Defaults to 64 KiB, larger buffers can be faster on fast disks.
+pub fn set_hash_buffer_size(bytes: usize) {
+    BUFFER_SIZE.store(bytes.max(1), Ordering::Relaxed);
+}
+
+pub fn hash_buffer_size() -> usize {
+    BUFFER_SIZE.load(Ordering::Relaxed)
+}
+
+/// A new digest for the algorithm, or `None` for algorithms we can't compute.
+/// This is the only place that maps algorithms to their implementations.
+pub(crate) fn digest_for(algorithm: &HashAlgorithm) -> Option<Box<dyn DynDigest + Send>> {
+    match algorithm {
+        HashAlgorithm::SHA512 => Some(Box::new(Sha512::default())),
+        HashAlgorithm::SHA256 => Some(Box::new(Sha256::default())),
+        HashAlgorithm::Unknown(_) => None,
+    }
+}
+
+/// Hashes a file on disk in fixed-size chunks, so memory use doesn't grow with
+/// the package size. Returns `None` for algorithms we can't compute.
+pub(crate) fn hash_file<P: AsRef<Path>>(
+    path: P,
+    algorithm: &HashAlgorithm,
+) -> std::io::Result<Option<Vec<u8>>> {
+    if digest_for(algorithm).is_none() {
+        return Ok(None);
+    }
+    let mut file = File::open(path)?;
+    hash_reader(&mut file, algorithm)
+}
+
+/// Like `hash_file`, for data that isn't in a file of its own.
+pub(crate) fn hash_reader<R: Read + ?Sized>(
+    reader: &mut R,
+    algorithm: &HashAlgorithm,
+) -> std::io::Result<Option<Vec<u8>>> {
+    let mut digest = match digest_for(algorithm) {
+        Some(digest) => digest,
+        None => return Ok(None),
+    };
+    let mut buffer = vec![0; hash_buffer_size()];
+    loop {
+        let read = match reader.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(error) if error.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(error) => return Err(error),
+        };
+        digest.update(&buffer[..read]);
+    }
+    Ok(Some(digest.finalize().into_vec()))
+}
+
+#[cfg(test)]
+mod tests {
+    use sha2::Digest;
+
+    use super::*;
+
+    // Generates `len` bytes without holding them, recording the largest read
+    struct SyntheticReader {
+        len: usize,
+        position: usize,
+        largest_read: usize,
+    }
+
+    impl Read for SyntheticReader {
+        fn read(&mut self, buffer: &mut [u8]) -> std::io::Result<usize> {
+            self.largest_read = self.largest_read.max(buffer.len());
+            let read = buffer.len().min(self.len - self.position);
+            for (offset, byte) in buffer[..read].iter_mut().enumerate() {
+                *byte = ((self.position + offset) % 251) as u8;
+            }
+            self.position += read;
+            Ok(read)
+        }
+    }
+
+    #[test]
+    fn hashes_large_files_in_chunks() {
+        // Not a multiple of the buffer size, so the last chunk is partial
+        let len = 8 * 1024 * 1024 + 123;
+        let mut reader = SyntheticReader {
+            len,
+            position: 0,
+            largest_read: 0,
+        };
+        let path = std::env::temp_dir().join(format!("nuget-dl-hash-{}", std::process::id()));
+        std::io::copy(&mut reader, &mut File::create(&path).unwrap()).unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+        let expected = Sha512::digest(&bytes).to_vec();
+
+        let actual = hash_file(&path, &HashAlgorithm::SHA512).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(actual, Some(expected.clone()));
+
+        let mut reader = SyntheticReader {
+            len,
+            position: 0,
+            largest_read: 0,
+        };
+        let actual = hash_reader(&mut reader, &HashAlgorithm::SHA512).unwrap();
+        assert_eq!(actual, Some(expected));
+        assert_eq!(reader.largest_read, hash_buffer_size());
+    }
+
+    #[test]
+    fn unknown_algorithms_are_not_hashed() {
+        let mut reader = SyntheticReader {
+            len: 16,
+            position: 0,
+            largest_read: 0,
+        };
+        let algorithm = HashAlgorithm::Unknown("MD5".to_owned());
+        assert_eq!(hash_reader(&mut reader, &algorithm).unwrap(), None);
+        assert_eq!(reader.largest_read, 0);
+    }
+}
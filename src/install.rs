@@ -0,0 +1,1274 @@
+#[cfg(feature = "blocking")]
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Instant,
+};
+
+use serde::Serialize;
+use tokio::{sync::Semaphore, task::JoinSet};
+
+#[cfg(feature = "blocking")]
+use crate::{
+    async_client::block_on,
+    config::{is_floating, resolve_requirement, ConfigFormat, NugetPackageRef},
+    feed::{flat_versions, list_versions, read_nuspec},
+    legacy_error, packages_subdir, parse_manifest,
+    project::{
+        dotnet_lockfile_packages, packages_config_references, project_package_references,
+        requirements_references,
+    },
+    FileNaming, NugetClient, PackageId, VersionRange,
+};
+use crate::{
+    async_client::AsyncClient,
+    config::{ExtractRule, NugetConfig},
+    extract_package_mapped, glob_filter,
+    hash::hash_file,
+    max_concurrent_downloads,
+    progress::ProgressAggregator,
+    resume::ResumeState,
+    DownloadResult, ExtractOptions, HashAlgorithm, InstallProgress, LockedPackage, Lockfile,
+    NugetError, NugetPackageFile, NugetVersion, PackageHash, PackagePolicy,
+};
+
+/// Downloads every package in the config, returning the packages sorted by
+/// name followed by any transitive dependencies.
+#[cfg(feature = "blocking")]
+pub fn process_nuget<P: AsRef<Path>>(
+    config_path: P,
+) -> Result<Vec<NugetPackageFile>, Box<dyn std::error::Error>> {
+    install_config(config_path, InstallMode::Strict)
+        .and_then(report_files)
+        .map_err(legacy_error)
+}
+
+/// Like `process_nuget`, with the install set up by `options`. Fails with
+/// the first package's error whatever the options' `InstallMode`.
+#[cfg(feature = "blocking")]
+pub fn process_nuget_with<P: AsRef<Path>>(
+    config_path: P,
+    options: &InstallOptions,
+) -> Result<Vec<NugetPackageFile>, Box<dyn std::error::Error + Send + Sync>> {
+    install_config_with(config_path, options).and_then(report_files)
+}
+
+/// Downloads the packages a legacy `packages.config` lists into the
+/// `packages_subdir()` directory next to it.
+#[cfg(feature = "blocking")]
+pub fn process_packages_config<P: AsRef<Path>>(
+    path: P,
+) -> Result<Vec<NugetPackageFile>, Box<dyn std::error::Error + Send + Sync>> {
+    let path = path.as_ref();
+    let references = packages_config_references(&std::fs::read_to_string(path)?)?;
+    process_references(path, references)
+}
+
+/// Downloads the packages a `.csproj` (or any MSBuild project) references
+/// with `PackageReference` items into the `packages_subdir()` directory next
+/// to it. Version ranges resolve to the lowest matching version like NuGet does,
+/// floating versions like `1.*` to the highest. Versions set through MSBuild
+/// properties, or centrally in `Directory.Packages.props`, aren't supported.
+#[cfg(feature = "blocking")]
+pub fn process_project_references<P: AsRef<Path>>(
+    csproj_path: P,
+) -> Result<Vec<NugetPackageFile>, Box<dyn std::error::Error + Send + Sync>> {
+    let csproj_path = csproj_path.as_ref();
+    let references = project_package_references(&std::fs::read_to_string(csproj_path)?)?;
+    process_references(csproj_path, references)
+}
+
+/// Downloads the packages a requirements file lists into the
+/// `packages_subdir()` directory next to it. Each line is `id version` or
+/// `id==version`, blank lines and `#` comments are ignored. Versions resolve
+/// like `process_project_references` does. Errors reading the file or
+/// resolving versions are returned, download failures are in the report.
+#[cfg(feature = "blocking")]
+pub fn process_requirements<P: AsRef<Path>>(
+    path: P,
+) -> Result<InstallReport, Box<dyn std::error::Error + Send + Sync>> {
+    let path = path.as_ref();
+    let text = std::fs::read_to_string(path)?;
+    let references = requirements_references(&text, &path.display().to_string())?;
+    install_references(path, references)
+}
+
+#[cfg(feature = "blocking")]
+fn process_references(
+    path: &Path,
+    references: Vec<(String, String)>,
+) -> Result<Vec<NugetPackageFile>, Box<dyn std::error::Error + Send + Sync>> {
+    report_files(install_references(path, references)?)
+}
+
+#[cfg(feature = "blocking")]
+fn install_references(
+    path: &Path,
+    references: Vec<(String, String)>,
+) -> Result<InstallReport, Box<dyn std::error::Error + Send + Sync>> {
+    let client = NugetClient::new();
+    let mut downloads = Vec::with_capacity(references.len());
+    for (name, requirement) in references {
+        let version = if requirement.parse::<NugetVersion>().is_ok() {
+            requirement
+        } else {
+            client.resolve_version_range(&name, &requirement.parse()?)?
+        };
+        downloads.push((name, version, None));
+    }
+    let packages_dir = path
+        .parent()
+        .unwrap_or(Path::new(""))
+        .join(packages_subdir());
+    Ok(download_all(&client, downloads, &packages_dir))
+}
+
+// Checks the packages against the config's policy and `policy`.
+fn check_policies<'a, I>(
+    config: &NugetConfig,
+    policy: Option<&PackagePolicy>,
+    package_names: I,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>>
+where
+    I: IntoIterator<Item = &'a String> + Clone,
+{
+    for policy in [config.policy.as_ref(), policy].into_iter().flatten() {
+        policy.check(package_names.clone().into_iter().map(String::as_str))?;
+    }
+    Ok(())
+}
+
+fn report_files(
+    report: InstallReport,
+) -> Result<Vec<NugetPackageFile>, Box<dyn std::error::Error + Send + Sync>> {
+    let mut files = Vec::with_capacity(report.packages.len());
+    for package in report.packages {
+        files.push(package.outcome?.file);
+    }
+    Ok(files)
+}
+
+/// `process_nuget` for async callers, see `install_config_async`.
+#[cfg(feature = "async")]
+pub async fn process_nuget_async<P: AsRef<Path>>(
+    config_path: P,
+) -> Result<Vec<NugetPackageFile>, Box<dyn std::error::Error + Send + Sync>> {
+    install(config_path.as_ref(), &InstallOptions::default())
+        .await
+        .and_then(report_files)
+}
+
+/// `install_config_with` for async callers. Versions are resolved and
+/// packages downloaded on the runtime, each package in a task of its own with
+/// at most `max_concurrent_downloads()` running at once. Hashing cached
+/// packages, extracting and writing the SBOM run on the runtime's blocking
+/// thread pool, so the install never stalls its worker threads. Needs a tokio
+/// runtime with IO and timers enabled.
+#[cfg(feature = "async")]
+pub async fn install_config_async<P: AsRef<Path>>(
+    config_path: P,
+    options: &InstallOptions,
+) -> Result<InstallReport, Box<dyn std::error::Error + Send + Sync>> {
+    install(config_path.as_ref(), options).await
+}
+
+type InstallProgressCallback = Arc<dyn Fn(&InstallProgress) + Send + Sync>;
+
+/// How `install_config_with` installs a config. The default is what
+/// `install_config` does in strict mode.
+#[derive(Clone, Default)]
+pub struct InstallOptions {
+    mode: InstallMode,
+    lockfile_mode: LockfileMode,
+    deadline: Option<Instant>,
+    progress: Option<InstallProgressCallback>,
+    resume: bool,
+    policy: Option<PackagePolicy>,
+}
+
+impl InstallOptions {
+    pub fn mode(mut self, mode: InstallMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Whether the lockfile may change, see `LockfileMode`.
+    pub fn lockfile_mode(mut self, lockfile_mode: LockfileMode) -> Self {
+        self.lockfile_mode = lockfile_mode;
+        self
+    }
+
+    /// Every request fails with `NugetError::DeadlineExceeded` once
+    /// `deadline` passes, cancelling the downloads still in flight. In best
+    /// effort mode the report has the packages that finished in time, the
+    /// rest fail with that error. Downloads cut short don't leave partial
+    /// files.
+    pub fn deadline(mut self, deadline: Instant) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    /// Calls `progress` with the bytes received across all packages, from
+    /// whichever thread is downloading. The total comes from the package
+    /// sizes looked up while planning, like `NugetClient::get_package_size`.
+    /// Packages that are already cached count as complete as soon as they're
+    /// verified.
+    pub fn progress<F: Fn(&InstallProgress) + Send + Sync + 'static>(
+        mut self,
+        progress: F,
+    ) -> Self {
+        self.progress = Some(Arc::new(progress));
+        self
+    }
+
+    /// Records each package as it finishes in a `.resume` file next to the
+    /// config, so rerunning after a failure or an interruption uses the
+    /// finished packages without checking them against the feed again. The
+    /// file is removed once every package is installed. Off by default.
+    pub fn resume(mut self, resume: bool) -> Self {
+        self.resume = resume;
+        self
+    }
+
+    /// Fails with `NugetError::PolicyViolation` if the config asks for a
+    /// package `policy` doesn't allow, on top of the config's own `[policy]`.
+    /// The packages the config lists are checked before anything is fetched,
+    /// their dependencies once they're resolved.
+    pub fn policy(mut self, policy: PackagePolicy) -> Self {
+        self.policy = Some(policy);
+        self
+    }
+}
+
+/// What an install may do with the config's lockfile, like Cargo's
+/// `--locked` and `--frozen`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LockfileMode {
+    /// Resolve anything the lockfile is missing and write it back.
+    #[default]
+    Update,
+    /// Resolve against the feed as usual, but fail with
+    /// `NugetError::LockfileOutOfDate` instead of changing the lockfile.
+    Locked,
+    /// Like `Locked`, but versions come straight from the lockfile, so
+    /// nothing is resolved against the feed. Fails before any request when
+    /// the lockfile doesn't cover the config. Packages are still downloaded.
+    Frozen,
+}
+
+/// How `install_config` handles packages that fail to install.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum InstallMode {
+    /// Fail with the first package's error, in config order.
+    #[default]
+    Strict,
+    /// Install what can be installed and report every failure.
+    BestEffort,
+}
+
+pub struct PackageInstall {
+    pub name: String,
+    pub version: String,
+    pub outcome: Result<DownloadResult, Box<dyn std::error::Error + Send + Sync>>,
+    /// The files the config's `extract` rules extracted from the package.
+    pub extracted: Vec<PathBuf>,
+}
+
+pub struct InstallReport {
+    pub packages: Vec<PackageInstall>,
+}
+
+impl InstallReport {
+    pub fn failures(&self) -> impl Iterator<Item = &PackageInstall> {
+        self.packages
+            .iter()
+            .filter(|package| package.outcome.is_err())
+    }
+
+    pub fn is_success(&self) -> bool {
+        self.failures().next().is_none()
+    }
+
+    /// The packages that had to be downloaded, rather than found in the
+    /// cache.
+    pub fn downloaded(&self) -> impl Iterator<Item = &PackageInstall> {
+        self.packages.iter().filter(|package| {
+            package
+                .outcome
+                .as_ref()
+                .map(|result| result.downloaded)
+                .unwrap_or(false)
+        })
+    }
+
+    /// Writes a CycloneDX JSON SBOM listing every installed package with the
+    /// SHA512 of the file in the cache, so it records exactly what a build
+    /// used. Packages that failed to install are left out.
+    pub fn write_sbom<P: AsRef<Path>>(
+        &self,
+        path: P,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let mut components = Vec::new();
+        for package in &self.packages {
+            let Ok(result) = &package.outcome else {
+                continue;
+            };
+            let hash = hash_file(&result.path, &HashAlgorithm::SHA512)?
+                .expect("SHA512 can always be computed");
+            components.push(SbomComponent {
+                kind: "library",
+                name: &package.name,
+                version: &package.version,
+                purl: format!("pkg:nuget/{}@{}", package.name, package.version),
+                hashes: vec![SbomHash {
+                    alg: "SHA-512",
+                    content: hash.iter().map(|byte| format!("{byte:02x}")).collect(),
+                }],
+            });
+        }
+        let sbom = Sbom {
+            bom_format: "CycloneDX",
+            spec_version: "1.4",
+            version: 1,
+            components,
+        };
+        std::fs::write(path, serde_json::to_string_pretty(&sbom)?)?;
+        Ok(())
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Sbom<'a> {
+    bom_format: &'static str,
+    spec_version: &'static str,
+    version: u32,
+    components: Vec<SbomComponent<'a>>,
+}
+
+#[derive(Serialize)]
+struct SbomComponent<'a> {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    name: &'a str,
+    version: &'a str,
+    purl: String,
+    hashes: Vec<SbomHash>,
+}
+
+#[derive(Serialize)]
+struct SbomHash {
+    alg: &'static str,
+    /// Hex, as CycloneDX expects.
+    content: String,
+}
+
+/// Downloads every package in the config in parallel. Every package is
+/// attempted even in strict mode, transient failures are already retried
+/// according to the client's retry policy. Errors resolving the config itself
+/// are always returned. When the config sets `sbom`, an SBOM of the installed
+/// packages is written there, see `InstallReport::write_sbom`.
+#[cfg(feature = "blocking")]
+pub fn install_config<P: AsRef<Path>>(
+    config_path: P,
+    mode: InstallMode,
+) -> Result<InstallReport, Box<dyn std::error::Error + Send + Sync>> {
+    install_config_with(config_path, &InstallOptions::default().mode(mode))
+}
+
+/// Like `install_config`, with the install set up by `options`. Runs the
+/// async install on a runtime of its own, so it can't be called from async
+/// code.
+#[cfg(feature = "blocking")]
+pub fn install_config_with<P: AsRef<Path>>(
+    config_path: P,
+    options: &InstallOptions,
+) -> Result<InstallReport, Box<dyn std::error::Error + Send + Sync>> {
+    block_on(install(config_path.as_ref(), options))?
+}
+
+async fn install(
+    config_path: &Path,
+    options: &InstallOptions,
+) -> Result<InstallReport, Box<dyn std::error::Error + Send + Sync>> {
+    let aggregator = options.progress.clone().map(|progress| {
+        Arc::new(ProgressAggregator::new(move |overall: &InstallProgress| {
+            progress(overall)
+        }))
+    });
+    let mut client = AsyncClient::new();
+    if let Some(deadline) = options.deadline {
+        client = client.with_deadline(deadline);
+    }
+    if let Some(aggregator) = &aggregator {
+        let aggregator = aggregator.clone();
+        client = client.with_progress(move |progress| aggregator.on_progress(progress));
+    }
+    install_with(Arc::new(client), config_path, options, aggregator).await
+}
+
+async fn install_with(
+    client: Arc<AsyncClient>,
+    config_path: &Path,
+    options: &InstallOptions,
+    progress: Option<Arc<ProgressAggregator>>,
+) -> Result<InstallReport, Box<dyn std::error::Error + Send + Sync>> {
+    let config = NugetConfig::from_path(config_path)?;
+    let packages_dir = config.packages_dir(config_path);
+    let policy = options.policy.as_ref();
+    check_policies(&config, policy, config.dependencies.keys())?;
+
+    let lockfile_path = Lockfile::path_for_config(config_path);
+    let lockfile = Lockfile::from_path(&lockfile_path)?;
+    let out_of_date = || NugetError::LockfileOutOfDate {
+        path: lockfile_path.clone(),
+    };
+    let packages = match (options.lockfile_mode, &lockfile) {
+        (LockfileMode::Frozen, Some(lockfile)) => {
+            frozen_packages(&config, lockfile)?.ok_or_else(out_of_date)?
+        }
+        (LockfileMode::Frozen, None) => return Err(Box::new(out_of_date())),
+        _ => config.resolve_packages(&client, lockfile.as_ref()).await?,
+    };
+    check_policies(
+        &config,
+        policy,
+        packages.iter().map(|package| &package.name),
+    )?;
+    warn_downgrades(&config, lockfile.as_ref(), &packages);
+    let resolved = Lockfile {
+        packages: packages.clone(),
+    };
+    if lockfile.as_ref() != Some(&resolved) {
+        if options.lockfile_mode != LockfileMode::Update {
+            return Err(Box::new(out_of_date()));
+        }
+        resolved.write(&lockfile_path)?;
+    }
+
+    let mut downloads = Vec::with_capacity(packages.len());
+    for LockedPackage { name, version, .. } in packages {
+        let pinned_hash = config.pinned_hash(&name)?;
+        downloads.push((name, version, pinned_hash));
+    }
+    if let Some(progress) = &progress {
+        let mut sizes = Vec::with_capacity(downloads.len());
+        for (name, version, _) in &downloads {
+            let size = client.get_package_size(name, version).await.ok().flatten();
+            sizes.push((name.as_str(), version.as_str(), size));
+        }
+        progress.plan(&sizes);
+    }
+    let resume = match options.resume {
+        true => Some(Arc::new(ResumeState::open(ResumeState::path_for_config(
+            config_path,
+        ))?)),
+        false => None,
+    };
+    let report = download_all_async(
+        &client,
+        downloads,
+        &packages_dir,
+        max_concurrent_downloads(),
+        progress,
+        resume.clone(),
+    )
+    .await;
+    let config_dir = config_path.parent().unwrap_or(Path::new("")).to_owned();
+    let mode = options.mode;
+    tokio::task::spawn_blocking(move || finish_install(&config, &config_dir, mode, report, resume))
+        .await?
+}
+
+// Extracts the installed packages and writes the SBOM, then removes the
+// resume state if everything is installed.
+fn finish_install(
+    config: &NugetConfig,
+    config_dir: &Path,
+    mode: InstallMode,
+    mut report: InstallReport,
+    resume: Option<Arc<ResumeState>>,
+) -> Result<InstallReport, Box<dyn std::error::Error + Send + Sync>> {
+    for package in &mut report.packages {
+        let rules = config.extract_rules(&package.name);
+        let Ok(result) = &package.outcome else {
+            continue;
+        };
+        match extract_files(&result.file, rules, config_dir) {
+            Ok(extracted) => package.extracted = extracted,
+            Err(error) => package.outcome = Err(error),
+        }
+    }
+    if mode == InstallMode::Strict {
+        if let Some(index) = report
+            .packages
+            .iter()
+            .position(|package| package.outcome.is_err())
+        {
+            report.packages.swap_remove(index).outcome?;
+        }
+    }
+    if let Some(sbom_path) = &config.sbom {
+        report.write_sbom(sbom_path)?;
+    }
+    if let Some(resume) = resume.and_then(Arc::into_inner) {
+        if report
+            .packages
+            .iter()
+            .all(|package| package.outcome.is_ok())
+        {
+            resume.finish()?;
+        }
+    }
+    Ok(report)
+}
+
+// Warns about packages resolving to a lower version than the lockfile has
+// for them, which is usually a typo in the config, unless `allow-downgrade`
+// is set.
+fn warn_downgrades(config: &NugetConfig, lockfile: Option<&Lockfile>, packages: &[LockedPackage]) {
+    let Some(lockfile) = lockfile else {
+        return;
+    };
+    if config.allow_downgrade.unwrap_or(false) {
+        return;
+    }
+    for package in packages {
+        let Ok(version) = package.version.parse::<NugetVersion>() else {
+            continue;
+        };
+        let locked = lockfile
+            .packages
+            .iter()
+            .filter(|locked| locked.name.eq_ignore_ascii_case(&package.name))
+            .filter_map(|locked| locked.version.parse::<NugetVersion>().ok())
+            .max();
+        if let Some(locked) = locked.filter(|locked| version < *locked) {
+            log::warn!(
+                "{} is downgrading from {locked} to {version}, set allow-downgrade if that's intended",
+                package.name
+            );
+        }
+    }
+}
+
+// The lockfile's packages, if it has every package the config lists with the
+// same requirement and nothing else the config doesn't ask for.
+fn frozen_packages(
+    config: &NugetConfig,
+    lockfile: &Lockfile,
+) -> Result<Option<Vec<LockedPackage>>, Box<dyn std::error::Error + Send + Sync>> {
+    for (name, package_ref) in &config.dependencies {
+        let requirement = config.resolve_version(name, package_ref)?;
+        if lockfile.locked_package(name, &requirement).is_none() {
+            log::debug!("The lockfile has no {name} for {requirement}");
+            return Ok(None);
+        }
+    }
+    let include_dependencies = config.include_dependencies.unwrap_or(false);
+    let stale = lockfile
+        .packages
+        .iter()
+        .any(|package| match &package.requirement {
+            Some(_) => !config
+                .dependencies
+                .keys()
+                .any(|name| name.eq_ignore_ascii_case(&package.name)),
+            None => !include_dependencies,
+        });
+    if stale {
+        return Ok(None);
+    }
+    Ok(Some(lockfile.packages.clone()))
+}
+
+// Applies a package's `extract` rules, returning the extracted files.
+fn extract_files(
+    file: &NugetPackageFile,
+    rules: &[ExtractRule],
+    config_dir: &Path,
+) -> Result<Vec<PathBuf>, Box<dyn std::error::Error + Send + Sync>> {
+    let mut extracted = Vec::new();
+    for rule in rules {
+        let filter = glob_filter(&[&rule.from])?;
+        let dest_dir = config_dir.join(&rule.to);
+        let options = ExtractOptions::default();
+        extracted.extend(extract_package_mapped(file, dest_dir, &options, |path| {
+            let entry_path = path.to_string_lossy().replace('\\', "/");
+            if !filter.is_match(entry_path) {
+                return None;
+            }
+            path.file_name().map(PathBuf::from)
+        })?);
+    }
+    Ok(extracted)
+}
+
+/// Moves the config's floating versions, like `1.2.*` or `>=1.2.0`, to the
+/// newest matching version when the feed has published one since the
+/// lockfile last recorded, and downloads the packages that changed. Meant to
+/// be run periodically to keep a cache fresh: packages with nothing newer are
+/// only looked up, not resolved, hashed or downloaded. The newest publish date
+/// seen is stored in the lockfile. Exact versions and transitive dependencies
+/// are left to `install_config`.
+#[cfg(feature = "blocking")]
+pub fn refresh_config<P: AsRef<Path>>(
+    config_path: P,
+) -> Result<InstallReport, Box<dyn std::error::Error + Send + Sync>> {
+    let config_path = config_path.as_ref();
+    let config = NugetConfig::from_path(config_path)?;
+    let packages_dir = config.packages_dir(config_path);
+    check_policies(&config, None, config.dependencies.keys())?;
+
+    let client = NugetClient::new();
+    let lockfile_path = Lockfile::path_for_config(config_path);
+    let existing = Lockfile::from_path(&lockfile_path)?;
+    let mut lockfile = existing.clone().unwrap_or_default();
+    let mut downloads = Vec::new();
+    for (name, package_ref) in &config.dependencies {
+        let requirement = config.resolve_version(name, package_ref)?;
+        if !is_floating(&requirement) {
+            continue;
+        }
+        let range: VersionRange = requirement.parse()?;
+        let versions = client.matching_versions(name, &range)?;
+        let newest_published = versions
+            .iter()
+            .filter_map(|(_, published)| published.as_deref())
+            .max_by(|a, b| normalize_timestamp(a).cmp(normalize_timestamp(b)))
+            .map(str::to_owned);
+
+        let locked = lockfile.locked_package(name, &requirement);
+        let last_seen = locked.and_then(|locked| locked.published.as_deref());
+        if let (Some(newest), Some(last_seen)) = (&newest_published, last_seen) {
+            if normalize_timestamp(newest) <= normalize_timestamp(last_seen) {
+                continue;
+            }
+        }
+
+        let version = versions
+            .iter()
+            .map(|(version, _)| version)
+            .max()
+            .ok_or_else(|| NugetError::NoMatchingVersion {
+                package_name: name.clone(),
+                requirement: requirement.clone(),
+            })?
+            .to_string();
+        let changed = locked
+            .map(|locked| locked.version != version)
+            .unwrap_or(true);
+        lockfile
+            .packages
+            .retain(|package| !package.name.eq_ignore_ascii_case(name));
+        lockfile.packages.push(LockedPackage {
+            name: name.clone(),
+            version: version.clone(),
+            requirement: Some(requirement),
+            published: newest_published,
+        });
+        if changed {
+            let pinned_hash = config.pinned_hash(name)?;
+            downloads.push((name.clone(), version, pinned_hash));
+        }
+    }
+    lockfile
+        .packages
+        .sort_by_key(|package| package.name.to_lowercase());
+    if existing.as_ref() != Some(&lockfile) {
+        lockfile.write(&lockfile_path)?;
+    }
+
+    Ok(download_all(&client, downloads, &packages_dir))
+}
+
+// Feeds report UTC times, either with a `Z` or `+00:00` suffix or none at all.
+// What's left compares in time order as text, fractional seconds included.
+#[cfg(feature = "blocking")]
+fn normalize_timestamp(timestamp: &str) -> &str {
+    timestamp
+        .strip_suffix('Z')
+        .or_else(|| timestamp.strip_suffix("+00:00"))
+        .unwrap_or(timestamp)
+}
+
+/// Rewrites the config's floating versions, like `1.2.*` or `>=1.2.0`, with
+/// the exact versions they resolve to, the locked ones if there's a lockfile.
+/// Comments and formatting are kept. Only the config's own dependencies are
+/// pinned, not those of configs it includes. `version-var` variables are
+/// pinned in the config's `[vars]` when every package using them resolves to
+/// the same version, `version-from-env` versions are left alone. Only TOML
+/// configs can be pinned. Returns the pinned packages.
+#[cfg(feature = "blocking")]
+pub fn pin_config<P: AsRef<Path>>(
+    config_path: P,
+) -> Result<Vec<LockedPackage>, Box<dyn std::error::Error + Send + Sync>> {
+    let config_path = config_path.as_ref();
+    if ConfigFormat::from_path(config_path)? != ConfigFormat::Toml {
+        return Err(Box::new(NugetError::InvalidConfig(format!(
+            "{}: only TOML configs can be pinned",
+            config_path.display()
+        ))));
+    }
+    let config = NugetConfig::from_path(config_path)?;
+    let client = NugetClient::new();
+    let lockfile = Lockfile::from_path(Lockfile::path_for_config(config_path))?;
+
+    let config_text = std::fs::read_to_string(config_path)?;
+    let mut document: toml_edit::DocumentMut = config_text.parse()?;
+    let mut pinned = Vec::new();
+    // The packages using each floating `version-var`, with their versions
+    let mut var_pins: BTreeMap<String, Vec<LockedPackage>> = BTreeMap::new();
+    if let Some(dependencies) = document
+        .get_mut("dependencies")
+        .and_then(toml_edit::Item::as_table_like_mut)
+    {
+        for (key, item) in dependencies.iter_mut() {
+            let name = key.get();
+            let Some(package_ref) = config.dependencies.get(name) else {
+                continue;
+            };
+            let requirement = config.resolve_version(name, package_ref)?;
+            if !is_floating(&requirement) {
+                continue;
+            }
+            let locked = lockfile
+                .as_ref()
+                .and_then(|lockfile| lockfile.locked_version(name, &requirement));
+            let version = match locked {
+                Some(version) => version.to_owned(),
+                None => resolve_requirement(&client, name, &requirement)?,
+            };
+            let package = LockedPackage {
+                name: name.to_owned(),
+                version: version.clone(),
+                requirement: Some(requirement),
+                published: None,
+            };
+            let value = match package_ref {
+                NugetPackageRef::Version(_) => item.as_value_mut(),
+                NugetPackageRef::Detailed(detailed) => {
+                    if let Some(var) = &detailed.version_var {
+                        var_pins.entry(var.clone()).or_default().push(package);
+                        continue;
+                    }
+                    if detailed.version_from_env.is_some() {
+                        log::warn!("Not pinning {name}, its version comes from the environment");
+                        continue;
+                    }
+                    item.as_table_like_mut()
+                        .and_then(|table| table.get_mut("version"))
+                        .and_then(toml_edit::Item::as_value_mut)
+                }
+            };
+            if let Some(value) = value {
+                replace_value(value, &version);
+                pinned.push(package);
+            }
+        }
+    }
+
+    for (var, packages) in var_pins {
+        let version = &packages[0].version;
+        if packages.iter().any(|package| &package.version != version) {
+            log::warn!(
+                "Not pinning \"{var}\", the packages using it resolve to different versions"
+            );
+            continue;
+        }
+        let value = document
+            .get_mut("vars")
+            .and_then(toml_edit::Item::as_table_like_mut)
+            .and_then(|vars| vars.get_mut(&var))
+            .and_then(toml_edit::Item::as_value_mut);
+        match value {
+            Some(value) => {
+                replace_value(value, version);
+                pinned.extend(packages);
+            }
+            None => log::warn!("Not pinning \"{var}\", it's defined in an included config"),
+        }
+    }
+
+    if !pinned.is_empty() {
+        std::fs::write(config_path, document.to_string())?;
+    }
+    Ok(pinned)
+}
+
+// Keeps the comments and whitespace around the value.
+#[cfg(feature = "blocking")]
+fn replace_value(value: &mut toml_edit::Value, version: &str) {
+    let decor = value.decor().clone();
+    *value = version.into();
+    *value.decor_mut() = decor;
+}
+
+/// The config with its dependencies and every other table sorted by key and
+/// its formatting normalized, in the config's own format, for stable diffs.
+/// Only formatting changes: the result reads back as the same config, minus
+/// its comments. Includes are left as they are, not merged in.
+#[cfg(feature = "blocking")]
+pub fn canonicalize_config<P: AsRef<Path>>(
+    config_path: P,
+) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+    let config_path = config_path.as_ref();
+    let text = std::fs::read_to_string(config_path)?;
+    let format = ConfigFormat::from_path(config_path)?;
+    // Only valid configs are canonicalized
+    format.parse::<NugetConfig>(&text)?;
+    format.canonicalize(&text)
+}
+
+/// Downloads the packages into `dir` on up to `concurrency` threads, without
+/// a config. Every package is attempted, failures are reported per package.
+#[cfg(feature = "blocking")]
+pub fn prefetch<P: AsRef<Path>>(
+    packages: &[PackageId],
+    dir: P,
+    concurrency: usize,
+) -> InstallReport {
+    let packages = packages
+        .iter()
+        .map(|package| (package.name.clone(), package.version.to_string(), None))
+        .collect();
+    download_all_with(&NugetClient::new(), packages, dir.as_ref(), concurrency)
+}
+
+/// Makes `dir` hold the packages: cached copies that match the feed's hash
+/// are kept, and only missing or invalid packages are downloaded, in
+/// parallel. `PackageInstall::outcome`'s `downloaded` tells the two apart.
+#[cfg(feature = "blocking")]
+pub fn ensure_packages<P: AsRef<Path>>(packages: &[PackageId], dir: P) -> InstallReport {
+    let packages: Vec<_> = packages
+        .iter()
+        .map(|package| (package.name.clone(), package.version.to_string(), None))
+        .collect();
+    download_all(&NugetClient::new(), packages, dir.as_ref())
+}
+
+/// Downloads the packages into `dest_dir` laid out like a V3 flat container:
+/// `{id}/{version}/{id}.{version}.nupkg`, lowercase, with the package's
+/// `.sha512` and `.nuspec` next to it and an `{id}/index.json` of every
+/// mirrored version. Versions are normalized the way flat containers expect,
+/// so `1.0` is mirrored as `1.0.0`. The directory can be served as a static
+/// feed or used as a `FeedSource::LocalDir`. Packages are verified against
+/// the feed's hash like any download, and one that fails doesn't stop the
+/// others.
+#[cfg(feature = "blocking")]
+pub fn mirror<P: AsRef<Path>>(packages: &[PackageId], dest_dir: P) -> InstallReport {
+    mirror_with_client(NugetClient::new(), packages, dest_dir)
+}
+
+/// Like `mirror`, but downloads through `client`, e.g. one set up for a
+/// private feed. Its file naming and sidecar settings are replaced with the
+/// mirror's.
+#[cfg(feature = "blocking")]
+pub fn mirror_with_client<P: AsRef<Path>>(
+    client: NugetClient,
+    packages: &[PackageId],
+    dest_dir: P,
+) -> InstallReport {
+    let dest_dir = dest_dir.as_ref();
+    let client = client
+        .with_file_naming(FileNaming::GlobalPackages)
+        .with_hash_sidecars(true);
+    let packages: Vec<_> = packages
+        .iter()
+        .map(|package| (package.name.clone(), package.version.normalized(), None))
+        .collect();
+    let mut report = download_all(&client, packages, dest_dir);
+
+    for package in &mut report.packages {
+        let Ok(result) = &package.outcome else {
+            continue;
+        };
+        if let Err(error) = write_mirror_nuspec(&package.name, &result.path) {
+            package.outcome = Err(error);
+        }
+    }
+    // Indexed once per id, after every version of it is in place
+    let mut indexes: HashMap<String, Result<(), String>> = HashMap::new();
+    for package in &mut report.packages {
+        if package.outcome.is_err() {
+            continue;
+        }
+        let id = package.name.to_lowercase();
+        let index = indexes.entry(id).or_insert_with_key(|id| {
+            write_flat_index(dest_dir, id).map_err(|error| error.to_string())
+        });
+        if let Err(error) = index {
+            package.outcome =
+                Err(format!("Couldn't write the index for {}: {error}", package.name).into());
+        }
+    }
+    report
+}
+
+// Flat containers serve the package's `.nuspec` as `{id}.nuspec`.
+#[cfg(feature = "blocking")]
+fn write_mirror_nuspec(
+    package_name: &str,
+    package_path: &Path,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let nuspec = read_nuspec(package_path)?;
+    let dir = package_path.parent().unwrap_or(Path::new(""));
+    std::fs::write(
+        dir.join(format!("{}.nuspec", package_name.to_lowercase())),
+        nuspec,
+    )?;
+    Ok(())
+}
+
+#[cfg(feature = "blocking")]
+#[derive(Serialize)]
+struct FlatIndex {
+    versions: Vec<String>,
+}
+
+// Lists every version in the mirror, not just the ones mirrored now.
+#[cfg(feature = "blocking")]
+fn write_flat_index(
+    dest_dir: &Path,
+    id: &str,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let mut versions: Vec<_> = list_versions(dest_dir, id)?
+        .into_iter()
+        .map(|version| match version.parse::<NugetVersion>() {
+            Ok(parsed) => parsed.normalized().to_lowercase(),
+            Err(_) => version.to_lowercase(),
+        })
+        .collect();
+    versions.sort_by_cached_key(|version| version.parse::<NugetVersion>().ok());
+    versions.dedup();
+    let index = FlatIndex { versions };
+    std::fs::write(
+        dest_dir.join(id).join("index.json"),
+        serde_json::to_string(&index)?,
+    )?;
+    Ok(())
+}
+
+/// Downloads every package a .NET `packages.lock.json` resolved into `dir`,
+/// verified against the lockfile's content hashes instead of the feed's, so
+/// no package metadata is fetched. A package that doesn't match fails with
+/// `NugetError::HashMismatch`. Errors reading the lockfile are returned,
+/// download failures are in the report.
+#[cfg(feature = "blocking")]
+pub fn process_dotnet_lockfile<P: AsRef<Path>, D: AsRef<Path>>(
+    path: P,
+    dir: D,
+) -> Result<InstallReport, Box<dyn std::error::Error + Send + Sync>> {
+    let text = std::fs::read_to_string(path)?;
+    let packages: Vec<_> = dotnet_lockfile_packages(&text)?
+        .into_iter()
+        .map(|(name, version, hash)| (name, version, Some(hash)))
+        .collect();
+    Ok(download_all(&NugetClient::new(), packages, dir.as_ref()))
+}
+
+/// `ensure_packages` for the packages in a hash manifest. Cached copies are
+/// verified against the manifest's hashes, so when nothing is missing the
+/// feed isn't contacted at all.
+#[cfg(feature = "blocking")]
+pub fn ensure_manifest<P: AsRef<Path>, M: AsRef<Path>>(
+    dir: P,
+    manifest_path: M,
+) -> Result<InstallReport, Box<dyn std::error::Error + Send + Sync>> {
+    let packages: Vec<_> = parse_manifest(manifest_path)?
+        .into_iter()
+        .map(|entry| {
+            let hash = PackageHash {
+                hash: entry.hash,
+                algorithm: HashAlgorithm::from_string(entry.algorithm),
+            };
+            (entry.id, entry.version, Some(hash))
+        })
+        .collect();
+    Ok(download_all(&NugetClient::new(), packages, dir.as_ref()))
+}
+
+// Reports the packages in the order given, however the downloads finish.
+// Packages with a pinned hash are verified against it instead of the feed.
+// Runs no more threads than `max_concurrent_downloads` lets download at once.
+#[cfg(feature = "blocking")]
+fn download_all(
+    client: &NugetClient,
+    packages: Vec<(String, String, Option<PackageHash>)>,
+    dir: &Path,
+) -> InstallReport {
+    download_all_with(client, packages, dir, max_concurrent_downloads())
+}
+
+#[cfg(feature = "blocking")]
+fn download_all_with(
+    client: &NugetClient,
+    packages: Vec<(String, String, Option<PackageHash>)>,
+    dir: &Path,
+    concurrency: usize,
+) -> InstallReport {
+    let next = AtomicUsize::new(0);
+    let threads = concurrency.clamp(1, packages.len().max(1));
+    let outcomes: Vec<_> = std::thread::scope(|scope| {
+        let handles: Vec<_> = (0..threads)
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut outcomes = Vec::new();
+                    loop {
+                        let index = next.fetch_add(1, Ordering::Relaxed);
+                        let Some((name, version, pinned_hash)) = packages.get(index) else {
+                            break;
+                        };
+                        let outcome = match pinned_hash {
+                            Some(hash) => {
+                                client.download_package_with_hash(name, version, dir, hash)
+                            }
+                            None => client.download_package_timed(name, version, dir),
+                        };
+                        outcomes.push((index, outcome));
+                    }
+                    outcomes
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .flat_map(|handle| handle.join().expect("download thread panicked"))
+            .collect()
+    });
+    ordered_report(packages, outcomes)
+}
+
+// Like `download_all`, with each package downloading in a task of its own and
+// at most `concurrency` at once. Packages `resume` has as finished aren't
+// checked again, the ones finished now are recorded in it.
+async fn download_all_async(
+    client: &Arc<AsyncClient>,
+    packages: Vec<(String, String, Option<PackageHash>)>,
+    dir: &Path,
+    concurrency: usize,
+    progress: Option<Arc<ProgressAggregator>>,
+    resume: Option<Arc<ResumeState>>,
+) -> InstallReport {
+    let semaphore = Arc::new(Semaphore::new(concurrency.max(1)));
+    let mut tasks = JoinSet::new();
+    for (index, (name, version, pinned_hash)) in packages.iter().cloned().enumerate() {
+        let client = client.clone();
+        let semaphore = semaphore.clone();
+        let dir = dir.to_owned();
+        let progress = progress.clone();
+        let resume = resume.clone();
+        tasks.spawn(async move {
+            let _permit = semaphore
+                .acquire()
+                .await
+                .expect("the semaphore is never closed");
+            let completed = resume
+                .as_ref()
+                .and_then(|resume| resume.completed(&name, &version));
+            let resumed = completed.is_some();
+            let outcome = match completed {
+                Some(completed) => Ok(completed),
+                None => {
+                    client
+                        .download_package(&name, &version, &dir, pinned_hash.as_ref())
+                        .await
+                }
+            };
+            if let (Some(resume), Ok(result), false) = (&resume, &outcome, resumed) {
+                if let Err(error) = resume.record(&name, &version, &result.path) {
+                    log::warn!("Couldn't record {name} {version} as finished: {error}");
+                }
+            }
+            if let Some(progress) = &progress {
+                let size = match &outcome {
+                    Ok(result) => tokio::fs::metadata(&result.path)
+                        .await
+                        .ok()
+                        .map(|metadata| metadata.len()),
+                    Err(_) => None,
+                };
+                progress.on_finished(&name, &version, size);
+            }
+            (index, outcome)
+        });
+    }
+    let mut outcomes = Vec::with_capacity(packages.len());
+    while let Some(outcome) = tasks.join_next().await {
+        outcomes.push(outcome.expect("download task panicked"));
+    }
+    ordered_report(packages, outcomes)
+}
+
+type DownloadOutcome = Result<DownloadResult, Box<dyn std::error::Error + Send + Sync>>;
+
+// Pairs each package with the outcome for its index.
+fn ordered_report(
+    packages: Vec<(String, String, Option<PackageHash>)>,
+    mut outcomes: Vec<(usize, DownloadOutcome)>,
+) -> InstallReport {
+    outcomes.sort_by_key(|(index, _)| *index);
+    let packages = packages
+        .into_iter()
+        .zip(outcomes)
+        .map(|((name, version, _), (_, outcome))| PackageInstall {
+            name,
+            version,
+            outcome,
+            extracted: Vec::new(),
+        })
+        .collect();
+    InstallReport { packages }
+}
+
+#[cfg(feature = "blocking")]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PackageStatus {
+    /// The cached package matches the feed's hash.
+    UpToDate,
+    /// Nothing for this package is cached.
+    Missing,
+    /// Only other versions of this package are cached.
+    VersionChanged { cached_versions: Vec<String> },
+    /// The cached package doesn't match the feed's hash.
+    HashMismatch,
+}
+
+#[cfg(feature = "blocking")]
+#[derive(Clone, Debug)]
+pub struct PackagePlan {
+    pub name: String,
+    pub version: String,
+    pub status: PackageStatus,
+}
+
+/// Compares what the config asks for with what's cached in `dir`, without
+/// downloading any packages. Only package metadata is fetched.
+#[cfg(feature = "blocking")]
+pub fn plan_config<P: AsRef<Path>, D: AsRef<Path>>(
+    config_path: P,
+    dir: D,
+) -> Result<Vec<PackagePlan>, Box<dyn std::error::Error + Send + Sync>> {
+    let config_path = config_path.as_ref();
+    let config = NugetConfig::from_path(config_path)?;
+    let dir = dir.as_ref();
+
+    let lockfile = Lockfile::from_path(Lockfile::path_for_config(config_path))?;
+    let packages = block_on(config.resolve_packages(&AsyncClient::new(), lockfile.as_ref()))??;
+    let client = NugetClient::new();
+    warn_downgrades(&config, lockfile.as_ref(), &packages);
+
+    let mut plans = Vec::with_capacity(packages.len());
+    for LockedPackage { name, version, .. } in packages {
+        let path = client.cache_path(&name, &version, dir);
+        let status = if path.exists() {
+            let matches = match config.pinned_hash(&name)? {
+                Some(hash) => client.file_matches_hash(&path, &hash)?,
+                None => client.package_matches_hash(&name, &version, &path)?,
+            };
+            if matches {
+                PackageStatus::UpToDate
+            } else {
+                PackageStatus::HashMismatch
+            }
+        } else {
+            let cached_versions = flat_versions(dir, &name)?;
+            if cached_versions.is_empty() {
+                PackageStatus::Missing
+            } else {
+                PackageStatus::VersionChanged { cached_versions }
+            }
+        };
+        plans.push(PackagePlan {
+            name,
+            version,
+            status,
+        });
+    }
+    Ok(plans)
+}
+
+#[cfg(feature = "blocking")]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ConfigProblem {
+    /// The dependency the problem is with, or `None` for the config as a
+    /// whole.
+    pub package: Option<String>,
+    pub message: String,
+}
+
+/// Checks that the config parses and that every package it lists exists on
+/// the feed, returning every problem found. Only package versions are
+/// fetched, nothing is downloaded. Transitive dependencies aren't checked.
+#[cfg(feature = "blocking")]
+pub fn validate_config<P: AsRef<Path>>(
+    config_path: P,
+) -> Result<Vec<ConfigProblem>, Box<dyn std::error::Error + Send + Sync>> {
+    let config_path = config_path.as_ref();
+    let config = match NugetConfig::from_path(config_path) {
+        Ok(config) => config,
+        Err(error) => {
+            // Name the broken dependencies if we can, the parse error only
+            // describes the first one
+            let mut problems: Vec<_> = NugetConfig::dependency_errors(config_path)
+                .into_iter()
+                .map(|(name, message)| ConfigProblem {
+                    package: Some(name),
+                    message,
+                })
+                .collect();
+            if problems.is_empty() {
+                problems.push(ConfigProblem {
+                    package: None,
+                    message: error.to_string(),
+                });
+            }
+            return Ok(problems);
+        }
+    };
+
+    let client = NugetClient::new();
+    let mut problems = Vec::new();
+    for (name, package_ref) in &config.dependencies {
+        let problem = |message: String| ConfigProblem {
+            package: Some(name.clone()),
+            message,
+        };
+        if let Err(error) = config.pinned_hash(name) {
+            problems.push(problem(error.to_string()));
+        }
+        let requirement = match config.resolve_version(name, package_ref) {
+            Ok(requirement) => requirement,
+            Err(error) => {
+                problems.push(problem(error.to_string()));
+                continue;
+            }
+        };
+        if is_floating(&requirement) {
+            let checked = requirement
+                .parse::<VersionRange>()
+                .map_err(|error| error.into())
+                .and_then(|range| client.resolve_version_range(name, &range));
+            if let Err(error) = checked {
+                problems.push(problem(error.to_string()));
+            }
+            continue;
+        }
+        if let Err(error) = requirement.parse::<NugetVersion>() {
+            problems.push(problem(error.to_string()));
+            continue;
+        }
+        match client.package_exists(name, &requirement) {
+            Ok(true) => {}
+            Ok(false) => problems.push(problem(format!(
+                "{name} {requirement} does not exist on the feed"
+            ))),
+            Err(error) => problems.push(problem(error.to_string())),
+        }
+    }
+    Ok(problems)
+}
@@ -1,144 +1,445 @@
+#![cfg_attr(not(feature = "blocking"), allow(dead_code, unused_imports))]
+
+#[cfg(any(feature = "blocking", feature = "async"))]
+mod async_client;
+mod bundle;
+#[cfg(feature = "blocking")]
+mod client;
+mod concurrency;
+#[cfg(any(feature = "blocking", feature = "async"))]
+mod config;
+mod content_store;
+mod error;
+mod extract;
+mod feed;
+mod framework;
+mod hash;
+#[cfg(any(feature = "blocking", feature = "async"))]
+mod install;
+mod lockfile;
+mod manifest;
+mod metadata;
+#[cfg(any(feature = "blocking", feature = "async"))]
+mod metadata_cache;
+mod nuget_config;
+mod package_id;
+mod policy;
+#[cfg(any(feature = "blocking", feature = "async"))]
+mod progress;
+#[cfg(feature = "blocking")]
+mod project;
+#[cfg(any(feature = "blocking", feature = "async"))]
+mod resume;
+mod retry;
+#[cfg(feature = "blocking")]
+mod stats;
+#[cfg(feature = "async")]
+mod stream;
+#[cfg(any(feature = "blocking", feature = "async"))]
+mod target;
+mod v3;
+mod version;
+
 use std::{
     collections::HashMap,
     fs::File,
-    io::{Read, Write},
+    io::Write,
+    ops::Deref,
     path::{Path, PathBuf},
+    time::Duration,
 };
 
-use serde::Deserialize;
-use sha2::{Digest, Sha512};
-use xml::{reader::XmlEvent, EventReader};
+#[cfg(feature = "blocking")]
+pub use bundle::export_bundle;
+pub use bundle::import_bundle;
+#[cfg(feature = "blocking")]
+pub use client::{
+    AuditNode, AuditStatus, LatestDownload, NugetClient, NugetClientBuilder, ResolvedPackage,
+    TempFileNaming, UnsupportedHashPolicy, UpdateResult, UpdateStatus, UrlRewriter,
+};
+pub use concurrency::{
+    max_concurrent_downloads, max_download_rate, set_max_concurrent_downloads,
+    set_max_download_rate,
+};
+pub use content_store::{ContentEntry, ContentManifest, ContentStore};
+pub use error::NugetError;
+pub use extract::{
+    extract_entry, extract_entry_to_writer, extract_package, extract_package_files,
+    extract_package_mapped, glob_filter, list_package_contents, EntryFilter, ExtractOptions,
+    PackageEntry,
+};
+pub use feed::{list_cached_packages, FeedSource};
+pub use hash::{hash_buffer_size, set_hash_buffer_size};
+#[cfg(feature = "blocking")]
+pub use install::{
+    canonicalize_config, ensure_manifest, ensure_packages, install_config, install_config_with,
+    mirror, mirror_with_client, pin_config, plan_config, prefetch, process_dotnet_lockfile,
+    process_nuget, process_nuget_with, process_packages_config, process_project_references,
+    process_requirements, refresh_config, validate_config, ConfigProblem, PackagePlan,
+    PackageStatus,
+};
+#[cfg(feature = "async")]
+pub use install::{install_config_async, process_nuget_async};
+#[cfg(any(feature = "blocking", feature = "async"))]
+pub use install::{InstallMode, InstallOptions, InstallReport, LockfileMode, PackageInstall};
+pub use lockfile::{LockedPackage, Lockfile};
+pub use manifest::{
+    parse_manifest, verify_against_manifest, ManifestEntry, ManifestStatus, ManifestVerification,
+};
+pub use metadata::{DependencyGroup, PackageDependency, PackageMetadata, PackageTimestamps};
+pub use nuget_config::{PackageSource, PackageSources, SourceCredentials};
+pub use package_id::PackageId;
+pub use policy::PackagePolicy;
+#[cfg(any(feature = "blocking", feature = "async"))]
+pub use progress::InstallProgress;
+pub use retry::{RetryDelays, RetryPolicy};
+#[cfg(feature = "blocking")]
+pub use stats::ClientStats;
+#[cfg(feature = "async")]
+pub use stream::download_package_stream_async;
+pub use v3::{
+    AlternatePackage, PackageDeprecation, PackageVulnerability, SearchResult, ServiceIndex,
+    ServiceResource, VulnerabilitySeverity,
+};
+pub use version::{compare_versions, NugetVersion, VersionRange};
 
 pub struct NugetPackageFile {
     pub stem: String,
     pub file: File,
 }
 
+/// How long each phase of a `download_package_timed` call took. Phases that
+/// didn't run, e.g. the download for an up to date cached package, are zero.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PackageTimings {
+    /// Fetching the package metadata and hash from the feed.
+    pub metadata: Duration,
+    /// Connecting and waiting for the download's response headers, including
+    /// DNS resolution, TLS, redirects and retries.
+    pub request: Duration,
+    /// Receiving the package body.
+    pub download: Duration,
+    /// Hashing the cached package.
+    pub hashing: Duration,
+}
+
+/// How far along a package download is, reported to the client's progress
+/// callback as the body arrives.
+#[derive(Clone, Copy, Debug)]
+pub struct DownloadProgress<'a> {
+    pub package_name: &'a str,
+    pub version: &'a str,
+    /// Bytes received so far. Starts over from zero when a download is
+    /// retried.
+    pub downloaded: u64,
+    /// The size of the package, if the feed says.
+    pub total: Option<u64>,
+}
+
+pub struct DownloadResult {
+    pub file: NugetPackageFile,
+    /// Whether the package was downloaded, rather than found in the cache.
+    pub downloaded: bool,
+    /// Where the package is cached, which is under the fallback cache
+    /// directory when the download directory isn't writable.
+    pub path: PathBuf,
+    pub timings: PackageTimings,
+    /// The hash the package was checked against, `None` if it wasn't, e.g.
+    /// when the feed reports no hash.
+    pub verified_hash: Option<PackageHash>,
+}
+
+/// A downloaded package file that knows which package it is. Derefs to the
+/// `File`, so it can be used wherever the file is.
+pub struct PackageFile {
+    pub id: PackageId,
+    pub path: PathBuf,
+    /// The SHA512 of the cached file.
+    pub hash: PackageHash,
+    file: File,
+}
+
+impl PackageFile {
+    pub fn into_file(self) -> File {
+        self.file
+    }
+}
+
+impl Deref for PackageFile {
+    type Target = File;
+
+    fn deref(&self) -> &File {
+        &self.file
+    }
+}
+
+impl From<PackageFile> for NugetPackageFile {
+    fn from(package_file: PackageFile) -> Self {
+        let version = package_file.id.version.to_string();
+        NugetPackageFile {
+            stem: get_package_file_stem(&package_file.id.name, &version),
+            file: package_file.file,
+        }
+    }
+}
+
+#[cfg(feature = "blocking")]
 pub fn download_package_bytes(
     package_name: &str,
     version: &str,
 ) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
-    let url = format!("https://www.nuget.org/api/v2/package/{package_name}/{version}");
-    let bytes: Vec<u8> = reqwest::blocking::get(url)?
-        .bytes()?
-        .iter()
-        .map(|x| *x)
-        .collect();
-    Ok(bytes)
+    NugetClient::new()
+        .download_package_bytes(package_name, version)
+        .map_err(legacy_error)
 }
 
+#[cfg(feature = "blocking")]
+pub fn download_package_bytes_verified(
+    package_name: &str,
+    version: &str,
+) -> Result<(Vec<u8>, PackageHash), Box<dyn std::error::Error + Send + Sync>> {
+    NugetClient::new().download_package_bytes_verified(package_name, version)
+}
+
+#[cfg(feature = "blocking")]
+pub fn download_package_to_writer<W: Write>(
+    package_name: &str,
+    version: &str,
+    writer: &mut W,
+) -> Result<u64, Box<dyn std::error::Error + Send + Sync>> {
+    NugetClient::new().download_package_to_writer(package_name, version, writer)
+}
+
+#[cfg(feature = "blocking")]
 pub fn download_package_overwrite<P: AsRef<Path>>(
     package_name: &str,
     version: &str,
     download_dir: P,
 ) -> Result<NugetPackageFile, Box<dyn std::error::Error>> {
-    let download_dir = download_dir.as_ref();
-    let bytes = download_package_bytes(package_name, version)?;
-    std::fs::create_dir_all(download_dir)?;
-    let package_file_name = get_package_file_name(package_name, version);
-    let path = {
-        let mut path = download_dir.to_owned();
-        path.push(package_file_name);
-        path
-    };
-    let file = {
-        let mut file = File::create(path)?;
-        file.write_all(&bytes)?;
-        file
-    };
-    Ok(NugetPackageFile {
-        stem: get_package_file_stem(package_name, version),
-        file,
-    })
+    NugetClient::new()
+        .download_package_overwrite(package_name, version, download_dir)
+        .map_err(legacy_error)
 }
 
+#[cfg(feature = "blocking")]
+pub fn download_package_id<P: AsRef<Path>>(
+    package_id: &PackageId,
+    download_dir: P,
+) -> Result<NugetPackageFile, Box<dyn std::error::Error + Send + Sync>> {
+    NugetClient::new().download_package_id(package_id, download_dir)
+}
+
+#[cfg(feature = "blocking")]
+pub fn download_and_extract<P: AsRef<Path>, F: EntryFilter>(
+    package_name: &str,
+    version: &str,
+    dest_dir: P,
+    filter: F,
+    keep_package: bool,
+) -> Result<Vec<PathBuf>, Box<dyn std::error::Error + Send + Sync>> {
+    NugetClient::new().download_and_extract(package_name, version, dest_dir, filter, keep_package)
+}
+
+#[cfg(feature = "blocking")]
+pub fn copy_cached_package<P: AsRef<Path>, D: AsRef<Path>>(
+    package_name: &str,
+    version: &str,
+    cache_dir: P,
+    dest: D,
+) -> Result<PathBuf, Box<dyn std::error::Error + Send + Sync>> {
+    NugetClient::new().copy_cached_package(package_name, version, cache_dir, dest)
+}
+
+#[cfg(feature = "blocking")]
+pub fn download_matching_package<P: AsRef<Path>>(
+    package_name: &str,
+    requirement: &str,
+    download_dir: P,
+) -> Result<PackageFile, Box<dyn std::error::Error + Send + Sync>> {
+    NugetClient::new().download_matching_package(package_name, requirement, download_dir)
+}
+
+#[cfg(feature = "blocking")]
+pub fn download_package_file<P: AsRef<Path>>(
+    package_id: &PackageId,
+    download_dir: P,
+) -> Result<PackageFile, Box<dyn std::error::Error + Send + Sync>> {
+    NugetClient::new().download_package_file(package_id, download_dir)
+}
+
+#[cfg(feature = "blocking")]
+pub fn download_package_timed<P: AsRef<Path>>(
+    package_name: &str,
+    version: &str,
+    download_dir: P,
+) -> Result<DownloadResult, Box<dyn std::error::Error + Send + Sync>> {
+    NugetClient::new().download_package_timed(package_name, version, download_dir)
+}
+
+#[cfg(feature = "blocking")]
+pub fn download_package_with_hash<P: AsRef<Path>>(
+    package_name: &str,
+    version: &str,
+    download_dir: P,
+    hash: &PackageHash,
+) -> Result<DownloadResult, Box<dyn std::error::Error + Send + Sync>> {
+    NugetClient::new().download_package_with_hash(package_name, version, download_dir, hash)
+}
+
+#[cfg(feature = "blocking")]
 pub fn download_package<P: AsRef<Path>>(
     package_name: &str,
     version: &str,
     download_dir: P,
 ) -> Result<NugetPackageFile, Box<dyn std::error::Error>> {
-    let download_dir = download_dir.as_ref();
+    NugetClient::new()
+        .download_package(package_name, version, download_dir)
+        .map_err(legacy_error)
+}
 
-    // Get the download file path
-    let package_file_name = get_package_file_name(package_name, version);
-    let path = {
-        let mut path = download_dir.to_owned();
-        path.push(package_file_name);
-        path
-    };
-    println!("{:?}", path);
+/// Where a client with `file_naming` caches the package inside
+/// `download_dir`, see `NugetClient::cache_path`. Nothing is checked on disk
+/// or over the network.
+pub fn cache_path<P: AsRef<Path>>(
+    package_name: &str,
+    version: &str,
+    download_dir: P,
+    file_naming: FileNaming,
+) -> PathBuf {
+    download_dir
+        .as_ref()
+        .join(file_naming.package_path(package_name, version))
+}
 
-    // First check if the file is already there
-    let matches = if path.exists() {
-        // Treat any failures as a failing match
-        package_matches_hash(package_name, version, &path).unwrap_or(false)
-    } else {
-        false
-    };
+// How long a default-named temp file from another process has to go
+// unmodified before it's considered abandoned. Live downloads keep writing,
+// and stalled ones time out long before this.
+const STALE_TEMP_FILE_AGE: Duration = Duration::from_secs(60 * 60);
 
-    let file = if !matches {
-        download_package_overwrite(package_name, version, download_dir)?
-    } else {
-        let stem = get_package_file_stem(package_name, version);
-        NugetPackageFile {
-            stem,
-            file: File::open(&path)?,
-        }
+pub(crate) fn default_temp_file_name(package_file_name: &str) -> String {
+    format!("{package_file_name}.download-{}", std::process::id())
+}
+
+// Removes the package's default-named temp files other processes abandoned.
+// Best effort, failures are only logged.
+pub(crate) fn remove_stale_temp_files(temp_dir: &Path, package_file_name: &str) {
+    let Ok(entries) = std::fs::read_dir(temp_dir) else {
+        return;
     };
-    Ok(file)
+    let prefix = format!("{package_file_name}.download-");
+    let own = default_temp_file_name(package_file_name);
+    for entry in entries.filter_map(Result::ok) {
+        let file_name = entry.file_name();
+        let Some(file_name) = file_name.to_str() else {
+            continue;
+        };
+        let is_temp = file_name
+            .strip_prefix(&prefix)
+            .is_some_and(|pid| !pid.is_empty() && pid.bytes().all(|byte| byte.is_ascii_digit()));
+        if !is_temp || file_name == own {
+            continue;
+        }
+        let stale = entry
+            .metadata()
+            .and_then(|metadata| metadata.modified())
+            .ok()
+            .and_then(|modified| modified.elapsed().ok())
+            .is_some_and(|age| age >= STALE_TEMP_FILE_AGE);
+        if stale {
+            match std::fs::remove_file(entry.path()) {
+                Ok(()) => log::debug!("Removed the stale temp file {}", entry.path().display()),
+                Err(error) => log::debug!("Couldn't remove {}: {error}", entry.path().display()),
+            }
+        }
+    }
 }
 
-fn get_package_file_stem(package_name: &str, version: &str) -> String {
+// Where `with_trust_unchanged_files` records a verified package.
+pub fn get_package_file_stem(package_name: &str, version: &str) -> String {
     format!("{package_name}.{version}")
 }
 
-fn get_package_file_name(package_name: &str, version: &str) -> String {
+/// Always ends in `.nupkg`, whatever URL the package was downloaded from.
+pub fn get_package_file_name(package_name: &str, version: &str) -> String {
     format!("{package_name}.{version}.nupkg")
 }
 
-fn package_matches_hash<P: AsRef<Path>>(
-    package_name: &str,
-    version: &str,
-    package_file: P,
-) -> Result<bool, Box<dyn std::error::Error>> {
-    // Get the hash from nuget.org
-    let hash = get_package_hash(package_name, version)?;
-    let reference_hash = base64::decode(&hash.hash)?;
-
-    let mut hasher = match &hash.algorithm {
-        HashAlgorithm::SHA512 => Sha512::new(),
-        HashAlgorithm::Unknown(_) => {
-            // We don't know how to handle this hashing algorithm,
-            // assume that it doesn't match.
-            return Ok(false);
-        }
+/// Checks that the start of a download is a package, which is a zip archive.
+/// Feeds sometimes serve an HTML error page with a success status.
+pub fn detect_package_format(bytes: &[u8]) -> Result<(), NugetError> {
+    const ZIP_SIGNATURE: &[u8] = b"PK\x03\x04";
+    if bytes.starts_with(ZIP_SIGNATURE) {
+        return Ok(());
+    }
+    let text = String::from_utf8_lossy(&bytes[..bytes.len().min(64)]);
+    let reason = if bytes.is_empty() {
+        "the response is empty".to_owned()
+    } else if text.trim_start().starts_with('<') {
+        "the response looks like an HTML or XML document".to_owned()
+    } else {
+        format!("the response doesn't start with a zip signature: {text:?}")
     };
+    Err(NugetError::InvalidPackage(reason))
+}
+
+// Deep package directories plus long package ids can exceed MAX_PATH, so
+// cache files are accessed through their `\\?\` extended-length form.
+#[cfg(windows)]
+pub(crate) fn long_path(path: &Path) -> PathBuf {
+    let absolute = match std::path::absolute(path) {
+        Ok(absolute) => absolute,
+        Err(_) => return path.to_owned(),
+    };
+    match absolute.to_str() {
+        Some(text) if text.starts_with(r"\\?\") => absolute,
+        Some(text) if text.starts_with(r"\\") => PathBuf::from(format!(r"\\?\UNC\{}", &text[2..])),
+        Some(text) => PathBuf::from(format!(r"\\?\{text}")),
+        None => absolute,
+    }
+}
 
-    // Get the hash from the existing file
-    let mut file = File::open(package_file)?;
-    let mut bytes = Vec::new();
-    file.read_to_end(&mut bytes)?;
-    hasher.update(&bytes);
-    let file_hash = hasher.finalize();
-
-    // Compare the hashes
-    let reference_iter = reference_hash.iter();
-    let actual_iter = file_hash.iter();
-    for (reference, actual) in reference_iter.zip(actual_iter) {
-        if *reference != *actual {
-            return Ok(false);
+#[cfg(not(windows))]
+pub(crate) fn long_path(path: &Path) -> PathBuf {
+    path.to_owned()
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FileNaming {
+    /// `{id}.{version}.nupkg` directly inside the download directory.
+    #[default]
+    Flat,
+    /// `{id}/{version}/{id}.{version}.nupkg` with the id and version
+    /// lowercased, the layout used by NuGet's global packages folder.
+    GlobalPackages,
+}
+
+impl FileNaming {
+    /// The path of the package file relative to the download directory.
+    pub fn package_path(&self, package_name: &str, version: &str) -> PathBuf {
+        match self {
+            FileNaming::Flat => PathBuf::from(get_package_file_name(package_name, version)),
+            FileNaming::GlobalPackages => {
+                let package_name = package_name.to_lowercase();
+                let version = version.to_lowercase();
+                let mut path = PathBuf::from(&package_name);
+                path.push(&version);
+                path.push(get_package_file_name(&package_name, &version));
+                path
+            }
         }
     }
-    Ok(true)
 }
 
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct PackageHash {
     pub hash: String,
     pub algorithm: HashAlgorithm,
 }
 
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub enum HashAlgorithm {
     SHA512,
+    SHA256,
     Unknown(String),
 }
 
@@ -146,122 +447,227 @@ impl HashAlgorithm {
     pub fn from_string(string: String) -> Self {
         match string.as_str() {
             "SHA512" | "sha512" => Self::SHA512,
+            "SHA256" | "sha256" => Self::SHA256,
             _ => Self::Unknown(string),
         }
     }
+
+    /// The name feeds use for the algorithm.
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::SHA512 => "SHA512",
+            Self::SHA256 => "SHA256",
+            Self::Unknown(algorithm) => algorithm,
+        }
+    }
 }
 
+#[cfg(feature = "blocking")]
 pub fn get_package_hash(
     package_name: &str,
     version: &str,
 ) -> Result<PackageHash, Box<dyn std::error::Error>> {
-    let url =
-        format!("https://www.nuget.org/api/v2/Packages(Id='{package_name}',Version='{version}')");
-    println!("{:#?}", url);
-    let text = reqwest::blocking::get(url)?.text()?;
-    println!("{:#?}", text);
-
-    let parser = EventReader::from_str(&text);
-    let mut event_iter = parser.into_iter();
-    let mut package_hash = None;
-    let mut package_hash_algorithm = None;
-    while let Some(e) = event_iter.next() {
-        match e {
-            Ok(XmlEvent::StartElement { name, .. }) => match name.local_name.as_str() {
-                "PackageHash" => {
-                    let next_text = get_text(event_iter.next().unwrap().unwrap()).unwrap();
-                    package_hash = Some(next_text);
-                }
-                "PackageHashAlgorithm" => {
-                    let next_text = get_text(event_iter.next().unwrap().unwrap()).unwrap();
-                    package_hash_algorithm = Some(next_text);
-                }
-                _ => {}
-            },
-            _ => {}
-        }
-    }
+    NugetClient::new()
+        .get_package_hash(package_name, version)
+        .map_err(legacy_error)
+}
 
-    let package_hash = package_hash.expect("Package hash not found!");
-    let package_hash_algorithm = HashAlgorithm::from_string(
-        package_hash_algorithm.expect("Package hash algorithm not found!"),
-    );
+#[cfg(feature = "blocking")]
+pub fn get_package_metadata(
+    package_name: &str,
+    version: &str,
+) -> Result<PackageMetadata, Box<dyn std::error::Error + Send + Sync>> {
+    NugetClient::new().get_package_metadata(package_name, version)
+}
 
-    Ok(PackageHash {
-        hash: package_hash,
-        algorithm: package_hash_algorithm,
-    })
+#[cfg(feature = "blocking")]
+pub fn get_package_timestamps(
+    package_name: &str,
+    version: &str,
+) -> Result<PackageTimestamps, Box<dyn std::error::Error + Send + Sync>> {
+    NugetClient::new().get_package_timestamps(package_name, version)
 }
 
-fn get_text(event: XmlEvent) -> Option<String> {
-    match event {
-        XmlEvent::Characters(string) => Some(string),
-        _ => None,
-    }
+#[cfg(feature = "blocking")]
+pub fn get_raw_metadata(
+    package_name: &str,
+    version: &str,
+) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+    NugetClient::new().get_raw_metadata(package_name, version)
+}
+
+#[cfg(feature = "blocking")]
+pub fn get_packages_metadata(
+    packages: &[(&str, &str)],
+) -> Result<HashMap<(String, String), PackageMetadata>, Box<dyn std::error::Error + Send + Sync>> {
+    NugetClient::new().get_packages_metadata(packages)
+}
+
+#[cfg(feature = "blocking")]
+pub fn list_package_versions(
+    package_name: &str,
+    include_unlisted: bool,
+) -> Result<Vec<String>, Box<dyn std::error::Error + Send + Sync>> {
+    NugetClient::new().list_package_versions(package_name, include_unlisted)
+}
+
+#[cfg(feature = "blocking")]
+pub fn resolve_dependencies(
+    package_name: &str,
+    version: &str,
+    target_framework: Option<&str>,
+) -> Result<Vec<ResolvedPackage>, Box<dyn std::error::Error + Send + Sync>> {
+    NugetClient::new().resolve_dependencies(package_name, version, target_framework)
 }
 
+#[cfg(feature = "blocking")]
+pub fn download_dependencies<P: AsRef<Path>>(
+    package_name: &str,
+    version: &str,
+    target_framework: Option<&str>,
+    download_dir: P,
+) -> Result<Vec<DownloadResult>, Box<dyn std::error::Error + Send + Sync>> {
+    NugetClient::new().download_dependencies(package_name, version, target_framework, download_dir)
+}
+
+#[cfg(feature = "blocking")]
+pub fn audit_closure(
+    package_name: &str,
+    version: &str,
+    target_framework: Option<&str>,
+) -> AuditNode {
+    NugetClient::new().audit_closure(package_name, version, target_framework)
+}
+
+#[cfg(feature = "blocking")]
+pub fn resolve_latest_version(
+    package_name: &str,
+    include_prerelease: bool,
+) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+    NugetClient::new().resolve_latest_version(package_name, include_prerelease)
+}
+
+#[cfg(feature = "blocking")]
+pub fn download_latest_batch<P: AsRef<Path>>(
+    package_names: &[&str],
+    download_dir: P,
+    include_prerelease: bool,
+) -> Vec<LatestDownload> {
+    NugetClient::new().download_latest_batch(package_names, download_dir, include_prerelease)
+}
+
+#[cfg(feature = "blocking")]
+pub fn update_latest_package<P: AsRef<Path>>(
+    package_name: &str,
+    download_dir: P,
+    include_prerelease: bool,
+) -> Result<UpdateResult, Box<dyn std::error::Error + Send + Sync>> {
+    NugetClient::new().update_latest_package(package_name, download_dir, include_prerelease)
+}
+
+#[cfg(feature = "blocking")]
+pub fn search_packages(
+    query: &str,
+    take: usize,
+    include_prerelease: bool,
+) -> Result<Vec<SearchResult>, Box<dyn std::error::Error + Send + Sync>> {
+    NugetClient::new().search_packages(query, take, include_prerelease)
+}
+
+/// Downloads the packages into `packages_subdir()` inside the crate being
+/// built, returning a `PackageFile` for each. Versions can be anything
+/// `download_matching_package` takes, e.g. `"1.2.*"` or `"latest"`, the
+/// files' ids have the versions they resolved to.
+#[cfg(feature = "blocking")]
 #[macro_export]
 macro_rules! nuget_packages {
     ( $( { $name:literal , $version:literal } ),* $(,)* ) => (
         {
-            let manifest_dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR"));
-            let packages_dir = {
-                let mut packages_dir = manifest_dir.to_owned();
-                packages_dir.push("packages");
-                packages_dir
+            let packages_dir = match option_env!("CARGO_MANIFEST_DIR") {
+                Some(manifest_dir) => {
+                    let mut packages_dir = std::path::Path::new(manifest_dir).to_owned();
+                    packages_dir.push(nuget_dl::packages_subdir());
+                    packages_dir
+                }
+                None => nuget_dl::get_default_package_dir(),
+            };
+
+            let download_packages = || -> std::result::Result<Vec<nuget_dl::PackageFile>, Box<dyn std::error::Error + Send + Sync>> {
+                let mut files = Vec::new();
+                $( files.push(nuget_dl::download_matching_package($name, $version, &packages_dir)?); )*
+                Ok(files)
+            };
+            download_packages().map_err(|error| -> Box<dyn std::error::Error> { error })
+        }
+    );
+    ( $( $package_id:literal ),* $(,)* ) => (
+        {
+            let packages_dir = match option_env!("CARGO_MANIFEST_DIR") {
+                Some(manifest_dir) => {
+                    let mut packages_dir = std::path::Path::new(manifest_dir).to_owned();
+                    packages_dir.push(nuget_dl::packages_subdir());
+                    packages_dir
+                }
+                None => nuget_dl::get_default_package_dir(),
             };
 
-            let download_packages = || -> std::result::Result<Vec<nuget_dl::NugetPackageFile>, Box<dyn std::error::Error>> {
+            let download_packages = || -> std::result::Result<Vec<nuget_dl::PackageFile>, Box<dyn std::error::Error + Send + Sync>> {
                 let mut files = Vec::new();
-                $( files.push(nuget_dl::download_package($name, $version, &packages_dir)?); )*
+                $( files.push(nuget_dl::download_package_file(&$package_id.parse()?, &packages_dir)?); )*
                 Ok(files)
             };
-            download_packages()
+            download_packages().map_err(|error| -> Box<dyn std::error::Error> { error })
         }
     )
 }
 
-#[derive(Deserialize)]
-#[serde(rename_all = "kebab-case")]
-struct NugetConfig {
-    packages_dir: Option<PathBuf>,
-    dependencies: HashMap<String, NugetPackageRef>,
+// The functions that were here before `NugetClient` keep returning a plain
+// `Box<dyn Error>`, so callers' `?` into their own `Box<dyn Error>` still
+// compiles. `NugetClient` returns errors that can be sent across threads.
+pub(crate) fn legacy_error(
+    error: Box<dyn std::error::Error + Send + Sync>,
+) -> Box<dyn std::error::Error> {
+    error
 }
 
-#[derive(Deserialize)]
-#[serde(untagged)]
-enum NugetPackageRef {
-    Version(String),
+/// The name of the directory packages go in when no directory is given.
+pub const DEFAULT_PACKAGES_SUBDIR: &str = "packages";
+
+/// The environment variable that overrides `DEFAULT_PACKAGES_SUBDIR`, e.g.
+/// set to `.nuget-cache` through `[env]` in `.cargo/config.toml`.
+pub const PACKAGES_SUBDIR_ENV: &str = "NUGET_DL_PACKAGES_SUBDIR";
+
+/// The name of the directory `nuget_packages!`, `process_nuget` and
+/// `get_default_package_dir` put packages in: `PACKAGES_SUBDIR_ENV` when it's
+/// set to a plain directory name, `packages` otherwise.
+pub fn packages_subdir() -> String {
+    match std::env::var(PACKAGES_SUBDIR_ENV) {
+        Ok(name) if is_plain_dir_name(&name) => name,
+        Ok(name) => {
+            log::warn!("Ignoring {PACKAGES_SUBDIR_ENV}={name:?}, expected a directory name");
+            DEFAULT_PACKAGES_SUBDIR.to_owned()
+        }
+        Err(_) => DEFAULT_PACKAGES_SUBDIR.to_owned(),
+    }
 }
 
-fn get_default_package_dir() -> PathBuf {
-    let temp = std::env::var("CARGO_MANIFEST_DIR").unwrap();
-    let manifest_dir = std::path::Path::new(&temp);
-    let mut packages_dir = manifest_dir.to_owned();
-    packages_dir.push("packages");
-    packages_dir
+fn is_plain_dir_name(name: &str) -> bool {
+    let mut components = Path::new(name).components();
+    matches!(
+        (components.next(), components.next()),
+        (Some(std::path::Component::Normal(_)), None)
+    )
 }
 
-pub fn process_nuget<P: AsRef<Path>>(
-    config_path: P,
-) -> Result<Vec<NugetPackageFile>, Box<dyn std::error::Error>> {
-    let config_text = std::fs::read_to_string(config_path)?;
-    let config: NugetConfig = toml::from_str(&config_text)?;
-
-    let packages_dir = if let Some(packages_dir) = config.packages_dir {
-        packages_dir
-    } else {
-        get_default_package_dir()
+/// `packages_subdir()` inside the crate being built, or inside the current
+/// directory when not running under cargo.
+pub fn get_default_package_dir() -> PathBuf {
+    let mut packages_dir = match std::env::var_os("CARGO_MANIFEST_DIR") {
+        Some(manifest_dir) => PathBuf::from(manifest_dir),
+        None => std::env::current_dir().unwrap_or_else(|_| PathBuf::from(".")),
     };
-
-    let mut files = Vec::new();
-    for (name, package_ref) in config.dependencies {
-        let file = match package_ref {
-            NugetPackageRef::Version(version) => download_package(&name, &version, &packages_dir)?,
-        };
-        files.push(file);
-    }
-    Ok(files)
+    packages_dir.push(packages_subdir());
+    packages_dir
 }
 
 pub fn unpack_nuget_file(file: &NugetPackageFile) -> Result<(), Box<dyn std::error::Error>> {
@@ -285,8 +691,7 @@ pub fn unpack_nuget_file_to_dir<P: AsRef<Path>>(
         std::fs::create_dir(&unpacked_dir)?;
     }
 
-    let mut archive = zip::ZipArchive::new(&file.file).unwrap();
-    archive.extract(unpacked_dir)?;
+    extract_package(file, unpacked_dir, &ExtractOptions::default()).map_err(legacy_error)?;
 
     Ok(())
 }
@@ -0,0 +1,75 @@
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+/// The exact versions a config resolved to, written next to the config as
+/// `<config name>.lock`, e.g. `nuget.lock` for `nuget.toml`. Floating versions
+/// like `1.2.*` keep using the locked version for as long as the config's
+/// requirement doesn't change.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Lockfile {
+    #[serde(default, rename = "package")]
+    pub packages: Vec<LockedPackage>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LockedPackage {
+    pub name: String,
+    pub version: String,
+    /// The version from the config, e.g. `1.2.*`. `None` for transitive
+    /// dependencies.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub requirement: Option<String>,
+    /// The newest publish date seen for versions matching a floating
+    /// requirement, recorded by `refresh_config`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub published: Option<String>,
+}
+
+impl Lockfile {
+    pub fn path_for_config(config_path: &Path) -> PathBuf {
+        config_path.with_extension("lock")
+    }
+
+    /// Reads the lockfile, returning `None` if it doesn't exist.
+    pub fn from_path<P: AsRef<Path>>(
+        path: P,
+    ) -> Result<Option<Self>, Box<dyn std::error::Error + Send + Sync>> {
+        let path = path.as_ref();
+        if !path.exists() {
+            return Ok(None);
+        }
+        let text = std::fs::read_to_string(path)?;
+        Ok(Some(toml::from_str(&text)?))
+    }
+
+    pub fn write<P: AsRef<Path>>(
+        &self,
+        path: P,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let text = format!(
+            "# Generated by nuget-dl, do not edit.\n{}",
+            toml::to_string(self)?
+        );
+        std::fs::write(path, text)?;
+        Ok(())
+    }
+
+    /// The locked version for a package listed in the config, if its
+    /// requirement hasn't changed since it was locked.
+    pub fn locked_version(&self, package_name: &str, requirement: &str) -> Option<&str> {
+        self.locked_package(package_name, requirement)
+            .map(|package| package.version.as_str())
+    }
+
+    pub(crate) fn locked_package(
+        &self,
+        package_name: &str,
+        requirement: &str,
+    ) -> Option<&LockedPackage> {
+        self.packages.iter().find(|package| {
+            package.name.eq_ignore_ascii_case(package_name)
+                && package.requirement.as_deref() == Some(requirement)
+        })
+    }
+}
@@ -0,0 +1,131 @@
+use std::path::Path;
+
+use serde::Deserialize;
+
+use crate::{cache_path, hash::hash_file, long_path, FileNaming, HashAlgorithm, NugetError};
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct ManifestEntry {
+    pub id: String,
+    pub version: String,
+    pub algorithm: String,
+    /// Base64, the same encoding the feeds use.
+    pub hash: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ManifestStatus {
+    Verified,
+    Missing,
+    Mismatch,
+    UnsupportedAlgorithm,
+}
+
+#[derive(Clone, Debug)]
+pub struct ManifestVerification {
+    pub entry: ManifestEntry,
+    pub status: ManifestStatus,
+}
+
+/// Parses a hash manifest. Manifests ending in `.json` are a JSON array of
+/// `{ "id", "version", "algorithm", "hash" }` objects. Anything else is the
+/// text format: one `id version algorithm hash` entry per line, with blank
+/// lines and lines starting with `#` ignored.
+pub fn parse_manifest<P: AsRef<Path>>(
+    manifest_path: P,
+) -> Result<Vec<ManifestEntry>, Box<dyn std::error::Error + Send + Sync>> {
+    let manifest_path = manifest_path.as_ref();
+    let text = std::fs::read_to_string(manifest_path)?;
+    let is_json = manifest_path
+        .extension()
+        .map(|extension| extension.eq_ignore_ascii_case("json"))
+        .unwrap_or(false);
+    if is_json {
+        return Ok(serde_json::from_str(&text)?);
+    }
+    parse_manifest_text(&text, &manifest_path.display().to_string())
+}
+
+// `origin` names the manifest in error messages.
+pub(crate) fn parse_manifest_text(
+    text: &str,
+    origin: &str,
+) -> Result<Vec<ManifestEntry>, Box<dyn std::error::Error + Send + Sync>> {
+    let mut entries = Vec::new();
+    for (index, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        match fields.as_slice() {
+            [id, version, algorithm, hash] => entries.push(ManifestEntry {
+                id: id.to_string(),
+                version: version.to_string(),
+                algorithm: algorithm.to_string(),
+                hash: hash.to_string(),
+            }),
+            _ => {
+                return Err(Box::new(NugetError::InvalidConfig(format!(
+                    "{origin}:{}: expected `id version algorithm hash`",
+                    index + 1
+                ))))
+            }
+        }
+    }
+    Ok(entries)
+}
+
+/// Formats entries in the text manifest format `parse_manifest` reads.
+pub(crate) fn format_manifest(entries: &[ManifestEntry]) -> String {
+    let mut text = String::new();
+    for entry in entries {
+        text.push_str(&format!(
+            "{} {} {} {}\n",
+            entry.id, entry.version, entry.algorithm, entry.hash
+        ));
+    }
+    text
+}
+
+/// Verifies every package listed in the manifest against the copy cached in
+/// `dir`, without contacting any feed.
+pub fn verify_against_manifest<P: AsRef<Path>, M: AsRef<Path>>(
+    dir: P,
+    manifest_path: M,
+) -> Result<Vec<ManifestVerification>, Box<dyn std::error::Error + Send + Sync>> {
+    let dir = dir.as_ref();
+    let entries = parse_manifest(manifest_path)?;
+    let mut results = Vec::with_capacity(entries.len());
+    for entry in entries {
+        let status = verify_entry(dir, &entry)?;
+        results.push(ManifestVerification { entry, status });
+    }
+    Ok(results)
+}
+
+fn verify_entry(
+    dir: &Path,
+    entry: &ManifestEntry,
+) -> Result<ManifestStatus, Box<dyn std::error::Error + Send + Sync>> {
+    let path = long_path(&cache_path(
+        &entry.id,
+        &entry.version,
+        dir,
+        FileNaming::Flat,
+    ));
+    if !path.exists() {
+        return Ok(ManifestStatus::Missing);
+    }
+    let algorithm = HashAlgorithm::from_string(entry.algorithm.clone());
+    let actual = match hash_file(&path, &algorithm)? {
+        Some(actual) => actual,
+        None => return Ok(ManifestStatus::UnsupportedAlgorithm),
+    };
+    let expected = base64::decode(&entry.hash)?;
+    if actual == expected {
+        Ok(ManifestStatus::Verified)
+    } else {
+        Ok(ManifestStatus::Mismatch)
+    }
+}
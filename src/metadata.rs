@@ -0,0 +1,586 @@
+use std::collections::HashMap;
+
+use xml::{attribute::OwnedAttribute, reader::XmlEvent, EventReader};
+
+use crate::{
+    framework::{nearest_framework, TargetFramework},
+    v3::{CatalogLeaf, PackageDeprecation, PackageVulnerability},
+    HashAlgorithm, NugetError, NugetVersion, PackageHash,
+};
+
+#[derive(Clone, Debug)]
+pub struct PackageMetadata {
+    pub id: String,
+    pub version: String,
+    pub is_prerelease: bool,
+    /// Unlisted versions can still be downloaded, but should never be picked
+    /// when looking for the latest version of a package.
+    pub listed: bool,
+    /// The size of the `.nupkg` in bytes.
+    pub size: Option<u64>,
+    pub hash: Option<PackageHash>,
+    pub dependency_groups: Vec<DependencyGroup>,
+    /// When the version was published, as the feed formats it, e.g.
+    /// `2022-07-30T10:00:00.123Z`. Local feeds don't report it.
+    pub published: Option<String>,
+    /// When the feed last changed the version's metadata, e.g. to unlist
+    /// it, formatted like `published`. Local feeds don't report it.
+    pub last_updated: Option<String>,
+    /// Downloads of every version of the package. Only V2 feeds report it.
+    pub download_count: Option<u64>,
+    /// Downloads of this version. Only V2 feeds report it.
+    pub version_download_count: Option<u64>,
+    /// Only filled in when the client checks advisories, V2 and local feeds
+    /// don't report them.
+    pub deprecation: Option<PackageDeprecation>,
+    pub vulnerabilities: Vec<PackageVulnerability>,
+}
+
+/// See `NugetClient::get_package_timestamps`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PackageTimestamps {
+    pub published: Option<String>,
+    pub last_updated: Option<String>,
+}
+
+#[derive(Clone, Debug)]
+pub struct DependencyGroup {
+    /// `None` for dependencies that apply to every framework.
+    pub target_framework: Option<String>,
+    pub dependencies: Vec<PackageDependency>,
+}
+
+#[derive(Clone, Debug)]
+pub struct PackageDependency {
+    pub id: String,
+    /// A NuGet version range, see `VersionRange`.
+    pub version_range: String,
+}
+
+// OData encodes dependencies as `id:range:framework` triples separated by
+// `|`. A framework with no dependencies shows up as `::framework`.
+fn parse_dependency_groups(text: &str) -> Vec<DependencyGroup> {
+    let mut groups: Vec<DependencyGroup> = Vec::new();
+    for item in text.split('|').filter(|item| !item.is_empty()) {
+        let mut parts = item.splitn(3, ':');
+        let id = parts.next().unwrap_or_default().trim();
+        let version_range = parts.next().unwrap_or_default().trim();
+        let target_framework = parts
+            .next()
+            .map(str::trim)
+            .filter(|framework| !framework.is_empty())
+            .map(str::to_owned);
+
+        let index = match groups
+            .iter()
+            .position(|group| group.target_framework == target_framework)
+        {
+            Some(index) => index,
+            None => {
+                groups.push(DependencyGroup {
+                    target_framework,
+                    dependencies: Vec::new(),
+                });
+                groups.len() - 1
+            }
+        };
+        if !id.is_empty() {
+            groups[index].dependencies.push(PackageDependency {
+                id: id.to_owned(),
+                version_range: version_range.to_owned(),
+            });
+        }
+    }
+    groups
+}
+
+impl PackageMetadata {
+    pub(crate) fn from_properties(properties: &HashMap<String, String>) -> Option<Self> {
+        let id = properties.get("Id")?.clone();
+        let version = properties
+            .get("NormalizedVersion")
+            .or_else(|| properties.get("Version"))?
+            .clone();
+        let flag = |key: &str| properties.get(key).map(|value| value == "true");
+        let text = |key: &str| {
+            properties
+                .get(key)
+                .map(|value| value.trim())
+                .filter(|value| !value.is_empty())
+                .map(str::to_owned)
+        };
+        let count = |key: &str| {
+            properties
+                .get(key)
+                .and_then(|value| value.trim().parse::<u64>().ok())
+        };
+        // Not every feed reports `Listed`. nuget.org marks unlisted packages
+        // by resetting their publish date to 1900-01-01.
+        let listed = flag("Listed").unwrap_or_else(|| {
+            !properties
+                .get("Published")
+                .map(|published| published.starts_with("1900-"))
+                .unwrap_or(false)
+        });
+        let hash = match (
+            properties.get("PackageHash"),
+            properties.get("PackageHashAlgorithm"),
+        ) {
+            (Some(hash), Some(algorithm)) if !hash.is_empty() => Some(PackageHash {
+                hash: hash.clone(),
+                algorithm: HashAlgorithm::from_string(algorithm.clone()),
+            }),
+            _ => None,
+        };
+        Some(Self {
+            id,
+            version,
+            is_prerelease: flag("IsPrerelease").unwrap_or(false),
+            listed,
+            size: properties
+                .get("PackageSize")
+                .and_then(|size| size.parse().ok()),
+            hash,
+            dependency_groups: properties
+                .get("Dependencies")
+                .map(|dependencies| parse_dependency_groups(dependencies))
+                .unwrap_or_default(),
+            published: text("Published"),
+            last_updated: text("LastUpdated"),
+            download_count: count("DownloadCount"),
+            version_download_count: count("VersionDownloadCount"),
+            deprecation: None,
+            vulnerabilities: Vec::new(),
+        })
+    }
+}
+
+impl From<CatalogLeaf> for PackageMetadata {
+    fn from(leaf: CatalogLeaf) -> Self {
+        let hash = match (leaf.package_hash, leaf.package_hash_algorithm) {
+            (Some(hash), Some(algorithm)) if !hash.is_empty() => Some(PackageHash {
+                hash,
+                algorithm: HashAlgorithm::from_string(algorithm),
+            }),
+            _ => None,
+        };
+        let is_prerelease = leaf
+            .version
+            .parse::<NugetVersion>()
+            .is_ok_and(|version| version.is_prerelease());
+        Self {
+            id: leaf.id,
+            version: leaf.version,
+            is_prerelease,
+            listed: leaf.listed,
+            size: leaf.package_size,
+            hash,
+            dependency_groups: leaf
+                .dependency_groups
+                .into_iter()
+                .map(|group| DependencyGroup {
+                    target_framework: group.target_framework,
+                    dependencies: group
+                        .dependencies
+                        .into_iter()
+                        .map(|dependency| PackageDependency {
+                            id: dependency.id,
+                            version_range: dependency.range.unwrap_or_default(),
+                        })
+                        .collect(),
+                })
+                .collect(),
+            published: leaf.published,
+            last_updated: leaf.last_edited,
+            download_count: None,
+            version_download_count: None,
+            deprecation: leaf.deprecation,
+            vulnerabilities: leaf.vulnerabilities,
+        }
+    }
+}
+
+pub(crate) struct ODataFeed {
+    /// The `m:properties` of each entry, keyed by local name.
+    pub entries: Vec<HashMap<String, String>>,
+    /// The link to the next page of results, if any.
+    pub next: Option<String>,
+    /// The message of an OData error document, which some feeds send with a
+    /// 200 instead of a 404.
+    pub error: Option<String>,
+}
+
+impl ODataFeed {
+    /// The single entry of a `Packages(Id=...,Version=...)` response. An
+    /// empty feed or an error document means the feed doesn't have that
+    /// version.
+    pub fn package_entry(
+        &self,
+        package_name: &str,
+        version: &str,
+    ) -> Result<&HashMap<String, String>, NugetError> {
+        if let Some(error) = &self.error {
+            log::debug!("The feed returned an error for {package_name} {version}: {error}");
+        }
+        self.entries
+            .first()
+            .ok_or_else(|| NugetError::PackageNotFound {
+                package_name: package_name.to_owned(),
+                version: version.to_owned(),
+            })
+    }
+}
+
+// Other names feeds use for the properties we read, each with the name it
+// stands for. Names are matched ignoring case, so the names we read are
+// listed for themselves too.
+const PROPERTY_ALIASES: &[(&str, &str)] = &[
+    ("PackageHash", "PackageHash"),
+    ("PackageHashAlgorithm", "PackageHashAlgorithm"),
+    ("Hash", "PackageHash"),
+    ("HashAlgorithm", "PackageHashAlgorithm"),
+    ("PackageHashAlgorithmName", "PackageHashAlgorithm"),
+];
+
+// The property a local element name holds, and whether it was renamed.
+// `aliases` are tried before the built-in ones.
+fn property_name(local_name: &str, aliases: &[(String, String)]) -> (String, bool) {
+    let alias = aliases
+        .iter()
+        .map(|(alias, property)| (alias.as_str(), property.as_str()))
+        .chain(PROPERTY_ALIASES.iter().copied())
+        .find(|(alias, _)| alias.eq_ignore_ascii_case(local_name));
+    match alias {
+        Some((_, property)) if property != local_name => (property.to_owned(), true),
+        _ => (local_name.to_owned(), false),
+    }
+}
+
+// Works for both a feed of entries and a single entry document. Properties
+// are keyed by local name, so namespace prefixes don't matter, with
+// `aliases` (alias, property) pairs and known synonyms renamed to the names
+// we read. A property the feed sends under its own name wins over an alias.
+pub(crate) fn parse_odata(
+    text: &str,
+    aliases: &[(String, String)],
+) -> Result<ODataFeed, Box<dyn std::error::Error + Send + Sync>> {
+    let parser = EventReader::from_str(text);
+    let mut entries = Vec::new();
+    let mut next = None;
+    let mut properties: Option<HashMap<String, String>> = None;
+    let mut current: Option<(String, bool, String)> = None;
+    // `Some` inside an `m:error` element, holding its `m:message` so far
+    let mut error: Option<String> = None;
+    let mut error_message = None;
+    let mut in_message = false;
+    for event in parser {
+        match event? {
+            XmlEvent::StartElement {
+                name, attributes, ..
+            } => match (&mut properties, name.local_name.as_str()) {
+                (None, "properties") => properties = Some(HashMap::new()),
+                (None, "error") => error = Some(String::new()),
+                (None, "message") if error.is_some() => in_message = true,
+                (None, "link") => {
+                    let attribute = |key: &str| {
+                        attributes
+                            .iter()
+                            .find(|attribute| attribute.name.local_name == key)
+                            .map(|attribute| attribute.value.clone())
+                    };
+                    if attribute("rel").as_deref() == Some("next") {
+                        next = attribute("href");
+                    }
+                }
+                // Null properties are left out, as if the feed hadn't sent them
+                (Some(_), _) if is_null(&attributes) => current = None,
+                (Some(_), local_name) => {
+                    let (key, renamed) = property_name(local_name, aliases);
+                    current = Some((key, renamed, String::new()));
+                }
+                _ => {}
+            },
+            XmlEvent::Characters(text) | XmlEvent::CData(text) => {
+                if let Some((_, _, value)) = &mut current {
+                    value.push_str(&text);
+                } else if let (true, Some(message)) = (in_message, &mut error) {
+                    message.push_str(&text);
+                }
+            }
+            XmlEvent::EndElement { name } => {
+                if let Some((key, renamed, value)) = current.take() {
+                    if let Some(properties) = &mut properties {
+                        if !renamed || !properties.contains_key(&key) {
+                            properties.insert(key, value);
+                        }
+                    }
+                } else if name.local_name == "properties" {
+                    if let Some(properties) = properties.take() {
+                        entries.push(properties);
+                    }
+                } else if name.local_name == "message" {
+                    in_message = false;
+                } else if name.local_name == "error" {
+                    error_message = error.take().map(|message| message.trim().to_owned());
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(ODataFeed {
+        entries,
+        next,
+        error: error_message,
+    })
+}
+
+fn is_null(attributes: &[OwnedAttribute]) -> bool {
+    attributes
+        .iter()
+        .any(|attribute| attribute.name.local_name == "null" && attribute.value == "true")
+}
+
+pub(crate) struct Nuspec {
+    pub id: String,
+    pub version: String,
+    pub dependency_groups: Vec<DependencyGroup>,
+}
+
+// Dependencies are either listed directly under `dependencies`, applying to
+// every framework, or split into `group` elements per target framework.
+pub(crate) fn parse_nuspec(text: &str) -> Result<Nuspec, Box<dyn std::error::Error + Send + Sync>> {
+    let parser = EventReader::from_str(text);
+    let mut path: Vec<String> = Vec::new();
+    let mut id = String::new();
+    let mut version = String::new();
+    let mut groups: Vec<DependencyGroup> = Vec::new();
+    for event in parser {
+        match event? {
+            XmlEvent::StartElement {
+                name, attributes, ..
+            } => {
+                let attribute = |key: &str| {
+                    attributes
+                        .iter()
+                        .find(|attribute| attribute.name.local_name == key)
+                        .map(|attribute| attribute.value.clone())
+                };
+                path.push(name.local_name);
+                let sections: Vec<&str> = path.iter().map(String::as_str).collect();
+                match sections.as_slice() {
+                    [.., "dependencies", "group"] => groups.push(DependencyGroup {
+                        target_framework: attribute("targetFramework"),
+                        dependencies: Vec::new(),
+                    }),
+                    [.., "dependencies", "group", "dependency"] => {
+                        if let (Some(group), Some(id)) = (groups.last_mut(), attribute("id")) {
+                            group.dependencies.push(PackageDependency {
+                                id,
+                                version_range: attribute("version").unwrap_or_default(),
+                            });
+                        }
+                    }
+                    [.., "dependencies", "dependency"] => {
+                        let index = match groups
+                            .iter()
+                            .position(|group| group.target_framework.is_none())
+                        {
+                            Some(index) => index,
+                            None => {
+                                groups.push(DependencyGroup {
+                                    target_framework: None,
+                                    dependencies: Vec::new(),
+                                });
+                                groups.len() - 1
+                            }
+                        };
+                        if let Some(id) = attribute("id") {
+                            groups[index].dependencies.push(PackageDependency {
+                                id,
+                                version_range: attribute("version").unwrap_or_default(),
+                            });
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            XmlEvent::Characters(text) => match path.as_slice() {
+                [.., metadata, field] if metadata == "metadata" && field == "id" => {
+                    id.push_str(&text)
+                }
+                [.., metadata, field] if metadata == "metadata" && field == "version" => {
+                    version.push_str(&text)
+                }
+                _ => {}
+            },
+            XmlEvent::EndElement { .. } => {
+                path.pop();
+            }
+            _ => {}
+        }
+    }
+    if id.is_empty() || version.is_empty() {
+        return Err("The nuspec is missing its id or version".into());
+    }
+    Ok(Nuspec {
+        id: id.trim().to_owned(),
+        version: version.trim().to_owned(),
+        dependency_groups: groups,
+    })
+}
+
+// The group nearest to the target framework, or the framework agnostic one.
+pub(crate) fn dependency_group<'a>(
+    metadata: &'a PackageMetadata,
+    target_framework: Option<&TargetFramework>,
+) -> Option<&'a DependencyGroup> {
+    let groups = &metadata.dependency_groups;
+    match target_framework {
+        Some(target_framework) => nearest_framework(
+            target_framework,
+            groups.iter().map(|group| group.target_framework.as_deref()),
+        )
+        .map(|index| &groups[index]),
+        None => groups.iter().find(|group| group.target_framework.is_none()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn feed(entries: &[&str]) -> String {
+        let entries: String = entries
+            .iter()
+            .map(|properties| format!("<entry><m:properties>{properties}</m:properties></entry>"))
+            .collect();
+        format!(
+            "<?xml version=\"1.0\" encoding=\"utf-8\"?><feed xmlns=\"http://www.w3.org/2005/Atom\" \
+             xmlns:d=\"http://schemas.microsoft.com/ado/2007/08/dataservices\" \
+             xmlns:m=\"http://schemas.microsoft.com/ado/2007/08/dataservices/metadata\">{entries}</feed>"
+        )
+    }
+
+    fn metadata(properties: &str) -> PackageMetadata {
+        let feed = parse_odata(&feed(&[properties]), &[]).unwrap();
+        PackageMetadata::from_properties(&feed.entries[0]).unwrap()
+    }
+
+    #[test]
+    fn parses_the_package_hash() {
+        let metadata = metadata(
+            "<d:Id>Foo</d:Id><d:Version>1.0.0</d:Version><d:PackageHash>abc==</d:PackageHash>\
+             <d:PackageHashAlgorithm>SHA512</d:PackageHashAlgorithm>",
+        );
+        let hash = metadata.hash.unwrap();
+        assert_eq!(hash.hash, "abc==");
+        assert_eq!(hash.algorithm, HashAlgorithm::SHA512);
+    }
+
+    #[test]
+    fn absent_or_empty_package_hashes_are_none() {
+        let absent = metadata(
+            "<d:Id>Foo</d:Id><d:Version>1.0.0</d:Version>\
+             <d:PackageHashAlgorithm>SHA512</d:PackageHashAlgorithm>",
+        );
+        assert!(absent.hash.is_none());
+        let empty = metadata(
+            "<d:Id>Foo</d:Id><d:Version>1.0.0</d:Version><d:PackageHash></d:PackageHash>\
+             <d:PackageHashAlgorithm>SHA512</d:PackageHashAlgorithm>",
+        );
+        assert!(empty.hash.is_none());
+        let no_algorithm = metadata(
+            "<d:Id>Foo</d:Id><d:Version>1.0.0</d:Version><d:PackageHash>abc==</d:PackageHash>",
+        );
+        assert!(no_algorithm.hash.is_none());
+    }
+
+    #[test]
+    fn null_package_hashes_are_none() {
+        let null = metadata(
+            "<d:Id>Foo</d:Id><d:Version>1.0.0</d:Version>\
+             <d:PackageHash m:null=\"true\"></d:PackageHash>\
+             <d:PackageHashAlgorithm>SHA512</d:PackageHashAlgorithm>",
+        );
+        assert!(null.hash.is_none());
+    }
+
+    #[test]
+    fn self_closing_properties_dont_take_the_next_value() {
+        let feed = parse_odata(
+            &feed(&["<d:Id>Foo</d:Id><d:Version>1.0.0</d:Version>\
+                     <d:PackageHash m:null=\"true\" />\
+                     <d:PackageHashAlgorithm>SHA512</d:PackageHashAlgorithm>\
+                     <d:PackageSize />\
+                     <d:Published>2022-01-01T00:00:00</d:Published>"]),
+            &[],
+        )
+        .unwrap();
+        let properties = &feed.entries[0];
+        assert_eq!(properties.get("PackageHash"), None);
+        assert_eq!(
+            properties.get("PackageHashAlgorithm").map(String::as_str),
+            Some("SHA512")
+        );
+        assert_eq!(properties.get("PackageSize").map(String::as_str), Some(""));
+        let metadata = PackageMetadata::from_properties(properties).unwrap();
+        assert!(metadata.hash.is_none());
+        assert_eq!(metadata.size, None);
+        assert_eq!(metadata.published.as_deref(), Some("2022-01-01T00:00:00"));
+    }
+
+    fn hash_of(text: &str, aliases: &[(String, String)]) -> Option<PackageHash> {
+        let feed = parse_odata(text, aliases).unwrap();
+        PackageMetadata::from_properties(&feed.entries[0])?.hash
+    }
+
+    #[test]
+    fn reads_properties_under_any_namespace_prefix() {
+        // Default namespaces instead of `m:` and `d:`, like some servers send
+        let text = "<?xml version=\"1.0\"?><entry xmlns=\"http://www.w3.org/2005/Atom\">\
+                    <properties xmlns=\"http://schemas.microsoft.com/ado/2007/08/dataservices/metadata\">\
+                    <Id xmlns=\"http://schemas.microsoft.com/ado/2007/08/dataservices\">Foo</Id>\
+                    <Version xmlns=\"http://schemas.microsoft.com/ado/2007/08/dataservices\">1.0.0</Version>\
+                    <ds:PackageHash xmlns:ds=\"http://schemas.microsoft.com/ado/2007/08/dataservices\">abc==</ds:PackageHash>\
+                    <ds:PackageHashAlgorithm xmlns:ds=\"http://schemas.microsoft.com/ado/2007/08/dataservices\">SHA512</ds:PackageHashAlgorithm>\
+                    </properties></entry>";
+        let hash = hash_of(text, &[]).unwrap();
+        assert_eq!(hash.hash, "abc==");
+        assert_eq!(hash.algorithm, HashAlgorithm::SHA512);
+    }
+
+    #[test]
+    fn reads_known_synonyms() {
+        let variations = [
+            "<d:Hash>abc==</d:Hash><d:HashAlgorithm>SHA512</d:HashAlgorithm>",
+            "<d:packagehash>abc==</d:packagehash>\
+             <d:PackageHashAlgorithmName>SHA512</d:PackageHashAlgorithmName>",
+        ];
+        for properties in variations {
+            let text = feed(&[&format!(
+                "<d:Id>Foo</d:Id><d:Version>1.0.0</d:Version>{properties}"
+            )]);
+            let hash = hash_of(&text, &[]).unwrap_or_else(|| panic!("{properties}"));
+            assert_eq!(hash.hash, "abc==", "{properties}");
+            assert_eq!(hash.algorithm, HashAlgorithm::SHA512, "{properties}");
+        }
+    }
+
+    #[test]
+    fn own_names_win_over_synonyms() {
+        let text = feed(&["<d:Id>Foo</d:Id><d:Version>1.0.0</d:Version>\
+                           <d:PackageHash>abc==</d:PackageHash><d:Hash>other==</d:Hash>\
+                           <d:PackageHashAlgorithm>SHA512</d:PackageHashAlgorithm>"]);
+        assert_eq!(hash_of(&text, &[]).unwrap().hash, "abc==");
+    }
+
+    #[test]
+    fn reads_configured_aliases() {
+        let text = feed(&["<d:Id>Foo</d:Id><d:Version>1.0.0</d:Version>\
+                           <d:Sha512Digest>abc==</d:Sha512Digest>\
+                           <d:PackageHashAlgorithm>SHA512</d:PackageHashAlgorithm>"]);
+        assert!(hash_of(&text, &[]).is_none());
+        let aliases = [("Sha512Digest".to_owned(), "PackageHash".to_owned())];
+        assert_eq!(hash_of(&text, &aliases).unwrap().hash, "abc==");
+    }
+}
@@ -0,0 +1,63 @@
+use std::{collections::HashMap, sync::Mutex};
+
+/// A small least-recently-used cache for a client session, keyed by package
+/// name and version. Published versions don't change, so their metadata can
+/// be reused for as long as the client lives.
+pub(crate) struct LruCache<V> {
+    capacity: usize,
+    state: Mutex<LruState<V>>,
+}
+
+struct LruState<V> {
+    // Each entry with when it was last used
+    entries: HashMap<String, (u64, V)>,
+    clock: u64,
+}
+
+impl<V: Clone> LruCache<V> {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            state: Mutex::new(LruState {
+                entries: HashMap::new(),
+                clock: 0,
+            }),
+        }
+    }
+
+    pub fn get(&self, package_name: &str, version: &str) -> Option<V> {
+        let mut state = self.state.lock().unwrap();
+        state.clock += 1;
+        let clock = state.clock;
+        let (last_used, value) = state.entries.get_mut(&key(package_name, version))?;
+        *last_used = clock;
+        Some(value.clone())
+    }
+
+    pub fn insert(&self, package_name: &str, version: &str, value: V) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut state = self.state.lock().unwrap();
+        let key = key(package_name, version);
+        if state.entries.len() >= self.capacity && !state.entries.contains_key(&key) {
+            // The cache is small, so finding the oldest entry by scanning is fine
+            let oldest = state
+                .entries
+                .iter()
+                .min_by_key(|(_, (last_used, _))| *last_used)
+                .map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                state.entries.remove(&oldest);
+            }
+        }
+        state.clock += 1;
+        let clock = state.clock;
+        state.entries.insert(key, (clock, value));
+    }
+}
+
+// Ids are case-insensitive, and so are version labels.
+fn key(package_name: &str, version: &str) -> String {
+    format!("{}@{}", package_name.to_lowercase(), version.to_lowercase())
+}
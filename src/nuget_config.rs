@@ -0,0 +1,210 @@
+use std::path::{Path, PathBuf};
+
+use xml::{reader::XmlEvent, EventReader};
+
+use crate::NugetError;
+
+/// A feed from the `packageSources` section of a `NuGet.Config` file.
+#[derive(Clone, Debug)]
+pub struct PackageSource {
+    pub name: String,
+    pub url: String,
+    /// Set through the `protocolVersion` attribute, or inferred from a URL
+    /// ending in `index.json`.
+    pub protocol_version: u32,
+    /// `false` for sources listed in `disabledPackageSources`.
+    pub enabled: bool,
+    pub credentials: Option<SourceCredentials>,
+}
+
+#[derive(Clone)]
+pub struct SourceCredentials {
+    pub username: String,
+    pub password: String,
+}
+
+// Keeps passwords out of logs
+impl std::fmt::Debug for SourceCredentials {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SourceCredentials")
+            .field("username", &self.username)
+            .finish_non_exhaustive()
+    }
+}
+
+/// The package sources and credentials configured for dotnet tooling.
+///
+/// Only `ClearTextPassword` credentials are read. Passwords stored with
+/// `Password` are encrypted with the Windows data protection API, those
+/// sources are returned without credentials and a warning is logged.
+#[derive(Clone, Debug, Default)]
+pub struct PackageSources {
+    pub sources: Vec<PackageSource>,
+}
+
+impl PackageSources {
+    pub fn from_path<P: AsRef<Path>>(
+        path: P,
+    ) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let text = std::fs::read_to_string(path)?;
+        Self::parse(&text)
+    }
+
+    /// Reads the user-wide `NuGet.Config`, `%APPDATA%\NuGet\NuGet.Config` on
+    /// Windows and `~/.nuget/NuGet/NuGet.Config` elsewhere. Returns no sources
+    /// if the file doesn't exist.
+    pub fn from_user_config() -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        match user_config_path() {
+            Some(path) if path.exists() => Self::from_path(path),
+            _ => Ok(Self::default()),
+        }
+    }
+
+    pub fn parse(text: &str) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let parser = EventReader::from_str(text);
+        let mut sources: Vec<PackageSource> = Vec::new();
+        let mut disabled = Vec::new();
+        // Each `add` element's meaning depends on the section it's in
+        let mut path: Vec<String> = Vec::new();
+        let mut credentials: Vec<(String, Option<String>, Option<String>)> = Vec::new();
+        for event in parser {
+            match event? {
+                XmlEvent::StartElement {
+                    name, attributes, ..
+                } => {
+                    path.push(name.local_name);
+                    if path.ends_with(&["packageSources".to_owned(), "clear".to_owned()]) {
+                        // Drops sources inherited from higher level configs
+                        sources.clear();
+                    } else if path.last().map(String::as_str) == Some("add") {
+                        let attribute = |key: &str| {
+                            attributes
+                                .iter()
+                                .find(|attribute| attribute.name.local_name == key)
+                                .map(|attribute| attribute.value.clone())
+                        };
+                        let (Some(key), Some(value)) = (attribute("key"), attribute("value"))
+                        else {
+                            continue;
+                        };
+                        let sections: Vec<&str> = path.iter().map(String::as_str).collect();
+                        match sections.as_slice() {
+                            [.., "packageSources", "add"] => {
+                                let protocol_version = match attribute("protocolVersion") {
+                                    Some(version) => version.parse().map_err(|_| {
+                                        NugetError::InvalidConfig(format!(
+                                            "{key}: invalid protocolVersion \"{version}\""
+                                        ))
+                                    })?,
+                                    None if value.ends_with("index.json") => 3,
+                                    None => 2,
+                                };
+                                sources.push(PackageSource {
+                                    name: key,
+                                    url: value,
+                                    protocol_version,
+                                    enabled: true,
+                                    credentials: None,
+                                });
+                            }
+                            [.., "disabledPackageSources", "add"]
+                                if value.eq_ignore_ascii_case("true") =>
+                            {
+                                disabled.push(key);
+                            }
+                            [.., "packageSourceCredentials", source, "add"] => {
+                                let source = decode_element_name(source);
+                                let index = match credentials
+                                    .iter()
+                                    .position(|(name, _, _)| *name == source)
+                                {
+                                    Some(index) => index,
+                                    None => {
+                                        credentials.push((source, None, None));
+                                        credentials.len() - 1
+                                    }
+                                };
+                                let entry = &mut credentials[index];
+                                match key.as_str() {
+                                    "Username" => entry.1 = Some(value),
+                                    "ClearTextPassword" => entry.2 = Some(value),
+                                    "Password" => log::warn!(
+                                        "Skipping the encrypted password for {}",
+                                        entry.0
+                                    ),
+                                    _ => {}
+                                }
+                            }
+                            _ => {}
+                        }
+                    }
+                }
+                XmlEvent::EndElement { .. } => {
+                    path.pop();
+                }
+                _ => {}
+            }
+        }
+
+        for source in &mut sources {
+            source.enabled = !disabled.contains(&source.name);
+            if let Some((_, Some(username), Some(password))) = credentials
+                .iter()
+                .find(|(name, _, _)| name.eq_ignore_ascii_case(&source.name))
+            {
+                source.credentials = Some(SourceCredentials {
+                    username: username.clone(),
+                    password: password.clone(),
+                });
+            }
+        }
+        Ok(Self { sources })
+    }
+
+    /// Looks up a source by name, ignoring case like dotnet does.
+    pub fn source(&self, name: &str) -> Option<&PackageSource> {
+        self.sources
+            .iter()
+            .find(|source| source.name.eq_ignore_ascii_case(name))
+    }
+}
+
+fn user_config_path() -> Option<PathBuf> {
+    let mut path = if cfg!(windows) {
+        PathBuf::from(std::env::var_os("APPDATA")?)
+    } else {
+        let mut path = PathBuf::from(std::env::var_os("HOME")?);
+        path.push(".nuget");
+        path
+    };
+    path.push("NuGet");
+    path.push("NuGet.Config");
+    Some(path)
+}
+
+// Source names that aren't valid XML names are escaped as `_xHHHH_` when
+// used as element names, e.g. `My_x0020_Feed` for `My Feed`.
+fn decode_element_name(name: &str) -> String {
+    let mut decoded = String::with_capacity(name.len());
+    let mut rest = name;
+    while let Some(index) = rest.find("_x") {
+        decoded.push_str(&rest[..index]);
+        let escape = rest[index + 2..]
+            .get(..5)
+            .filter(|escape| escape.ends_with('_'))
+            .and_then(|escape| u32::from_str_radix(&escape[..4], 16).ok())
+            .and_then(char::from_u32);
+        match escape {
+            Some(character) => {
+                decoded.push(character);
+                rest = &rest[index + 7..];
+            }
+            None => {
+                decoded.push_str("_x");
+                rest = &rest[index + 2..];
+            }
+        }
+    }
+    decoded.push_str(rest);
+    decoded
+}
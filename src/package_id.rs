@@ -0,0 +1,141 @@
+use std::{
+    fmt,
+    hash::{Hash, Hasher},
+    str::FromStr,
+};
+
+use crate::{NugetError, NugetVersion};
+
+/// A package name and exact version, written as `name@version`, e.g.
+/// `Microsoft.Windows.CppWinRT@2.0.220608.4`. Names compare
+/// case-insensitively, like they do on the feed.
+#[derive(Clone, Debug)]
+pub struct PackageId {
+    pub name: String,
+    pub version: NugetVersion,
+}
+
+// Catches empty names and versions before they end up in a feed URL.
+pub(crate) fn check_package_input(package_name: &str, version: &str) -> Result<(), NugetError> {
+    for (field, value) in [("package name", package_name), ("version", version)] {
+        if value.trim().is_empty() {
+            return Err(NugetError::InvalidInput {
+                field: field.to_owned(),
+            });
+        }
+    }
+    Ok(())
+}
+
+impl PackageId {
+    pub fn new(name: &str, version: &str) -> Result<Self, NugetError> {
+        Ok(Self {
+            name: name.to_owned(),
+            version: version.parse()?,
+        })
+    }
+
+    /// Parses a `{name}.{version}.nupkg` file name. Names may contain dots
+    /// themselves, so the version is matched from the right: of the suffixes
+    /// that are valid versions, the one with the most numeric parts wins,
+    /// preferring the longer one on ties. That recovers
+    /// `Microsoft.AI.DirectML` `1.9.0`, `directxtk12_desktop_2019`
+    /// `2022.7.30.1` and `Foo` `1.0.0-rc.1`, without mistaking a name part
+    /// like `7-Zip` in `Foo.7-Zip.1.0.0` for a prerelease version.
+    pub fn from_file_name(file_name: &str) -> Option<Self> {
+        let end = file_name.len().checked_sub(".nupkg".len())?;
+        let stem = file_name
+            .get(end..)
+            .filter(|extension| extension.eq_ignore_ascii_case(".nupkg"))
+            .map(|_| &file_name[..end])?;
+        let numeric_parts = |version: &str| {
+            version
+                .split(['-', '+'])
+                .next()
+                .unwrap_or_default()
+                .split('.')
+                .count()
+        };
+        stem.match_indices('.')
+            .filter_map(|(index, _)| {
+                let (name, version) = (&stem[..index], &stem[index + 1..]);
+                if name.is_empty() || !version.starts_with(|c: char| c.is_ascii_digit()) {
+                    return None;
+                }
+                let package = Self::new(name, version).ok()?;
+                Some(((numeric_parts(version), version.len()), package))
+            })
+            .max_by_key(|(key, _)| *key)
+            .map(|(_, package)| package)
+    }
+}
+
+impl FromStr for PackageId {
+    type Err = NugetError;
+
+    fn from_str(string: &str) -> Result<Self, Self::Err> {
+        match string.trim().split_once('@') {
+            Some((name, version)) if !name.is_empty() => Self::new(name, version),
+            _ => Err(NugetError::InvalidPackageId(string.to_owned())),
+        }
+    }
+}
+
+impl fmt::Display for PackageId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}@{}", self.name, self.version)
+    }
+}
+
+impl PartialEq for PackageId {
+    fn eq(&self, other: &Self) -> bool {
+        self.name.eq_ignore_ascii_case(&other.name) && self.version == other.version
+    }
+}
+
+impl Eq for PackageId {}
+
+impl Hash for PackageId {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.name.to_ascii_lowercase().hash(state);
+        self.version.hash(state);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_file_name_splits_at_the_version() {
+        let cases = [
+            ("Foo.1.0.0.nupkg", Some(("Foo", "1.0.0"))),
+            (
+                "Microsoft.AI.DirectML.1.9.0.nupkg",
+                Some(("Microsoft.AI.DirectML", "1.9.0")),
+            ),
+            (
+                "directxtk12_desktop_2019.2022.7.30.1.nupkg",
+                Some(("directxtk12_desktop_2019", "2022.7.30.1")),
+            ),
+            (
+                "Microsoft.Windows.CppWinRT.2.0.220608.4.nupkg",
+                Some(("Microsoft.Windows.CppWinRT", "2.0.220608.4")),
+            ),
+            ("Foo.1.0.0-rc.1.nupkg", Some(("Foo", "1.0.0-rc.1"))),
+            ("Foo.7-Zip.1.0.0.nupkg", Some(("Foo.7-Zip", "1.0.0"))),
+            ("Foo2.Bar3.1.2.nupkg", Some(("Foo2.Bar3", "1.2"))),
+            ("Foo.1.0.0.NUPKG", Some(("Foo", "1.0.0"))),
+            ("Foo.nupkg", None),
+            ("Foo.1.0.0.zip", None),
+        ];
+        for (file_name, expected) in cases {
+            let package = PackageId::from_file_name(file_name);
+            let actual = package
+                .as_ref()
+                .map(|package| (package.name.clone(), package.version.to_string()));
+            let expected = expected.map(|(name, version)| (name.to_owned(), version.to_owned()));
+            assert_eq!(actual, expected, "{file_name}");
+        }
+    }
+}
@@ -0,0 +1,75 @@
+use serde::Deserialize;
+
+use crate::NugetError;
+
+/// Which packages may be downloaded, by ID. Patterns are globs like
+/// `Contoso.*`, matched case-insensitively since NuGet IDs are. A package
+/// matching `deny` is refused even if it matches `allow`.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PackagePolicy {
+    /// When set, only packages matching one of these may be downloaded.
+    pub allow: Option<Vec<String>>,
+    #[serde(default)]
+    pub deny: Vec<String>,
+}
+
+impl PackagePolicy {
+    /// Whether the policy lets `package_name` be downloaded.
+    pub fn allows(
+        &self,
+        package_name: &str,
+    ) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+        let (allow, deny) = self.glob_sets()?;
+        Ok(is_allowed(allow.as_ref(), &deny, package_name))
+    }
+
+    /// Fails with `NugetError::PolicyViolation` for the first package the
+    /// policy refuses.
+    pub(crate) fn check<'a, I: IntoIterator<Item = &'a str>>(
+        &self,
+        package_names: I,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let (allow, deny) = self.glob_sets()?;
+        for package_name in package_names {
+            if !is_allowed(allow.as_ref(), &deny, package_name) {
+                return Err(Box::new(NugetError::PolicyViolation {
+                    package_name: package_name.to_owned(),
+                }));
+            }
+        }
+        Ok(())
+    }
+
+    fn glob_sets(
+        &self,
+    ) -> Result<
+        (Option<globset::GlobSet>, globset::GlobSet),
+        Box<dyn std::error::Error + Send + Sync>,
+    > {
+        let allow = self.allow.as_deref().map(glob_set).transpose()?;
+        Ok((allow, glob_set(&self.deny)?))
+    }
+}
+
+fn is_allowed(
+    allow: Option<&globset::GlobSet>,
+    deny: &globset::GlobSet,
+    package_name: &str,
+) -> bool {
+    allow.is_none_or(|allow| allow.is_match(package_name)) && !deny.is_match(package_name)
+}
+
+fn glob_set(
+    patterns: &[String],
+) -> Result<globset::GlobSet, Box<dyn std::error::Error + Send + Sync>> {
+    let mut builder = globset::GlobSetBuilder::new();
+    for pattern in patterns {
+        builder.add(
+            globset::GlobBuilder::new(pattern)
+                .case_insensitive(true)
+                .build()?,
+        );
+    }
+    Ok(builder.build()?)
+}
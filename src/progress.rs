@@ -0,0 +1,95 @@
+use std::{collections::HashMap, sync::Mutex};
+
+use crate::DownloadProgress;
+
+/// The overall progress of a config install, see `InstallOptions::progress`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct InstallProgress {
+    /// Bytes received so far, with packages found in the cache counted as
+    /// complete.
+    pub downloaded: u64,
+    /// The combined size of the packages. Packages the feed reports no size
+    /// for are added once their download starts.
+    pub total: u64,
+    pub completed_packages: usize,
+    pub total_packages: usize,
+}
+
+// Combines the client's per-package progress into one `InstallProgress`.
+pub(crate) struct ProgressAggregator {
+    state: Mutex<AggregateState>,
+    callback: Box<dyn Fn(&InstallProgress) + Send + Sync>,
+}
+
+#[derive(Default)]
+struct AggregateState {
+    progress: InstallProgress,
+    // Bytes received and size of each package, keyed by lowercase `name@version`
+    packages: HashMap<String, (u64, Option<u64>)>,
+}
+
+fn key(package_name: &str, version: &str) -> String {
+    format!("{package_name}@{version}").to_lowercase()
+}
+
+impl ProgressAggregator {
+    pub fn new<F: Fn(&InstallProgress) + Send + Sync + 'static>(callback: F) -> Self {
+        Self {
+            state: Mutex::new(AggregateState::default()),
+            callback: Box::new(callback),
+        }
+    }
+
+    /// Sets the packages about to be installed and their sizes, if known.
+    pub fn plan(&self, packages: &[(&str, &str, Option<u64>)]) {
+        let mut state = self.state.lock().unwrap();
+        state.progress.total_packages = packages.len();
+        for (package_name, version, size) in packages {
+            state.progress.total += size.unwrap_or(0);
+            state
+                .packages
+                .insert(key(package_name, version), (0, *size));
+        }
+        (self.callback)(&state.progress);
+    }
+
+    pub fn on_progress(&self, progress: &DownloadProgress) {
+        let mut state = self.state.lock().unwrap();
+        let AggregateState {
+            progress: overall,
+            packages,
+        } = &mut *state;
+        let package = packages
+            .entry(key(progress.package_name, progress.version))
+            .or_default();
+        if package.1.is_none() {
+            package.1 = progress.total;
+            overall.total += progress.total.unwrap_or(0);
+        }
+        // A retried download starts over
+        overall.downloaded = overall.downloaded - package.0 + progress.downloaded;
+        package.0 = progress.downloaded;
+        (self.callback)(&state.progress);
+    }
+
+    /// Marks the package done. `size` is the size of the installed package,
+    /// `None` if it failed.
+    pub fn on_finished(&self, package_name: &str, version: &str, size: Option<u64>) {
+        let mut state = self.state.lock().unwrap();
+        let AggregateState {
+            progress: overall,
+            packages,
+        } = &mut *state;
+        if let Some(size) = size {
+            let package = packages.entry(key(package_name, version)).or_default();
+            if package.1.is_none() {
+                package.1 = Some(size);
+                overall.total += size;
+            }
+            overall.downloaded = overall.downloaded - package.0 + size;
+            package.0 = size;
+        }
+        overall.completed_packages += 1;
+        (self.callback)(&state.progress);
+    }
+}
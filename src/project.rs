@@ -0,0 +1,182 @@
+use std::collections::BTreeMap;
+
+use serde::Deserialize;
+use xml::{reader::XmlEvent, EventReader};
+
+use crate::{HashAlgorithm, NugetError, PackageHash};
+
+/// A package id, its version and the hash it must match.
+pub(crate) type PinnedPackage = (String, String, PackageHash);
+
+// `packages.lock.json`, keyed by target framework and then package id
+#[derive(Deserialize)]
+struct DotnetLockfile {
+    #[serde(default)]
+    dependencies: BTreeMap<String, BTreeMap<String, DotnetLockedPackage>>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DotnetLockedPackage {
+    #[serde(rename = "type")]
+    kind: Option<String>,
+    resolved: Option<String>,
+    /// Base64 SHA512 of the `.nupkg`.
+    content_hash: Option<String>,
+}
+
+/// Every package a `packages.lock.json` resolved, with its content hash.
+/// Packages resolved for several frameworks are listed once, project
+/// references are skipped.
+pub(crate) fn dotnet_lockfile_packages(
+    text: &str,
+) -> Result<Vec<PinnedPackage>, Box<dyn std::error::Error + Send + Sync>> {
+    let lockfile: DotnetLockfile = serde_json::from_str(text)?;
+    let mut packages: Vec<PinnedPackage> = Vec::new();
+    for (framework, dependencies) in lockfile.dependencies {
+        for (name, package) in dependencies {
+            if package.kind.as_deref() == Some("Project") {
+                continue;
+            }
+            let (Some(version), Some(hash)) = (package.resolved, package.content_hash) else {
+                return Err(Box::new(NugetError::InvalidConfig(format!(
+                    "{name} for {framework} in packages.lock.json has no resolved version or content hash"
+                ))));
+            };
+            let listed = packages.iter().any(|(listed_name, listed_version, _)| {
+                listed_name.eq_ignore_ascii_case(&name)
+                    && listed_version.eq_ignore_ascii_case(&version)
+            });
+            if !listed {
+                let hash = PackageHash {
+                    hash,
+                    algorithm: HashAlgorithm::SHA512,
+                };
+                packages.push((name, version, hash));
+            }
+        }
+    }
+    Ok(packages)
+}
+
+/// The packages in a requirements file, one `id version` or `id==version`
+/// per line. Blank lines and lines starting with `#` are ignored. `origin`
+/// names the file in error messages.
+pub(crate) fn requirements_references(
+    text: &str,
+    origin: &str,
+) -> Result<Vec<(String, String)>, Box<dyn std::error::Error + Send + Sync>> {
+    let mut references = Vec::new();
+    for (index, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let fields: Vec<&str> = match line.split_once("==") {
+            Some((id, version)) => vec![id.trim(), version.trim()],
+            None => line.split_whitespace().collect(),
+        };
+        match fields.as_slice() {
+            [id, version] if !id.is_empty() && !version.is_empty() => {
+                references.push((id.to_string(), version.to_string()))
+            }
+            _ => {
+                return Err(Box::new(NugetError::InvalidConfig(format!(
+                    "{origin}:{}: expected `id version` or `id==version`",
+                    index + 1
+                ))))
+            }
+        }
+    }
+    Ok(references)
+}
+
+/// The `id` and `version` of every `package` in a legacy `packages.config`.
+pub(crate) fn packages_config_references(
+    text: &str,
+) -> Result<Vec<(String, String)>, Box<dyn std::error::Error + Send + Sync>> {
+    let mut references = Vec::new();
+    for event in EventReader::from_str(text) {
+        if let XmlEvent::StartElement {
+            name, attributes, ..
+        } = event?
+        {
+            if name.local_name != "package" {
+                continue;
+            }
+            let attribute = |key: &str| {
+                attributes
+                    .iter()
+                    .find(|attribute| attribute.name.local_name == key)
+                    .map(|attribute| attribute.value.clone())
+            };
+            match (attribute("id"), attribute("version")) {
+                (Some(id), Some(version)) => references.push((id, version)),
+                _ => {
+                    return Err(Box::new(NugetError::InvalidConfig(
+                        "a package in packages.config is missing its id or version".to_owned(),
+                    )))
+                }
+            }
+        }
+    }
+    Ok(references)
+}
+
+/// The `Include` and version of every `PackageReference` in a project file.
+/// The version is either the `Version` attribute or a `Version` child element.
+/// `Update` items only change references made elsewhere, so they're skipped.
+pub(crate) fn project_package_references(
+    text: &str,
+) -> Result<Vec<(String, String)>, Box<dyn std::error::Error + Send + Sync>> {
+    let mut references = Vec::new();
+    // The reference being read, while inside its element
+    let mut current: Option<(String, Option<String>)> = None;
+    let mut in_version = false;
+    for event in EventReader::from_str(text) {
+        match event? {
+            XmlEvent::StartElement {
+                name, attributes, ..
+            } => {
+                let attribute = |key: &str| {
+                    attributes
+                        .iter()
+                        .find(|attribute| attribute.name.local_name.eq_ignore_ascii_case(key))
+                        .map(|attribute| attribute.value.trim().to_owned())
+                };
+                if name.local_name == "PackageReference" {
+                    current = attribute("Include").map(|include| (include, attribute("Version")));
+                } else if current.is_some() && name.local_name == "Version" {
+                    in_version = true;
+                }
+            }
+            XmlEvent::Characters(text) if in_version => {
+                if let Some((_, version)) = &mut current {
+                    *version = Some(text.trim().to_owned());
+                }
+            }
+            XmlEvent::EndElement { name } => {
+                if name.local_name == "Version" {
+                    in_version = false;
+                } else if name.local_name == "PackageReference" {
+                    let Some((include, version)) = current.take() else {
+                        continue;
+                    };
+                    let version = version.ok_or_else(|| {
+                        NugetError::InvalidConfig(format!(
+                            "the PackageReference to {include} has no version"
+                        ))
+                    })?;
+                    if version.contains("$(") {
+                        return Err(Box::new(NugetError::InvalidConfig(format!(
+                            "the PackageReference to {include} uses an MSBuild property"
+                        ))));
+                    }
+                    references.push((include, version));
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(references)
+}
@@ -0,0 +1,85 @@
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::Write,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use crate::{get_package_file_stem, DownloadResult, NugetPackageFile, PackageTimings};
+
+// The packages an interrupted install already finished, so a rerun can use
+// them without checking them again. Each line is a tab separated id, version
+// and cached path, appended as soon as the package is done.
+pub(crate) struct ResumeState {
+    path: PathBuf,
+    completed: HashMap<String, PathBuf>,
+    file: Mutex<File>,
+}
+
+fn key(package_name: &str, version: &str) -> String {
+    format!("{package_name}@{version}").to_lowercase()
+}
+
+impl ResumeState {
+    pub fn path_for_config(config_path: &Path) -> PathBuf {
+        config_path.with_extension("resume")
+    }
+
+    /// Opens the state file, creating it if this is a fresh install.
+    pub fn open(path: PathBuf) -> std::io::Result<Self> {
+        let text = match std::fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(error) => return Err(error),
+        };
+        let completed = text
+            .lines()
+            .filter_map(|line| {
+                let mut fields = line.splitn(3, '\t');
+                match (fields.next(), fields.next(), fields.next()) {
+                    (Some(id), Some(version), Some(path)) => {
+                        Some((key(id, version), PathBuf::from(path)))
+                    }
+                    _ => None,
+                }
+            })
+            .collect();
+        let file = File::options().create(true).append(true).open(&path)?;
+        Ok(Self {
+            path,
+            completed,
+            file: Mutex::new(file),
+        })
+    }
+
+    /// The package as an earlier run left it, if it finished and is still
+    /// there.
+    pub fn completed(&self, package_name: &str, version: &str) -> Option<DownloadResult> {
+        let path = self.completed.get(&key(package_name, version))?;
+        let file = File::open(path).ok()?;
+        log::debug!("{package_name} {version} finished in an earlier run");
+        Some(DownloadResult {
+            file: NugetPackageFile {
+                stem: get_package_file_stem(package_name, version),
+                file,
+            },
+            downloaded: false,
+            path: path.clone(),
+            timings: PackageTimings::default(),
+            verified_hash: None,
+        })
+    }
+
+    pub fn record(&self, package_name: &str, version: &str, path: &Path) -> std::io::Result<()> {
+        let mut file = self.file.lock().unwrap();
+        writeln!(file, "{package_name}\t{version}\t{}", path.display())?;
+        file.flush()
+    }
+
+    /// Removes the state file once everything is installed.
+    pub fn finish(self) -> std::io::Result<()> {
+        drop(self.file);
+        std::fs::remove_file(&self.path)
+    }
+}
@@ -0,0 +1,110 @@
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+    time::Duration,
+};
+
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    pub max_retries: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    /// Pick each delay uniformly from `[0, backoff]` ("full jitter") so that
+    /// parallel downloads that fail together don't retry in lockstep.
+    pub jitter: bool,
+    /// Seed for the jitter. When unset every retry sequence is seeded randomly.
+    pub seed: Option<u64>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(10),
+            jitter: true,
+            seed: None,
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn none() -> Self {
+        Self {
+            max_retries: 0,
+            ..Default::default()
+        }
+    }
+
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    /// The backoff before jitter is applied for the given (zero based) retry.
+    pub fn backoff(&self, retry: u32) -> Duration {
+        let factor = 2u32.saturating_pow(retry);
+        self.initial_backoff
+            .saturating_mul(factor)
+            .min(self.max_backoff)
+    }
+
+    /// The delays to wait before each retry, in order.
+    pub fn delays(&self) -> RetryDelays {
+        let seed = self
+            .seed
+            .unwrap_or_else(|| RandomState::new().build_hasher().finish());
+        RetryDelays {
+            policy: self.clone(),
+            retry: 0,
+            rng: SplitMix64(seed),
+        }
+    }
+}
+
+pub struct RetryDelays {
+    policy: RetryPolicy,
+    retry: u32,
+    rng: SplitMix64,
+}
+
+impl Iterator for RetryDelays {
+    type Item = Duration;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.retry >= self.policy.max_retries {
+            return None;
+        }
+        let backoff = self.policy.backoff(self.retry);
+        self.retry += 1;
+        if !self.policy.jitter {
+            return Some(backoff);
+        }
+        let nanos = u64::try_from(backoff.as_nanos()).unwrap_or(u64::MAX);
+        let jittered = match nanos.checked_add(1) {
+            Some(bound) => self.rng.next_u64() % bound,
+            None => self.rng.next_u64(),
+        };
+        Some(Duration::from_nanos(jittered))
+    }
+}
+
+// Small, deterministic generator so jitter doesn't need an RNG dependency.
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+}
+
+pub(crate) fn is_retryable(error: &reqwest::Error) -> bool {
+    if let Some(status) = error.status() {
+        return status.is_server_error() || status == reqwest::StatusCode::TOO_MANY_REQUESTS;
+    }
+    error.is_timeout() || error.is_connect() || error.is_request()
+}
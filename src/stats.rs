@@ -0,0 +1,50 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// A snapshot of a client's traffic, see `NugetClient::with_stats`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ClientStats {
+    /// Package bytes received, including from local feeds.
+    pub bytes_downloaded: u64,
+    /// `download_package` calls satisfied by an already cached package.
+    pub cache_hits: u64,
+    /// `download_package` calls that had to download the package.
+    pub cache_misses: u64,
+    /// HTTP requests sent, counting every retry.
+    pub requests: u64,
+}
+
+#[derive(Default)]
+pub(crate) struct StatsCounters {
+    bytes_downloaded: AtomicU64,
+    cache_hits: AtomicU64,
+    cache_misses: AtomicU64,
+    requests: AtomicU64,
+}
+
+impl StatsCounters {
+    pub fn add_bytes_downloaded(&self, bytes: u64) {
+        self.bytes_downloaded.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    pub fn add_cache_lookup(&self, hit: bool) {
+        let counter = if hit {
+            &self.cache_hits
+        } else {
+            &self.cache_misses
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn add_request(&self) {
+        self.requests.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self) -> ClientStats {
+        ClientStats {
+            bytes_downloaded: self.bytes_downloaded.load(Ordering::Relaxed),
+            cache_hits: self.cache_hits.load(Ordering::Relaxed),
+            cache_misses: self.cache_misses.load(Ordering::Relaxed),
+            requests: self.requests.load(Ordering::Relaxed),
+        }
+    }
+}
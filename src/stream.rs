@@ -0,0 +1,22 @@
+use bytes::Bytes;
+use futures_util::{stream, Stream, TryStreamExt};
+
+use crate::feed::NUGET_V2_URL;
+
+/// Downloads a package from nuget.org, yielding the body in chunks as they
+/// arrive instead of buffering it. Nothing is requested until the stream is
+/// first polled. The bytes aren't verified, compare them against
+/// `get_package_hash` to check them.
+pub fn download_package_stream_async(
+    package_name: &str,
+    version: &str,
+) -> impl Stream<Item = Result<Bytes, Box<dyn std::error::Error + Send + Sync>>> {
+    let url = format!("{NUGET_V2_URL}/package/{package_name}/{version}");
+    stream::once(async move {
+        let response = reqwest::get(&url).await?.error_for_status()?;
+        Ok::<_, Box<dyn std::error::Error + Send + Sync>>(
+            response.bytes_stream().map_err(Into::into),
+        )
+    })
+    .try_flatten()
+}
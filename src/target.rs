@@ -0,0 +1,89 @@
+use cfg_expr::{
+    expr::TargetPredicate, targets::get_builtin_target_by_triple, Expression, Predicate,
+};
+
+use crate::NugetError;
+
+/// Whether a `[target.<spec>]` section applies, where the spec is either a
+/// `cfg(...)` expression, like Cargo's, or a target triple. Build scripts are
+/// evaluated against the target being built, from the `CARGO_CFG_*` and
+/// `TARGET` variables Cargo sets. Anywhere else the platform nuget-dl was
+/// built for is used.
+pub(crate) fn target_matches(spec: &str) -> Result<bool, NugetError> {
+    if !spec.starts_with("cfg(") {
+        return Ok(match std::env::var("TARGET") {
+            Ok(target) => target == spec,
+            Err(_) => get_builtin_target_by_triple(spec).is_some_and(|info| {
+                info.arch.as_str() == std::env::consts::ARCH
+                    && info.os.as_ref().map(|os| os.as_str()).unwrap_or("none")
+                        == std::env::consts::OS
+            }),
+        });
+    }
+    let expression = Expression::parse(spec)
+        .map_err(|error| NugetError::InvalidConfig(format!("invalid target: {error}")))?;
+    Ok(expression.eval(|predicate| match predicate {
+        Predicate::Target(target) => {
+            let (key, value) = target_key_value(target);
+            cfg_values(key).contains(&value)
+        }
+        Predicate::KeyValue { key, val } => cfg_values(key).iter().any(|value| value == val),
+        Predicate::TargetFeature(feature) => cfg_values("target_feature")
+            .iter()
+            .any(|value| value == feature),
+        Predicate::Flag(flag) => std::env::var_os(cfg_var(flag)).is_some(),
+        Predicate::Feature(feature) => {
+            let var = format!("CARGO_FEATURE_{}", feature.to_uppercase().replace('-', "_"));
+            std::env::var_os(var).is_some()
+        }
+        Predicate::DebugAssertions => match std::env::var_os("CARGO_CFG_TARGET_OS") {
+            Some(_) => std::env::var_os("CARGO_CFG_DEBUG_ASSERTIONS").is_some(),
+            None => cfg!(debug_assertions),
+        },
+        Predicate::Test | Predicate::ProcMacro => false,
+    }))
+}
+
+fn target_key_value(target: &TargetPredicate) -> (&'static str, String) {
+    match target {
+        TargetPredicate::Abi(abi) => ("target_abi", abi.as_str().to_owned()),
+        TargetPredicate::Arch(arch) => ("target_arch", arch.as_str().to_owned()),
+        TargetPredicate::Endian(endian) => ("target_endian", format!("{endian:?}")),
+        TargetPredicate::Env(env) => ("target_env", env.as_str().to_owned()),
+        TargetPredicate::Family(family) => ("target_family", family.as_str().to_owned()),
+        TargetPredicate::HasAtomic(has_atomic) => ("target_has_atomic", has_atomic.to_string()),
+        TargetPredicate::Os(os) => ("target_os", os.as_str().to_owned()),
+        TargetPredicate::Panic(panic) => ("panic", panic.as_str().to_owned()),
+        TargetPredicate::PointerWidth(width) => ("target_pointer_width", width.to_string()),
+        TargetPredicate::Vendor(vendor) => ("target_vendor", vendor.as_str().to_owned()),
+    }
+}
+
+fn cfg_var(key: &str) -> String {
+    format!("CARGO_CFG_{}", key.to_uppercase())
+}
+
+// Cargo passes multiple values, e.g. for `target_family`, comma separated.
+fn cfg_values(key: &str) -> Vec<String> {
+    if std::env::var_os("CARGO_CFG_TARGET_OS").is_some() {
+        return std::env::var(cfg_var(key))
+            .map(|values| values.split(',').map(str::to_owned).collect())
+            .unwrap_or_default();
+    }
+    let value = match key {
+        "target_os" => std::env::consts::OS,
+        "target_arch" => std::env::consts::ARCH,
+        "target_family" => std::env::consts::FAMILY,
+        "target_pointer_width" => return vec![usize::BITS.to_string()],
+        "target_endian" if cfg!(target_endian = "little") => "little",
+        "target_endian" => "big",
+        "target_env" if cfg!(target_env = "msvc") => "msvc",
+        "target_env" if cfg!(target_env = "gnu") => "gnu",
+        "target_env" if cfg!(target_env = "musl") => "musl",
+        "target_vendor" if cfg!(target_vendor = "pc") => "pc",
+        "target_vendor" if cfg!(target_vendor = "apple") => "apple",
+        "target_vendor" if cfg!(target_vendor = "unknown") => "unknown",
+        _ => return Vec::new(),
+    };
+    vec![value.to_owned()]
+}
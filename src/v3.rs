@@ -0,0 +1,196 @@
+use std::collections::HashMap;
+
+use serde::Deserialize;
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct ServiceResource {
+    #[serde(rename = "@id")]
+    pub id: String,
+    #[serde(rename = "@type")]
+    pub resource_type: String,
+    pub comment: Option<String>,
+}
+
+#[derive(Clone, Debug)]
+pub struct ServiceIndex {
+    pub version: String,
+    /// Resources keyed by their `@type`, e.g. `PackageBaseAddress/3.0.0`.
+    pub resources: HashMap<String, Vec<ServiceResource>>,
+}
+
+impl ServiceIndex {
+    pub(crate) fn from_json(text: &str) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        #[derive(Deserialize)]
+        struct RawServiceIndex {
+            version: String,
+            resources: Vec<ServiceResource>,
+        }
+
+        let raw: RawServiceIndex = serde_json::from_str(text)?;
+        let mut resources: HashMap<String, Vec<ServiceResource>> = HashMap::new();
+        for resource in raw.resources {
+            resources
+                .entry(resource.resource_type.clone())
+                .or_default()
+                .push(resource);
+        }
+        Ok(Self {
+            version: raw.version,
+            resources,
+        })
+    }
+
+    /// The first resource whose `@type` is `resource_type`, ignoring any
+    /// version suffix (`SearchQueryService` matches `SearchQueryService/3.0.0-rc`).
+    pub fn resource(&self, resource_type: &str) -> Option<&ServiceResource> {
+        if let Some(resource) = self
+            .resources
+            .get(resource_type)
+            .and_then(|resources| resources.first())
+        {
+            return Some(resource);
+        }
+        let mut candidates: Vec<_> = self
+            .resources
+            .iter()
+            .filter(|(key, _)| key.split('/').next() == Some(resource_type))
+            .collect();
+        // Prefer a stable ordering over whatever the map happens to give us
+        candidates.sort_by_key(|(key, _)| *key);
+        candidates
+            .into_iter()
+            .find_map(|(_, resources)| resources.first())
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchResult {
+    pub id: String,
+    /// The latest version matching the search's prerelease setting.
+    pub version: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub total_downloads: u64,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct SearchResponse {
+    pub data: Vec<SearchResult>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PackageDeprecation {
+    /// e.g. `Legacy`, `CriticalBugs` or `Other`.
+    #[serde(default)]
+    pub reasons: Vec<String>,
+    pub message: Option<String>,
+    pub alternate_package: Option<AlternatePackage>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct AlternatePackage {
+    pub id: String,
+    pub range: Option<String>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PackageVulnerability {
+    pub advisory_url: String,
+    pub severity: VulnerabilitySeverity,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+pub enum VulnerabilitySeverity {
+    #[serde(rename = "0")]
+    Low,
+    #[serde(rename = "1")]
+    Moderate,
+    #[serde(rename = "2")]
+    High,
+    #[serde(rename = "3")]
+    Critical,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct RegistrationIndex {
+    pub items: Vec<RegistrationPage>,
+}
+
+// Pages of large packages leave out `items`, they have to be fetched from
+// the page's `@id`.
+#[derive(Deserialize)]
+pub(crate) struct RegistrationPage {
+    #[serde(rename = "@id")]
+    pub id: String,
+    pub lower: String,
+    pub upper: String,
+    pub items: Option<Vec<RegistrationLeaf>>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct RegistrationLeaf {
+    pub catalog_entry: CatalogEntry,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct CatalogEntry {
+    pub version: String,
+    #[serde(default = "listed_default")]
+    pub listed: bool,
+    pub published: Option<String>,
+    pub deprecation: Option<PackageDeprecation>,
+    #[serde(default)]
+    pub vulnerabilities: Vec<PackageVulnerability>,
+}
+
+// `{RegistrationsBaseUrl}/{id}/{version}.json`
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct RegistrationLeafDocument {
+    /// The URL of the version's `CatalogLeaf`.
+    pub catalog_entry: String,
+}
+
+/// Everything the feed knows about one version of a package.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct CatalogLeaf {
+    pub id: String,
+    pub version: String,
+    #[serde(default = "listed_default")]
+    pub listed: bool,
+    pub package_size: Option<u64>,
+    pub package_hash: Option<String>,
+    pub package_hash_algorithm: Option<String>,
+    pub published: Option<String>,
+    pub last_edited: Option<String>,
+    #[serde(default)]
+    pub dependency_groups: Vec<CatalogDependencyGroup>,
+    pub deprecation: Option<PackageDeprecation>,
+    #[serde(default)]
+    pub vulnerabilities: Vec<PackageVulnerability>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct CatalogDependencyGroup {
+    pub target_framework: Option<String>,
+    #[serde(default)]
+    pub dependencies: Vec<CatalogDependency>,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct CatalogDependency {
+    pub id: String,
+    pub range: Option<String>,
+}
+
+// Older entries leave `listed` out, they're listed unless deleted
+fn listed_default() -> bool {
+    true
+}
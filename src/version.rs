@@ -0,0 +1,472 @@
+use std::{
+    cmp::Ordering,
+    fmt,
+    hash::{Hash, Hasher},
+    str::FromStr,
+};
+
+use crate::NugetError;
+
+/// A NuGet package version: one to four numeric parts followed by optional
+/// prerelease labels and build metadata, e.g. `2022.7.30.1` or `1.0.0-beta.2+abc`.
+#[derive(Clone, Debug)]
+pub struct NugetVersion {
+    numbers: [u64; 4],
+    release_labels: Vec<String>,
+    metadata: Option<String>,
+    original: String,
+}
+
+impl NugetVersion {
+    pub fn major(&self) -> u64 {
+        self.numbers[0]
+    }
+
+    pub fn minor(&self) -> u64 {
+        self.numbers[1]
+    }
+
+    pub fn patch(&self) -> u64 {
+        self.numbers[2]
+    }
+
+    pub fn revision(&self) -> u64 {
+        self.numbers[3]
+    }
+
+    pub fn release_labels(&self) -> &[String] {
+        &self.release_labels
+    }
+
+    pub fn metadata(&self) -> Option<&str> {
+        self.metadata.as_deref()
+    }
+
+    pub fn is_prerelease(&self) -> bool {
+        !self.release_labels.is_empty()
+    }
+
+    /// The version the way NuGet normalizes it: three numeric parts, plus the
+    /// fourth only when it isn't zero, without leading zeros or build
+    /// metadata. `2022.7.30.1` stays as is, `1.0` becomes `1.0.0`.
+    pub fn normalized(&self) -> String {
+        let parts = if self.revision() == 0 { 3 } else { 4 };
+        let mut normalized = self.numbers[..parts]
+            .iter()
+            .map(u64::to_string)
+            .collect::<Vec<_>>()
+            .join(".");
+        if self.is_prerelease() {
+            normalized.push('-');
+            normalized.push_str(&self.release_labels.join("."));
+        }
+        normalized
+    }
+}
+
+impl FromStr for NugetVersion {
+    type Err = NugetError;
+
+    fn from_str(string: &str) -> Result<Self, Self::Err> {
+        let invalid = || NugetError::InvalidVersion(string.to_owned());
+        let original = string.trim();
+        let (rest, metadata) = match original.split_once('+') {
+            Some((rest, metadata)) => (rest, Some(metadata)),
+            None => (original, None),
+        };
+        let (numbers_text, release) = match rest.split_once('-') {
+            Some((numbers_text, release)) => (numbers_text, Some(release)),
+            None => (rest, None),
+        };
+
+        let mut numbers = [0; 4];
+        for (index, part) in numbers_text.split('.').enumerate() {
+            if index == numbers.len()
+                || part.is_empty()
+                || !part.bytes().all(|b| b.is_ascii_digit())
+            {
+                return Err(invalid());
+            }
+            numbers[index] = part.parse().map_err(|_| invalid())?;
+        }
+
+        let is_label = |label: &str| {
+            !label.is_empty()
+                && label
+                    .bytes()
+                    .all(|b| b.is_ascii_alphanumeric() || b == b'-')
+        };
+        let release_labels = match release {
+            Some(release) => {
+                let labels: Vec<String> = release.split('.').map(str::to_owned).collect();
+                if !labels.iter().all(|label| is_label(label)) {
+                    return Err(invalid());
+                }
+                labels
+            }
+            None => Vec::new(),
+        };
+        if let Some(metadata) = metadata {
+            if !metadata.split('.').all(is_label) {
+                return Err(invalid());
+            }
+        }
+
+        Ok(Self {
+            numbers,
+            release_labels,
+            metadata: metadata.map(str::to_owned),
+            original: original.to_owned(),
+        })
+    }
+}
+
+impl fmt::Display for NugetVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.original)
+    }
+}
+
+// Build metadata doesn't take part in precedence, and missing numeric parts
+// are zero, so `1.0` and `1.0.0.0+abc` are the same version.
+impl Ord for NugetVersion {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.numbers.cmp(&other.numbers).then_with(|| {
+            match (self.is_prerelease(), other.is_prerelease()) {
+                (false, false) => Ordering::Equal,
+                (false, true) => Ordering::Greater,
+                (true, false) => Ordering::Less,
+                (true, true) => compare_release_labels(&self.release_labels, &other.release_labels),
+            }
+        })
+    }
+}
+
+impl PartialOrd for NugetVersion {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for NugetVersion {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for NugetVersion {}
+
+// Consistent with `Eq`: metadata is ignored, numeric labels are hashed by
+// value so `01` and `1` agree, and other labels are case-insensitive.
+impl Hash for NugetVersion {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.numbers.hash(state);
+        self.release_labels.len().hash(state);
+        for label in &self.release_labels {
+            match label.parse::<u64>() {
+                Ok(number) => (0u8, number).hash(state),
+                Err(_) => (1u8, label.to_lowercase()).hash(state),
+            }
+        }
+    }
+}
+
+/// Orders two version strings by NuGet's precedence rules, see
+/// `NugetVersion`'s `Ord` impl: up to four numeric parts with missing parts
+/// being zero, then releases before prereleases, then the prerelease labels.
+pub fn compare_versions(a: &str, b: &str) -> Result<Ordering, NugetError> {
+    let a: NugetVersion = a.parse()?;
+    let b: NugetVersion = b.parse()?;
+    Ok(a.cmp(&b))
+}
+
+fn compare_release_labels(a: &[String], b: &[String]) -> Ordering {
+    for (a, b) in a.iter().zip(b) {
+        let ordering = match (a.parse::<u64>(), b.parse::<u64>()) {
+            (Ok(a), Ok(b)) => a.cmp(&b),
+            // Numeric labels sort before alphanumeric ones
+            (Ok(_), Err(_)) => Ordering::Less,
+            (Err(_), Ok(_)) => Ordering::Greater,
+            (Err(_), Err(_)) => a.to_lowercase().cmp(&b.to_lowercase()),
+        };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+    a.len().cmp(&b.len())
+}
+
+/// A NuGet version range such as `1.0` (at least 1.0), `[1.0]` (exactly 1.0)
+/// or `[1.0,2.0)`. Floating versions like `1.2.*` (any `1.2.z`), `*` (any
+/// version) or `>=1.2.0` (at least 1.2.0) are ranges too, but resolve to the
+/// highest match rather than the lowest.
+#[derive(Clone, Debug)]
+pub struct VersionRange {
+    min: Option<(NugetVersion, bool)>,
+    max: Option<(NugetVersion, bool)>,
+    floating: bool,
+    original: String,
+}
+
+impl VersionRange {
+    /// The lower bound and whether it's inclusive.
+    pub fn min(&self) -> Option<(&NugetVersion, bool)> {
+        self.min
+            .as_ref()
+            .map(|(version, inclusive)| (version, *inclusive))
+    }
+
+    /// The upper bound and whether it's inclusive.
+    pub fn max(&self) -> Option<(&NugetVersion, bool)> {
+        self.max
+            .as_ref()
+            .map(|(version, inclusive)| (version, *inclusive))
+    }
+
+    pub fn is_floating(&self) -> bool {
+        self.floating
+    }
+
+    pub fn satisfies(&self, version: &NugetVersion) -> bool {
+        let above_min = match &self.min {
+            Some((min, true)) => version >= min,
+            Some((min, false)) => version > min,
+            None => true,
+        };
+        let below_max = match &self.max {
+            Some((max, true)) => version <= max,
+            Some((max, false)) => version < max,
+            None => true,
+        };
+        above_min && below_max
+    }
+}
+
+impl FromStr for VersionRange {
+    type Err = NugetError;
+
+    fn from_str(string: &str) -> Result<Self, Self::Err> {
+        let invalid = || NugetError::InvalidVersion(string.to_owned());
+        let original = string.trim();
+        let bound = |text: &str| -> Result<Option<NugetVersion>, NugetError> {
+            let text = text.trim();
+            if text.is_empty() {
+                Ok(None)
+            } else {
+                text.parse().map(Some)
+            }
+        };
+
+        if let Some(prefix) = original.strip_suffix('*') {
+            return floating_range(prefix, original).ok_or_else(invalid);
+        }
+        if let Some(min) = original.strip_prefix(">=") {
+            return Ok(Self {
+                min: Some((bound(min)?.ok_or_else(invalid)?, true)),
+                max: None,
+                floating: true,
+                original: original.to_owned(),
+            });
+        }
+
+        let (min, max) = if let Some(rest) = original.strip_prefix(['[', '(']) {
+            let min_inclusive = original.starts_with('[');
+            let (inner, max_inclusive) = if let Some(inner) = rest.strip_suffix(']') {
+                (inner, true)
+            } else if let Some(inner) = rest.strip_suffix(')') {
+                (inner, false)
+            } else {
+                return Err(invalid());
+            };
+            match inner.split_once(',') {
+                Some((min, max)) => (
+                    bound(min)?.map(|min| (min, min_inclusive)),
+                    bound(max)?.map(|max| (max, max_inclusive)),
+                ),
+                // `[1.0]` is the only valid single version form
+                None if min_inclusive && max_inclusive => {
+                    let version = bound(inner)?.ok_or_else(invalid)?;
+                    (Some((version.clone(), true)), Some((version, true)))
+                }
+                None => return Err(invalid()),
+            }
+        } else {
+            (bound(original)?.map(|min| (min, true)), None)
+        };
+
+        Ok(Self {
+            min,
+            max,
+            floating: false,
+            original: original.to_owned(),
+        })
+    }
+}
+
+// `1.2.*` covers `[1.2.0, 1.3.0)` and `*` covers everything.
+fn floating_range(prefix: &str, original: &str) -> Option<VersionRange> {
+    let (min, max) = if prefix.is_empty() {
+        (None, None)
+    } else {
+        let numbers = prefix.strip_suffix('.')?;
+        let min: NugetVersion = numbers.parse().ok()?;
+        if min.is_prerelease() || min.metadata().is_some() {
+            return None;
+        }
+        let parts = numbers.split('.').count();
+        let mut upper = min.numbers;
+        upper[parts - 1] = upper[parts - 1].checked_add(1)?;
+        let max_text = upper[..parts]
+            .iter()
+            .map(u64::to_string)
+            .collect::<Vec<_>>()
+            .join(".");
+        (Some((min, true)), Some((max_text.parse().ok()?, false)))
+    };
+    Some(VersionRange {
+        min,
+        max,
+        floating: true,
+        original: original.to_owned(),
+    })
+}
+
+impl fmt::Display for VersionRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.original)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn version(text: &str) -> NugetVersion {
+        text.parse().unwrap()
+    }
+
+    // Each version is lower than the next
+    fn assert_ascending(versions: &[&str]) {
+        for pair in versions.windows(2) {
+            assert!(
+                version(pair[0]) < version(pair[1]),
+                "{} < {}",
+                pair[0],
+                pair[1]
+            );
+        }
+    }
+
+    #[test]
+    fn orders_four_part_versions() {
+        assert_ascending(&[
+            "1.0.0",
+            "1.0.0.1",
+            "1.0.1",
+            "2022.7.30",
+            "2022.7.30.1",
+            "2022.7.30.10",
+            "2022.8.1",
+        ]);
+        assert_eq!(version("1.0"), version("1.0.0.0"));
+    }
+
+    #[test]
+    fn orders_prereleases_before_releases() {
+        assert_ascending(&["1.0.0-alpha", "1.0.0-beta", "1.0.0", "1.0.1-alpha"]);
+        assert_ascending(&["2022.7.30.1-preview", "2022.7.30.1"]);
+    }
+
+    #[test]
+    fn orders_numeric_labels_numerically_and_before_alphanumeric_ones() {
+        assert_ascending(&[
+            "1.0.0-2",
+            "1.0.0-10",
+            "1.0.0-alpha",
+            "1.0.0-alpha.2",
+            "1.0.0-alpha.10",
+            "1.0.0-alpha.beta",
+            "1.0.0-beta",
+        ]);
+        assert_eq!(version("1.0.0-RC.1"), version("1.0.0-rc.1"));
+    }
+
+    #[test]
+    fn compare_versions_follows_nuget_precedence() {
+        let cases = [
+            ("2022.7.30.1", "2022.7.30", Ordering::Greater),
+            ("2022.7.30.1", "2022.7.30.1", Ordering::Equal),
+            ("2022.7.30.1", "2022.12.1", Ordering::Less),
+            ("1.0", "1.0.0.0", Ordering::Equal),
+            ("1.0.0.1", "1.0.1", Ordering::Less),
+            ("1.0.0-beta", "1.0.0", Ordering::Less),
+            ("1.0.0-beta.2", "1.0.0-beta.10", Ordering::Less),
+            ("1.0.0-1", "1.0.0-alpha", Ordering::Less),
+            ("1.0.0-ALPHA", "1.0.0-alpha", Ordering::Equal),
+            ("1.0.0+build.1", "1.0.0+build.2", Ordering::Equal),
+            ("2.0.220608.4", "2.0.220531.1", Ordering::Greater),
+        ];
+        for (a, b, expected) in cases {
+            assert_eq!(compare_versions(a, b).unwrap(), expected, "{a} vs {b}");
+        }
+        assert!(compare_versions("1.0.0.0.0", "1.0").is_err());
+        assert!(compare_versions("1.0", "latest").is_err());
+    }
+
+    #[test]
+    fn parses_four_part_versions() {
+        let parsed = version("2022.7.30.1");
+        assert_eq!(
+            (
+                parsed.major(),
+                parsed.minor(),
+                parsed.patch(),
+                parsed.revision()
+            ),
+            (2022, 7, 30, 1)
+        );
+        assert!(!parsed.is_prerelease());
+        assert_eq!(parsed.to_string(), "2022.7.30.1");
+        assert_eq!(parsed.normalized(), "2022.7.30.1");
+        assert_eq!(version("2022.07.30.0").normalized(), "2022.7.30");
+        assert_eq!(
+            version("2022.7.30.1-beta+abc").normalized(),
+            "2022.7.30.1-beta"
+        );
+        assert!("2022.7.30.1.2".parse::<NugetVersion>().is_err());
+        assert!("2022.7..1".parse::<NugetVersion>().is_err());
+    }
+
+    #[test]
+    fn four_part_ranges() {
+        let range: VersionRange = "[2022.7.30.1, 2023)".parse().unwrap();
+        assert!(range.satisfies(&version("2022.7.30.1")));
+        assert!(range.satisfies(&version("2022.12.1")));
+        assert!(!range.satisfies(&version("2022.7.30")));
+        assert!(!range.satisfies(&version("2023.0.0")));
+    }
+
+    #[test]
+    fn equal_versions_hash_the_same() {
+        use std::collections::HashSet;
+
+        let pairs = [
+            ("1.0.0-01", "1.0.0-1"),
+            ("1.0.0-RC.1", "1.0.0-rc.1"),
+            ("1.0", "1.0.0.0"),
+            ("2022.7.30.1+abc", "2022.7.30.1"),
+        ];
+        for (a, b) in pairs {
+            assert_eq!(version(a), version(b));
+            let set = HashSet::from([version(a)]);
+            assert!(set.contains(&version(b)), "{a} and {b}");
+        }
+    }
+
+    #[test]
+    fn ignores_metadata() {
+        assert_eq!(version("1.0.0+abc"), version("1.0.0+def"));
+        assert_eq!(version("1.0.0-rc.1+abc"), version("1.0.0-rc.1"));
+        assert_eq!(version("1.0.0+abc").metadata(), Some("abc"));
+    }
+}
@@ -0,0 +1,205 @@
+// Not every test uses every helper
+#![allow(dead_code)]
+
+use std::{
+    io::{BufRead, BufReader, Read, Write},
+    net::{TcpListener, TcpStream},
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
+use sha2::{Digest, Sha256, Sha512};
+
+pub struct Request {
+    pub path: String,
+    /// Header names are lowercase.
+    pub headers: Vec<(String, String)>,
+}
+
+impl Request {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+pub struct Response {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+    /// Closes the connection after this many bytes of the body, while
+    /// `Content-Length` still announces the whole body.
+    pub cut_at: Option<usize>,
+}
+
+impl Response {
+    pub fn ok<B: Into<Vec<u8>>>(body: B) -> Self {
+        Self {
+            status: 200,
+            headers: Vec::new(),
+            body: body.into(),
+            cut_at: None,
+        }
+    }
+
+    pub fn not_found() -> Self {
+        Self {
+            status: 404,
+            ..Self::ok("")
+        }
+    }
+
+    pub fn with_header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_owned(), value.to_owned()));
+        self
+    }
+}
+
+/// A feed answering every request with `handler`, one connection per
+/// request. Returns the base URL and the paths requested so far.
+pub fn serve<F>(handler: F) -> (String, Arc<Mutex<Vec<String>>>)
+where
+    F: Fn(&Request) -> Response + Send + Sync + 'static,
+{
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let requests = Arc::new(Mutex::new(Vec::new()));
+    let handler = Arc::new(handler);
+    let logged = requests.clone();
+    std::thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let handler = handler.clone();
+            let logged = logged.clone();
+            std::thread::spawn(move || {
+                if let Some(request) = read_request(&stream) {
+                    logged.lock().unwrap().push(request.path.clone());
+                    let _ = write_response(stream, handler(&request));
+                }
+            });
+        }
+    });
+    (url, requests)
+}
+
+fn read_request(stream: &TcpStream) -> Option<Request> {
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    reader.read_line(&mut line).ok()?;
+    let path = line.split_whitespace().nth(1)?.to_owned();
+    let mut headers = Vec::new();
+    loop {
+        let mut line = String::new();
+        reader.read_line(&mut line).ok()?;
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        let (name, value) = line.split_once(':')?;
+        headers.push((name.trim().to_lowercase(), value.trim().to_owned()));
+    }
+    Some(Request { path, headers })
+}
+
+fn write_response(mut stream: TcpStream, response: Response) -> std::io::Result<()> {
+    let mut head = format!(
+        "HTTP/1.1 {} Test\r\nContent-Length: {}\r\nConnection: close\r\n",
+        response.status,
+        response.body.len()
+    );
+    for (name, value) in &response.headers {
+        head.push_str(&format!("{name}: {value}\r\n"));
+    }
+    head.push_str("\r\n");
+    stream.write_all(head.as_bytes())?;
+    let len = response.cut_at.unwrap_or(response.body.len());
+    stream.write_all(&response.body[..len])?;
+    stream.flush()?;
+    if response.cut_at.is_some() {
+        // Give the client time to read what was sent before the reset
+        std::thread::sleep(std::time::Duration::from_millis(100));
+    }
+    Ok(())
+}
+
+/// A `.nupkg` whose `.nuspec` names `id` and `version`, padded with
+/// `padding` bytes of stored content.
+pub fn nupkg(id: &str, version: &str, padding: usize) -> Vec<u8> {
+    let mut bytes = std::io::Cursor::new(Vec::new());
+    let mut zip = zip::ZipWriter::new(&mut bytes);
+    let options =
+        zip::write::FileOptions::default().compression_method(zip::CompressionMethod::Stored);
+    zip.start_file(format!("{id}.nuspec"), options).unwrap();
+    write!(
+        zip,
+        "<?xml version=\"1.0\"?><package><metadata><id>{id}</id><version>{version}</version>\
+         </metadata></package>"
+    )
+    .unwrap();
+    zip.start_file("content/padding.bin", options).unwrap();
+    let pattern: Vec<u8> = (0..padding).map(|index| (index % 251) as u8).collect();
+    zip.write_all(&pattern).unwrap();
+    zip.finish().unwrap();
+    drop(zip);
+    bytes.into_inner()
+}
+
+pub fn sha512_base64(bytes: &[u8]) -> String {
+    base64::encode(Sha512::digest(bytes))
+}
+
+pub fn sha256_base64(bytes: &[u8]) -> String {
+    base64::encode(Sha256::digest(bytes))
+}
+
+/// A V2 feed serving `package` for every download, with metadata reporting
+/// `hash` and `algorithm` for every version.
+pub fn v2_feed(package: Vec<u8>, hash: String, algorithm: &'static str) -> String {
+    let (url, _) = serve(move |request| {
+        if request.path.starts_with("/package/") {
+            return Response::ok(package.clone());
+        }
+        let size = package.len().to_string();
+        Response::ok(odata_feed(&[odata_entry(&[
+            ("Id", "Foo"),
+            ("Version", "1.0.0"),
+            ("PackageHash", &hash),
+            ("PackageHashAlgorithm", algorithm),
+            ("PackageSize", &size),
+        ])]))
+    });
+    url
+}
+
+/// An OData entry with the given `m:properties`, written as is.
+pub fn odata_entry(properties: &[(&str, &str)]) -> String {
+    let properties: String = properties
+        .iter()
+        .map(|(name, value)| format!("<d:{name}>{value}</d:{name}>"))
+        .collect();
+    format!("<entry><m:properties>{properties}</m:properties></entry>")
+}
+
+pub fn odata_feed(entries: &[String]) -> String {
+    format!(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?><feed xmlns=\"http://www.w3.org/2005/Atom\" \
+         xmlns:d=\"http://schemas.microsoft.com/ado/2007/08/dataservices\" \
+         xmlns:m=\"http://schemas.microsoft.com/ado/2007/08/dataservices/metadata\">{}</feed>",
+        entries.concat()
+    )
+}
+
+/// An empty directory under the system temp directory, unique to `name`.
+pub fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("nuget-dl-test-{}-{name}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+pub fn read_to_end(mut reader: impl Read) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes).unwrap();
+    bytes
+}
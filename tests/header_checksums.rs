@@ -0,0 +1,55 @@
+mod common;
+
+use common::{nupkg, serve, sha512_base64, temp_dir, v2_feed, Response};
+use nuget_dl::{NugetClient, NugetError};
+
+#[test]
+fn feeds_can_vouch_for_their_own_downloads() {
+    let package = nupkg("Foo", "1.0.0", 0);
+    let hash = sha512_base64(&package);
+    let (url, requests) = serve(move |request| {
+        if request.path.starts_with("/package/") {
+            return Response::ok(package.clone()).with_header("x-ms-meta-sha512", &hash);
+        }
+        Response::not_found()
+    });
+    let dir = temp_dir("header-checksum");
+    NugetClient::new()
+        .with_download_url(&url)
+        .with_metadata_url(&url)
+        .with_header_checksums(true)
+        .download_package("Foo", "1.0.0", &dir)
+        .unwrap();
+    // The metadata was never needed
+    let requests = requests.lock().unwrap();
+    assert!(requests.iter().all(|path| path.starts_with("/package/")));
+}
+
+#[test]
+fn mirrors_cant_vouch_for_their_own_downloads() {
+    let package = nupkg("Foo", "1.0.0", 0);
+    let feed = v2_feed(package.clone(), sha512_base64(&package), "SHA512");
+    let tampered = nupkg("Foo", "1.0.0", 16);
+    let tampered_hash = sha512_base64(&tampered);
+    let (mirror, _) = serve(move |_| {
+        Response::ok(tampered.clone()).with_header("x-ms-meta-sha512", &tampered_hash)
+    });
+    let dir = temp_dir("header-checksum-mirror");
+    let error = match NugetClient::new()
+        .with_download_url(&mirror)
+        .with_metadata_url(&feed)
+        .with_header_checksums(true)
+        .download_package("Foo", "1.0.0", &dir)
+    {
+        Ok(_) => panic!("the mirror's checksum was trusted"),
+        Err(error) => error,
+    };
+    assert!(
+        matches!(
+            error.downcast_ref::<NugetError>(),
+            Some(NugetError::HashMismatch { .. })
+        ),
+        "{error}"
+    );
+    assert!(!dir.join("Foo.1.0.0.nupkg").exists());
+}
@@ -0,0 +1,51 @@
+mod common;
+
+use common::{nupkg, sha512_base64, temp_dir, v2_feed};
+use nuget_dl::{NugetClient, NugetError};
+
+// A feed serving `package` as Foo 1.0.0, with a hash that matches it.
+fn client(package: Vec<u8>) -> NugetClient {
+    let hash = sha512_base64(&package);
+    let url = v2_feed(package, hash, "SHA512");
+    NugetClient::new()
+        .with_download_url(&url)
+        .with_metadata_url(&url)
+}
+
+#[test]
+fn mismatched_packages_are_only_rejected_when_asked() {
+    let package = nupkg("Bar", "2.0.0", 0);
+
+    let dir = temp_dir("identity-default");
+    client(package.clone())
+        .download_package("Foo", "1.0.0", &dir)
+        .unwrap();
+    assert!(dir.join("Foo.1.0.0.nupkg").exists());
+
+    let dir = temp_dir("identity-verified");
+    let error = match client(package)
+        .with_verify_identity(true)
+        .download_package("Foo", "1.0.0", &dir)
+    {
+        Ok(_) => panic!("a package for Bar 2.0.0 was accepted as Foo 1.0.0"),
+        Err(error) => error,
+    };
+    assert!(
+        matches!(
+            error.downcast_ref::<NugetError>(),
+            Some(NugetError::IdentityMismatch { actual_id, actual_version, .. })
+                if actual_id == "Bar" && actual_version == "2.0.0"
+        ),
+        "{error}"
+    );
+    assert!(!dir.join("Foo.1.0.0.nupkg").exists());
+}
+
+#[test]
+fn identities_compare_the_way_nuget_does() {
+    let dir = temp_dir("identity-normalized");
+    client(nupkg("foo", "1.0", 0))
+        .with_verify_identity(true)
+        .download_package("Foo", "1.0.0", &dir)
+        .unwrap();
+}
@@ -0,0 +1,71 @@
+mod common;
+
+use common::{odata_entry, odata_feed, serve, Response};
+use nuget_dl::{NugetClient, NugetError};
+
+fn client() -> NugetClient {
+    let (url, _) = serve(|request| {
+        if !request.path.starts_with("/FindPackagesById()") {
+            return Response::not_found();
+        }
+        Response::ok(odata_feed(&[
+            odata_entry(&[
+                ("Id", "Foo"),
+                ("Version", "1.0.0"),
+                ("Published", "2022-01-01T00:00:00"),
+            ]),
+            odata_entry(&[
+                ("Id", "Foo"),
+                ("Version", "2.0.0"),
+                ("Listed", "false"),
+                ("Published", "2022-02-01T00:00:00"),
+            ]),
+            // nuget.org's way of marking a version unlisted
+            odata_entry(&[
+                ("Id", "Foo"),
+                ("Version", "1.5.0"),
+                ("Published", "1900-01-01T00:00:00"),
+            ]),
+        ]))
+    });
+    NugetClient::new().with_metadata_url(url)
+}
+
+#[test]
+fn lists_unlisted_versions_only_when_asked() {
+    let client = client();
+    assert_eq!(
+        client.list_package_versions("Foo", false).unwrap(),
+        ["1.0.0"]
+    );
+    assert_eq!(
+        client.list_package_versions("Foo", true).unwrap(),
+        ["1.0.0", "2.0.0", "1.5.0"]
+    );
+}
+
+#[test]
+fn latest_version_skips_unlisted_versions() {
+    assert_eq!(
+        client().resolve_latest_version("Foo", false).unwrap(),
+        "1.0.0"
+    );
+}
+
+#[test]
+fn empty_feeds_have_no_versions() {
+    let (url, _) = serve(|_| Response::ok(odata_feed(&[])));
+    let client = NugetClient::new().with_metadata_url(url);
+    assert!(client
+        .list_package_versions("Foo", true)
+        .unwrap()
+        .is_empty());
+    let error = client.resolve_latest_version("Foo", true).unwrap_err();
+    assert!(
+        matches!(
+            error.downcast_ref::<NugetError>(),
+            Some(NugetError::NoMatchingVersion { .. })
+        ),
+        "{error}"
+    );
+}
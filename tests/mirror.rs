@@ -0,0 +1,61 @@
+mod common;
+
+use std::path::PathBuf;
+
+use common::{nupkg, serve, sha512_base64, temp_dir, v2_feed, Response};
+use nuget_dl::{mirror_with_client, FeedSource, NugetClient, PackageId};
+
+// Serves `dir` as a static V3 feed whose flat container is `dir` itself.
+fn serve_static(dir: PathBuf) -> String {
+    let (url, _) = serve(move |request| {
+        let host = request.header("host").unwrap_or_default();
+        match request.path.strip_prefix("/flat/") {
+            Some(path) => match std::fs::read(dir.join(path)) {
+                Ok(bytes) => Response::ok(bytes),
+                Err(_) => Response::not_found(),
+            },
+            None if request.path == "/index.json" => Response::ok(format!(
+                "{{\"version\": \"3.0.0\", \"resources\": [{{\"@id\": \"http://{host}/flat/\", \
+                 \"@type\": \"PackageBaseAddress/3.0.0\"}}]}}"
+            )),
+            None => Response::not_found(),
+        }
+    });
+    format!("{url}/index.json")
+}
+
+#[test]
+fn mirrors_serve_unnormalized_versions_as_a_v3_feed() {
+    let package = nupkg("Foo", "1.0", 0);
+    let hash = sha512_base64(&package);
+    let feed = v2_feed(package.clone(), hash.clone(), "SHA512");
+    let dest = temp_dir("mirror");
+    let report = mirror_with_client(
+        NugetClient::new()
+            .with_download_url(&feed)
+            .with_metadata_url(&feed),
+        &[PackageId::new("Foo", "1.0").unwrap()],
+        &dest,
+    );
+    assert!(report.is_success());
+    let version_dir = dest.join("foo").join("1.0.0");
+    assert!(version_dir.join("foo.1.0.0.nupkg").is_file());
+    assert_eq!(
+        std::fs::read_to_string(version_dir.join("foo.1.0.0.nupkg.sha512")).unwrap(),
+        hash
+    );
+    assert_eq!(
+        std::fs::read_to_string(dest.join("foo").join("index.json")).unwrap(),
+        "{\"versions\":[\"1.0.0\"]}"
+    );
+
+    // Downloaded back from the mirror, verified against the original feed
+    let index = serve_static(dest);
+    let dir = temp_dir("mirror-client");
+    NugetClient::new()
+        .with_download_source(FeedSource::V3(index))
+        .with_metadata_url(&feed)
+        .download_package("Foo", "1.0", &dir)
+        .unwrap();
+    assert!(std::fs::read(dir.join("Foo.1.0.nupkg")).unwrap() == package);
+}
@@ -0,0 +1,113 @@
+mod common;
+
+use common::{nupkg, odata_feed, serve, sha512_base64, temp_dir, v2_feed, Response};
+use nuget_dl::{NugetClient, NugetError, UnsupportedHashPolicy};
+
+fn client(entry: &'static str) -> NugetClient {
+    let (url, _) = serve(move |_| Response::ok(odata_feed(&[entry.to_owned()])));
+    NugetClient::new().with_metadata_url(url)
+}
+
+fn nuget_error<'a>(error: &'a (dyn std::error::Error + Send + Sync + 'static)) -> &'a NugetError {
+    error
+        .downcast_ref::<NugetError>()
+        .unwrap_or_else(|| panic!("not a NugetError: {error}"))
+}
+
+#[test]
+fn null_hashes_are_unavailable() {
+    let client = client(
+        "<entry><m:properties><d:Id>Foo</d:Id><d:Version>1.0.0</d:Version>\
+         <d:PackageHash m:null=\"true\" />\
+         <d:PackageHashAlgorithm>SHA512</d:PackageHashAlgorithm>\
+         </m:properties></entry>",
+    );
+    let error = client.get_package_hash("Foo", "1.0.0").unwrap_err();
+    assert!(
+        matches!(nuget_error(&*error), NugetError::HashUnavailable { .. }),
+        "{error}"
+    );
+}
+
+#[test]
+fn configured_property_aliases_are_used() {
+    let entry = "<entry><m:properties><d:Id>Foo</d:Id><d:Version>1.0.0</d:Version>\
+                 <d:ContentHash>abc==</d:ContentHash>\
+                 <d:ContentHashAlgorithm>SHA512</d:ContentHashAlgorithm>\
+                 </m:properties></entry>";
+    let hash = client(entry)
+        .with_odata_property_alias("ContentHash", "PackageHash")
+        .with_odata_property_alias("ContentHashAlgorithm", "PackageHashAlgorithm")
+        .get_package_hash("Foo", "1.0.0")
+        .unwrap();
+    assert_eq!(hash.hash, "abc==");
+}
+
+fn assert_not_found(client: &NugetClient) {
+    let error = client.get_package_hash("Foo", "1.0.0").unwrap_err();
+    assert!(
+        matches!(
+            nuget_error(&*error),
+            NugetError::PackageNotFound { package_name, version }
+                if package_name == "Foo" && version == "1.0.0"
+        ),
+        "{error}"
+    );
+    let error = client.get_package_metadata("Foo", "1.0.0").unwrap_err();
+    assert!(
+        matches!(nuget_error(&*error), NugetError::PackageNotFound { .. }),
+        "{error}"
+    );
+}
+
+#[test]
+fn empty_feeds_are_not_found() {
+    let (url, _) = serve(|_| Response::ok(odata_feed(&[])));
+    assert_not_found(&NugetClient::new().with_metadata_url(url));
+}
+
+#[test]
+fn error_documents_are_not_found() {
+    let (url, _) = serve(|_| {
+        Response::ok(
+            "<?xml version=\"1.0\" encoding=\"utf-8\"?>\
+             <m:error xmlns:m=\"http://schemas.microsoft.com/ado/2007/08/dataservices/metadata\">\
+             <m:code /><m:message xml:lang=\"en-US\">Resource not found for the segment \
+             'Packages'.</m:message></m:error>",
+        )
+    });
+    assert_not_found(&NugetClient::new().with_metadata_url(url));
+}
+
+#[test]
+fn unsupported_hash_algorithms_fail_unless_skipped() {
+    let package = nupkg("Foo", "1.0.0", 0);
+    let url = v2_feed(package.clone(), "abc==".to_owned(), "MD5");
+    let client = || {
+        NugetClient::new()
+            .with_download_url(&url)
+            .with_metadata_url(&url)
+    };
+    let dir = temp_dir("unsupported-hash");
+    let error = match client().download_package("Foo", "1.0.0", &dir) {
+        Ok(_) => panic!("an MD5 hash was accepted"),
+        Err(error) => error,
+    };
+    assert!(
+        matches!(
+            nuget_error(&*error),
+            NugetError::UnsupportedHashAlgorithm(algorithm) if algorithm == "MD5"
+        ),
+        "{error}"
+    );
+    assert!(!dir.join("Foo.1.0.0.nupkg").exists());
+
+    let file = client()
+        .with_unsupported_hash_policy(UnsupportedHashPolicy::SkipVerification)
+        .download_package("Foo", "1.0.0", &dir)
+        .unwrap();
+    assert_eq!(
+        sha512_base64(&std::fs::read(dir.join(format!("{}.nupkg", file.stem))).unwrap()),
+        sha512_base64(&package)
+    );
+}
@@ -0,0 +1,30 @@
+mod common;
+
+use common::{nupkg, sha512_base64, temp_dir, v2_feed};
+use nuget_dl::NugetClient;
+
+#[test]
+fn cached_packages_match_the_feed_hash_byte_for_byte() {
+    // Trailing bytes after the archive survive a verbatim copy but not a
+    // round trip through a zip writer
+    let mut package = nupkg("Foo", "1.0.0", 64 * 1024);
+    package.extend_from_slice(b"not part of the archive");
+    let hash = sha512_base64(&package);
+    let url = v2_feed(package.clone(), hash.clone(), "SHA512");
+    let client = NugetClient::new()
+        .with_download_url(&url)
+        .with_metadata_url(&url);
+    let dir = temp_dir("received-bytes");
+
+    client.download_package("Foo", "1.0.0", &dir).unwrap();
+    let cached = std::fs::read(dir.join("Foo.1.0.0.nupkg")).unwrap();
+    assert_eq!(sha512_base64(&cached), hash);
+    assert!(
+        cached == package,
+        "the cached file differs from the received bytes"
+    );
+
+    // A cached lookup verifies the same bytes without rewriting them
+    client.download_package("Foo", "1.0.0", &dir).unwrap();
+    assert!(std::fs::read(dir.join("Foo.1.0.0.nupkg")).unwrap() == package);
+}
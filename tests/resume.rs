@@ -0,0 +1,86 @@
+mod common;
+
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc, Arc,
+    },
+    time::Duration,
+};
+
+use common::{nupkg, odata_entry, odata_feed, serve, sha512_base64, temp_dir, Response};
+use nuget_dl::{NugetClient, RetryPolicy};
+
+#[test]
+fn resumed_downloads_verify_against_the_whole_package() {
+    let package = nupkg("Foo", "1.0.0", 512 * 1024);
+    let hash = sha512_base64(&package);
+    let resumes = Arc::new(AtomicUsize::new(0));
+    let (url, _) = {
+        let package = package.clone();
+        let resumes = resumes.clone();
+        serve(move |request| {
+            if !request.path.starts_with("/package/") {
+                let size = package.len().to_string();
+                return Response::ok(odata_feed(&[odata_entry(&[
+                    ("Id", "Foo"),
+                    ("Version", "1.0.0"),
+                    ("PackageHash", &hash),
+                    ("PackageHashAlgorithm", "SHA512"),
+                    ("PackageSize", &size),
+                ])]));
+            }
+            let start = request
+                .header("range")
+                .and_then(|range| range.strip_prefix("bytes="))
+                .and_then(|range| range.trim_end_matches('-').parse::<usize>().ok());
+            match start {
+                Some(start) => {
+                    resumes.fetch_add(1, Ordering::SeqCst);
+                    let range = format!("bytes {start}-{}/{}", package.len() - 1, package.len());
+                    Response {
+                        status: 206,
+                        ..Response::ok(&package[start..])
+                    }
+                    .with_header("Content-Range", &range)
+                }
+                // Every full request breaks off halfway through the body
+                None => Response {
+                    cut_at: Some(package.len() / 2),
+                    ..Response::ok(package.clone())
+                }
+                .with_header("Accept-Ranges", "bytes"),
+            }
+        })
+    };
+    let dir = temp_dir("resume");
+
+    // A single connection per host deadlocks if the broken response still
+    // holds its permit when the rest is requested, so don't wait forever
+    let (sender, receiver) = mpsc::channel();
+    {
+        let dir = dir.clone();
+        std::thread::spawn(move || {
+            let result = NugetClient::new()
+                .with_download_url(&url)
+                .with_metadata_url(&url)
+                .with_max_connections_per_host(1)
+                .with_download_retry_policy(RetryPolicy {
+                    initial_backoff: Duration::from_millis(10),
+                    ..RetryPolicy::default()
+                })
+                .download_package("Foo", "1.0.0", &dir)
+                .map(|_| ())
+                .map_err(|error| error.to_string());
+            let _ = sender.send(result);
+        });
+    }
+    receiver
+        .recv_timeout(Duration::from_secs(30))
+        .expect("the resumed download hung")
+        .unwrap();
+
+    assert_eq!(resumes.load(Ordering::SeqCst), 1);
+    let cached = std::fs::read(dir.join("Foo.1.0.0.nupkg")).unwrap();
+    assert!(cached == package, "the resumed package differs");
+}
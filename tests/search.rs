@@ -0,0 +1,44 @@
+mod common;
+
+use common::{serve, Response};
+use nuget_dl::{FeedSource, NugetClient};
+
+#[test]
+fn searches_the_configured_v3_feed() {
+    let (url, requests) = serve(|request| {
+        let host = request.header("host").unwrap_or_default();
+        match request.path.split('?').next() {
+            Some("/index.json") => Response::ok(format!(
+                "{{\"version\": \"3.0.0\", \"resources\": [{{\"@id\": \"http://{host}/query\", \
+                 \"@type\": \"SearchQueryService\"}}]}}"
+            )),
+            Some("/query") => {
+                Response::ok("{\"data\": [{\"id\": \"Foo\", \"version\": \"1.0.0\"}]}")
+            }
+            _ => Response::not_found(),
+        }
+    });
+    let client =
+        NugetClient::new().with_metadata_source(FeedSource::V3(format!("{url}/index.json")));
+    for _ in 0..2 {
+        let results = client.search_packages("foo", 10, false).unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].id, "Foo");
+    }
+
+    // The service index is fetched once
+    let requests = requests.lock().unwrap();
+    let paths: Vec<_> = requests
+        .iter()
+        .map(|path| path.split('?').next().unwrap())
+        .collect();
+    assert_eq!(paths, ["/index.json", "/query", "/query"]);
+}
+
+#[test]
+fn other_v2_feeds_have_no_service_index() {
+    let (url, requests) = serve(|_| Response::not_found());
+    let client = NugetClient::new().with_metadata_url(url);
+    assert!(client.search_packages("foo", 10, false).is_err());
+    assert!(requests.lock().unwrap().is_empty());
+}
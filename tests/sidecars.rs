@@ -0,0 +1,43 @@
+mod common;
+
+use std::path::Path;
+
+use common::{nupkg, sha256_base64, sha512_base64, temp_dir, v2_feed};
+use nuget_dl::NugetClient;
+
+fn client(url: &str) -> NugetClient {
+    NugetClient::new()
+        .with_download_url(url)
+        .with_metadata_url(url)
+        .with_hash_sidecars(true)
+}
+
+fn sidecar(dir: &Path) -> Option<String> {
+    std::fs::read_to_string(dir.join("Foo.1.0.0.nupkg.sha512")).ok()
+}
+
+#[test]
+fn writes_sidecars_for_packages_verified_with_sha512() {
+    let package = nupkg("Foo", "1.0.0", 0);
+    let hash = sha512_base64(&package);
+    let url = v2_feed(package, hash.clone(), "SHA512");
+    let dir = temp_dir("sidecar-sha512");
+    client(&url).download_package("Foo", "1.0.0", &dir).unwrap();
+    assert_eq!(sidecar(&dir), Some(hash));
+}
+
+#[test]
+fn no_sidecar_for_packages_verified_with_another_algorithm() {
+    let package = nupkg("Foo", "1.0.0", 0);
+    let hash = sha256_base64(&package);
+    let url = v2_feed(package, hash, "SHA256");
+    let dir = temp_dir("sidecar-sha256");
+    let client = client(&url);
+    client.download_package("Foo", "1.0.0", &dir).unwrap();
+    assert_eq!(sidecar(&dir), None);
+
+    // A stale sidecar from earlier is removed rather than trusted
+    std::fs::write(dir.join("Foo.1.0.0.nupkg.sha512"), "stale").unwrap();
+    client.download_package("Foo", "1.0.0", &dir).unwrap();
+    assert_eq!(sidecar(&dir), None);
+}
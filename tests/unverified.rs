@@ -0,0 +1,90 @@
+mod common;
+
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc, Mutex,
+};
+
+use common::{nupkg, odata_entry, odata_feed, serve, sha512_base64, temp_dir, Response};
+use nuget_dl::{NugetClient, RetryPolicy};
+
+static WARNINGS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+struct WarningLogger;
+
+impl log::Log for WarningLogger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        metadata.level() <= log::Level::Warn
+    }
+
+    fn log(&self, record: &log::Record) {
+        if self.enabled(record.metadata()) {
+            WARNINGS.lock().unwrap().push(record.args().to_string());
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+fn unverified_warnings() -> usize {
+    WARNINGS
+        .lock()
+        .unwrap()
+        .iter()
+        .filter(|warning| warning.starts_with("Not verifying Foo 1.0.0"))
+        .count()
+}
+
+#[test]
+fn packages_accepted_unverified_are_never_trusted_later() {
+    log::set_logger(&WarningLogger).unwrap();
+    log::set_max_level(log::LevelFilter::Warn);
+
+    let package = nupkg("Foo", "1.0.0", 0);
+    let hash = sha512_base64(&package);
+    let metadata_up = Arc::new(AtomicBool::new(false));
+    let feed_metadata_up = metadata_up.clone();
+    let (url, _) = serve(move |request| {
+        if request.path.starts_with("/package/") {
+            return Response::ok(package.clone());
+        }
+        if !feed_metadata_up.load(Ordering::SeqCst) {
+            return Response {
+                status: 500,
+                ..Response::ok("down")
+            };
+        }
+        Response::ok(odata_feed(&[odata_entry(&[
+            ("Id", "Foo"),
+            ("Version", "1.0.0"),
+            ("PackageHash", &hash),
+            ("PackageHashAlgorithm", "SHA512"),
+        ])]))
+    });
+    let client = NugetClient::new()
+        .with_download_url(&url)
+        .with_metadata_url(&url)
+        .with_metadata_retry_policy(RetryPolicy::none())
+        .with_metadata_cache_size(0)
+        .with_hash_sidecars(true)
+        .with_trust_unchanged_files(true)
+        .with_allow_unverified_on_metadata_failure(true);
+    let dir = temp_dir("unverified");
+    let sidecar = dir.join("Foo.1.0.0.nupkg.sha512");
+    let stamp = dir.join("Foo.1.0.0.nupkg.verified");
+
+    // Downloaded and then used from the cache while the metadata is down
+    client.download_package("Foo", "1.0.0", &dir).unwrap();
+    client.download_package("Foo", "1.0.0", &dir).unwrap();
+    assert!(!sidecar.exists());
+    assert!(!stamp.exists());
+    assert_eq!(unverified_warnings(), 2);
+
+    // Once the feed is back, the cached copy is verified before it's trusted
+    std::fs::write(dir.join("Foo.1.0.0.nupkg"), b"corrupted").unwrap();
+    metadata_up.store(true, Ordering::SeqCst);
+    let result = client.download_package("Foo", "1.0.0", &dir).unwrap();
+    assert!(result.file.metadata().unwrap().len() > b"corrupted".len() as u64);
+    assert!(sidecar.exists());
+    assert_eq!(unverified_warnings(), 2);
+}
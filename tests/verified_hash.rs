@@ -0,0 +1,46 @@
+mod common;
+
+use common::{nupkg, sha256_base64, sha512_base64, temp_dir, v2_feed};
+use nuget_dl::{HashAlgorithm, NugetClient, PackageHash, PackageId};
+
+fn client(url: &str) -> NugetClient {
+    NugetClient::new()
+        .with_download_url(url)
+        .with_metadata_url(url)
+}
+
+#[test]
+fn downloads_report_the_hash_they_matched() {
+    let package = nupkg("Foo", "1.0.0", 0);
+    let hash = PackageHash {
+        hash: sha512_base64(&package),
+        algorithm: HashAlgorithm::SHA512,
+    };
+    let url = v2_feed(package, hash.hash.clone(), "SHA512");
+    let client = client(&url);
+    let dir = temp_dir("verified-hash");
+
+    let downloaded = client.download_package_timed("Foo", "1.0.0", &dir).unwrap();
+    assert!(downloaded.downloaded);
+    assert_eq!(downloaded.verified_hash.as_ref(), Some(&hash));
+    let cached = client.download_package_timed("Foo", "1.0.0", &dir).unwrap();
+    assert!(!cached.downloaded);
+    assert_eq!(cached.verified_hash.as_ref(), Some(&hash));
+
+    let id = PackageId::new("Foo", "1.0.0").unwrap();
+    let file = client.download_package_file(&id, &dir).unwrap();
+    assert_eq!(file.hash, hash);
+}
+
+#[test]
+fn package_files_have_a_sha512_whatever_the_feed_reports() {
+    let package = nupkg("Foo", "1.0.0", 0);
+    let url = v2_feed(package.clone(), sha256_base64(&package), "SHA256");
+    let client = client(&url);
+    let dir = temp_dir("verified-hash-sha256");
+
+    let id = PackageId::new("Foo", "1.0.0").unwrap();
+    let file = client.download_package_file(&id, &dir).unwrap();
+    assert_eq!(file.hash.algorithm, HashAlgorithm::SHA512);
+    assert_eq!(file.hash.hash, sha512_base64(&package));
+}